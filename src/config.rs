@@ -2,15 +2,30 @@ use std::path::{Path, PathBuf};
 use std::fs;
 use serde::{Deserialize, Serialize};
 use anyhow::{Result, Context};
-use dirs;
+
+use crate::eligibility::EligibilityRules;
+use crate::scheduler::SchedulePolicy;
+use crate::store::StorageBackend;
+use crate::{AirdropPolicy, Pool};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    /// The default wallet, used when no wallet is named
     pub wallet_path: PathBuf,
+    /// Directory of the named wallets; `wallets` next to the default wallet
+    /// when absent
+    #[serde(default)]
+    pub wallets_dir: Option<PathBuf>,
     pub network: NetworkConfig,
     pub zcash: ZcashConfig,
     pub namada: NamadaConfig,
     pub logging: LoggingConfig,
+    #[serde(default)]
+    pub airdrop: AirdropConfig,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    #[serde(default)]
+    pub scheduling: SchedulePolicy,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,13 +60,76 @@ pub struct LoggingConfig {
     pub file: Option<PathBuf>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AirdropConfig {
+    pub policy: AirdropPolicy,
+    /// Last Zcash block whose notes count towards the airdrop
+    #[serde(default)]
+    pub snapshot_height: Option<u64>,
+    /// Notes worth less than this many zatoshis are reported as dust
+    #[serde(default)]
+    pub dust_threshold: u64,
+    /// Pools whose notes may claim
+    #[serde(default = "default_airdrop_pools")]
+    pub pools: Vec<Pool>,
+}
+
+impl Default for AirdropConfig {
+    fn default() -> Self {
+        Self {
+            policy: AirdropPolicy::default(),
+            snapshot_height: None,
+            dust_threshold: 0,
+            pools: default_airdrop_pools(),
+        }
+    }
+}
+
+fn default_airdrop_pools() -> Vec<Pool> {
+    EligibilityRules::default().pools
+}
+
+impl AirdropConfig {
+    /// The rules the eligibility report checks notes against
+    pub fn eligibility_rules(&self) -> EligibilityRules {
+        EligibilityRules {
+            snapshot_height: self.snapshot_height,
+            dust_threshold: self.dust_threshold,
+            pools: self.pools.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StorageConfig {
+    /// Database format of the wallet at `wallet_path`
+    #[serde(default)]
+    pub backend: StorageBackend,
+}
+
 impl Config {
-    pub fn default() -> Result<Self> {
+    /// Load `path`, falling back to `config.toml` in the config directory and
+    /// then to the built-in defaults
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        if let Some(path) = path {
+            return Self::from_file(path);
+        }
+        
+        let default_path = Self::get_config_dir()?.join("config.toml");
+        if default_path.exists() {
+            Self::from_file(&default_path)
+        } else {
+            Self::with_defaults()
+        }
+    }
+    
+    pub fn with_defaults() -> Result<Self> {
         let config_dir = Self::get_config_dir()?;
         let wallet_path = config_dir.join("wallet.db");
         
         Ok(Config {
             wallet_path,
+            wallets_dir: None,
             network: NetworkConfig {
                 zcash_rpc_url: "http://localhost:8232".to_string(),
                 namada_rpc_url: "http://localhost:26657".to_string(),
@@ -76,6 +154,9 @@ impl Config {
                 level: "info".to_string(),
                 file: Some(config_dir.join("wallet.log")),
             },
+            airdrop: AirdropConfig::default(),
+            storage: StorageConfig::default(),
+            scheduling: SchedulePolicy::default(),
         })
     }
     
@@ -85,10 +166,18 @@ impl Config {
         
         let config: Config = toml::from_str(&content)
             .with_context(|| "Failed to parse config file")?;
+        config.validate()
+            .with_context(|| format!("Invalid config file: {:?}", path))?;
         
         Ok(config)
     }
     
+    /// Reject settings that would only fail once a command relies on them
+    pub fn validate(&self) -> Result<()> {
+        self.airdrop.policy.validate()?;
+        Ok(())
+    }
+    
     pub fn save_to_file(&self, path: &Path) -> Result<()> {
         let content = toml::to_string_pretty(self)
             .with_context(|| "Failed to serialize config")?;
@@ -121,6 +210,49 @@ impl Config {
         Ok(data_dir)
     }
     
+    /// Directory holding the named wallets
+    pub fn wallets_dir(&self) -> PathBuf {
+        self.wallets_dir.clone().unwrap_or_else(|| {
+            self.wallet_path.parent().unwrap_or(Path::new(".")).join("wallets")
+        })
+    }
+    
+    /// Path of the wallet called `name`, or of the default wallet
+    pub fn named_wallet_path(&self, name: Option<&str>) -> Result<PathBuf> {
+        let Some(name) = name else {
+            return Ok(self.wallet_path.clone());
+        };
+        let valid = !name.is_empty()
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            anyhow::bail!("Invalid wallet name '{}'; use letters, digits, '-' and '_'", name);
+        }
+        Ok(self.wallets_dir().join(name))
+    }
+    
+    /// Point `wallet_path` at the wallet called `name`, if one is given
+    pub fn select_wallet(&mut self, name: Option<&str>) -> Result<()> {
+        self.wallets_dir = Some(self.wallets_dir());
+        self.wallet_path = self.named_wallet_path(name)?;
+        Ok(())
+    }
+    
+    /// Names of the wallets in the wallets directory, sorted
+    pub fn wallet_names(&self) -> Result<Vec<String>> {
+        let dir = self.wallets_dir();
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut names = Vec::new();
+        for entry in fs::read_dir(&dir).with_context(|| format!("Failed to read wallets directory: {:?}", dir))? {
+            if let Some(name) = entry?.file_name().to_str() {
+                names.push(name.to_string());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+    
     pub fn is_mainnet(&self) -> bool {
         self.network.zcash_network == "mainnet"
     }
@@ -128,4 +260,24 @@ impl Config {
     pub fn is_testnet(&self) -> bool {
         self.network.zcash_network == "testnet"
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProtocolError;
+
+    #[test]
+    fn test_invalid_policy_is_refused_on_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let mut config = Config::with_defaults().unwrap();
+        config.save_to_file(&path).unwrap();
+        assert!(Config::from_file(&path).is_ok());
+
+        config.airdrop.policy.rate_denominator = 0;
+        config.save_to_file(&path).unwrap();
+        let err = Config::from_file(&path).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(ProtocolError::InvalidPolicy(_))), "{:#}", err);
+    }
+}
@@ -0,0 +1,51 @@
+//! Checking a wallet's derived state against its stored records.
+//!
+//! The store is the wallet's source of truth. An unlocked wallet mirrors the
+//! stored notes in memory and in its note index, and some records point at
+//! others: a Zcash nullifier or spend height entry at its note, a
+//! reservation at the claim holding the note. An interrupted upgrade, a bug or a hand-edited database
+//! can leave these out of step. Each [`Discrepancy`] names one such
+//! mismatch; repairing the wallet rewrites the records from what they point
+//! at and reloads the in-memory state from the store.
+
+use std::fmt;
+
+use crate::NoteId;
+
+/// A mismatch between a wallet's records, or between them and its memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Discrepancy {
+    /// The in-memory copy of a note differs from its record, has no record,
+    /// or is held twice
+    MemoryNote(NoteId),
+    /// The note index disagrees with the note's record
+    IndexedNote(NoteId),
+    /// A Zcash nullifier is stored for a note the wallet does not hold
+    OrphanNullifier(NoteId),
+    /// A Zcash spend height is stored for a note the wallet does not hold or
+    /// that is not marked spent
+    StaleSpendHeight(NoteId),
+    /// A note is reserved by a claim that is missing or no longer under way
+    StaleReservation { note: NoteId, claim: String },
+    /// The note of a confirmed claim is not marked as claimed
+    UnmarkedClaim { note: NoteId, claim: String },
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Discrepancy::MemoryNote(id) => write!(f, "note {} in memory does not match its record", id),
+            Discrepancy::IndexedNote(id) => write!(f, "note {} in the note index does not match its record", id),
+            Discrepancy::OrphanNullifier(id) => write!(f, "nullifier stored for unknown note {}", id),
+            Discrepancy::StaleSpendHeight(id) => {
+                write!(f, "spend height stored for note {}, which is unknown or not marked spent", id)
+            }
+            Discrepancy::StaleReservation { note, claim } => {
+                write!(f, "note {} is reserved by claim {}, which is not under way", note, claim)
+            }
+            Discrepancy::UnmarkedClaim { note, claim } => {
+                write!(f, "note {} is not marked as claimed by confirmed claim {}", note, claim)
+            }
+        }
+    }
+}
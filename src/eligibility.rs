@@ -0,0 +1,383 @@
+//! Why each note does or does not qualify for the airdrop.
+//!
+//! A note qualifies when it was in the commitment tree, unspent, at the
+//! snapshot height, its airdrop nullifier has not been claimed, it is worth
+//! more than the dust threshold in a supported pool, and the wallet holds
+//! what it needs to prove and authorize the claim. Each of these is a
+//! [`Check`] with its own outcome, so a report says which rule excluded a
+//! note instead of leaving it out of a listing.
+//!
+//! A check the wallet cannot decide, such as the snapshot check before the
+//! wallet has scanned that far, is reported as unknown and does not exclude
+//! the note.
+
+use std::fmt;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::scanner::ScanState;
+use crate::wallet::NoteMetadata;
+use crate::{checked_value_sum, AirdropPolicy, NoteId, Pool};
+
+/// Airdrop rules the report checks notes against, from the `[airdrop]`
+/// section of the config
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EligibilityRules {
+    /// Last Zcash block whose notes count towards the airdrop
+    pub snapshot_height: Option<u64>,
+    /// Notes worth less than this are not worth claiming
+    pub dust_threshold: u64,
+    /// Pools whose notes may claim
+    pub pools: Vec<Pool>,
+}
+
+impl Default for EligibilityRules {
+    fn default() -> Self {
+        Self {
+            snapshot_height: None,
+            dust_threshold: 0,
+            pools: vec![Pool::Sapling, Pool::Orchard],
+        }
+    }
+}
+
+/// A rule a note must satisfy to claim
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Check {
+    /// In the commitment tree and unspent at the snapshot height
+    Snapshot,
+    /// No claim of the note exists or is under way
+    Unclaimed,
+    /// Above the dust threshold and minting something under the policy
+    Dust,
+    /// Held in a pool the airdrop accepts
+    Pool,
+    /// The wallet can prove and authorize the claim
+    ProofMaterials,
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Check::Snapshot => "snapshot",
+            Check::Unclaimed => "unclaimed",
+            Check::Dust => "dust",
+            Check::Pool => "pool",
+            Check::ProofMaterials => "proof-materials",
+        };
+        f.pad(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Pass,
+    Fail,
+    /// The wallet lacks the data to decide
+    Unknown,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Outcome::Pass => "pass",
+            Outcome::Fail => "fail",
+            Outcome::Unknown => "unknown",
+        };
+        f.pad(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckResult {
+    pub check: Check,
+    pub outcome: Outcome,
+    /// Why the check reached its outcome
+    pub detail: String,
+}
+
+impl CheckResult {
+    fn new(check: Check, outcome: Outcome, detail: impl Into<String>) -> Self {
+        Self { check, outcome, detail: detail.into() }
+    }
+}
+
+/// What the wallet holds to claim a note
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofMaterials {
+    /// Spending keys of the owning account
+    SpendingKey(u32),
+    /// Only viewing keys of the owning account; the claim is signed offline
+    ViewingKey(u32),
+    /// A note of no account, whose spending key the wallet does not hold
+    Missing,
+}
+
+/// Outcome of every check for one note
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoteEligibility {
+    pub note: NoteId,
+    pub account: Option<u32>,
+    pub value: u64,
+    /// Whether no check failed
+    pub eligible: bool,
+    /// Largest amount the note may mint under the airdrop policy
+    pub expected_amount: u64,
+    pub checks: Vec<CheckResult>,
+}
+
+impl NoteEligibility {
+    /// Checks that excluded the note
+    pub fn failures(&self) -> impl Iterator<Item = &CheckResult> {
+        self.checks.iter().filter(|check| check.outcome == Outcome::Fail)
+    }
+}
+
+/// Eligibility of every note of a wallet
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EligibilityReport {
+    pub snapshot_height: Option<u64>,
+    /// Last block the wallet scanned
+    pub scanned_height: Option<u64>,
+    pub notes: Vec<NoteEligibility>,
+    /// Airdrop amount the eligible notes may mint together
+    pub eligible_amount: u64,
+}
+
+impl EligibilityReport {
+    pub fn eligible(&self) -> impl Iterator<Item = &NoteEligibility> {
+        self.notes.iter().filter(|note| note.eligible)
+    }
+}
+
+/// Build the report of `notes` under `rules` and `policy`.
+///
+/// Each note comes with whether its airdrop nullifier is in the registry,
+/// what the wallet holds to claim it and the Zcash block it was seen spent in
+/// if that was recorded; `scan` is the wallet's scan progress.
+pub fn assess_notes(
+    notes: impl IntoIterator<Item = (NoteMetadata, bool, ProofMaterials, Option<u64>)>,
+    rules: &EligibilityRules,
+    policy: &AirdropPolicy,
+    scan: &ScanState,
+) -> Result<EligibilityReport> {
+    let mut report = EligibilityReport {
+        snapshot_height: rules.snapshot_height,
+        scanned_height: scan.last_height,
+        ..EligibilityReport::default()
+    };
+    for (note, in_registry, materials, spent_at) in notes {
+        report.notes.push(assess_note(&note, in_registry, materials, spent_at, rules, policy, scan)?);
+    }
+    report.eligible_amount = checked_value_sum(report.eligible().map(|note| note.expected_amount))?;
+    Ok(report)
+}
+
+fn assess_note(
+    note: &NoteMetadata,
+    in_registry: bool,
+    materials: ProofMaterials,
+    spent_at: Option<u64>,
+    rules: &EligibilityRules,
+    policy: &AirdropPolicy,
+    scan: &ScanState,
+) -> Result<NoteEligibility> {
+    let expected_amount = policy.max_mint(note.value)?;
+    let checks = vec![
+        snapshot_check(note, spent_at, rules, scan),
+        unclaimed_check(note, in_registry),
+        dust_check(note.value, expected_amount, rules),
+        pool_check(note.pool, rules),
+        proof_check(materials),
+    ];
+    Ok(NoteEligibility {
+        note: note.id(),
+        account: note.account,
+        value: note.value,
+        eligible: checks.iter().all(|check| check.outcome != Outcome::Fail),
+        expected_amount,
+        checks,
+    })
+}
+
+fn snapshot_check(note: &NoteMetadata, spent_at: Option<u64>, rules: &EligibilityRules, scan: &ScanState) -> CheckResult {
+    let Some(snapshot) = rules.snapshot_height else {
+        return CheckResult::new(Check::Snapshot, Outcome::Pass, "no snapshot height configured");
+    };
+    if note.status.is_spent {
+        match spent_at {
+            Some(height) if height <= snapshot => {
+                return CheckResult::new(Check::Snapshot, Outcome::Fail, format!("spent on Zcash in block {}", height));
+            }
+            // Spent after the snapshot, so it still counts if it was in the tree
+            Some(_) => {}
+            None if scan.last_height.is_some_and(|last| last > snapshot) => {
+                return CheckResult::new(
+                    Check::Snapshot,
+                    Outcome::Unknown,
+                    format!("spent on Zcash in an unknown block; the wallet scanned past block {}", snapshot),
+                );
+            }
+            // The wallet has not scanned past the snapshot, so the spend came before it
+            None => {
+                return CheckResult::new(Check::Snapshot, Outcome::Fail, format!("spent on Zcash by block {}", snapshot));
+            }
+        }
+    }
+    match scan.last_height {
+        Some(height) if note.position < scan.tree_size(note.pool) => {
+            CheckResult::new(Check::Snapshot, Outcome::Pass, format!("in the commitment tree by block {}", height))
+        }
+        Some(height) if height >= snapshot => CheckResult::new(
+            Check::Snapshot,
+            Outcome::Fail,
+            format!("not in the commitment tree at block {}", snapshot),
+        ),
+        Some(height) => CheckResult::new(
+            Check::Snapshot,
+            Outcome::Unknown,
+            format!("wallet scanned only to block {} of {}", height, snapshot),
+        ),
+        None => CheckResult::new(Check::Snapshot, Outcome::Unknown, "wallet has not been scanned"),
+    }
+}
+
+fn unclaimed_check(note: &NoteMetadata, in_registry: bool) -> CheckResult {
+    if note.status.is_claimed {
+        CheckResult::new(Check::Unclaimed, Outcome::Fail, "claimed by a confirmed claim")
+    } else if let Some(holder) = &note.status.reserved_by {
+        CheckResult::new(Check::Unclaimed, Outcome::Fail, format!("reserved by claim {}", holder))
+    } else if in_registry {
+        CheckResult::new(Check::Unclaimed, Outcome::Fail, "airdrop nullifier already in the registry")
+    } else {
+        CheckResult::new(Check::Unclaimed, Outcome::Pass, "airdrop nullifier not in the registry")
+    }
+}
+
+fn dust_check(value: u64, expected_amount: u64, rules: &EligibilityRules) -> CheckResult {
+    if value < rules.dust_threshold {
+        CheckResult::new(Check::Dust, Outcome::Fail, format!("value below the dust threshold of {}", rules.dust_threshold))
+    } else if expected_amount == 0 {
+        CheckResult::new(Check::Dust, Outcome::Fail, "mints nothing under the airdrop policy")
+    } else {
+        CheckResult::new(Check::Dust, Outcome::Pass, format!("mints {}", expected_amount))
+    }
+}
+
+fn pool_check(pool: Pool, rules: &EligibilityRules) -> CheckResult {
+    if rules.pools.contains(&pool) {
+        CheckResult::new(Check::Pool, Outcome::Pass, format!("{} notes may claim", pool))
+    } else {
+        CheckResult::new(Check::Pool, Outcome::Fail, format!("{} notes are not part of the airdrop", pool))
+    }
+}
+
+fn proof_check(materials: ProofMaterials) -> CheckResult {
+    match materials {
+        ProofMaterials::SpendingKey(account) => {
+            CheckResult::new(Check::ProofMaterials, Outcome::Pass, format!("spending key of account {}", account))
+        }
+        ProofMaterials::ViewingKey(account) => CheckResult::new(
+            Check::ProofMaterials,
+            Outcome::Pass,
+            format!("viewing key of account {}; sign the claim offline", account),
+        ),
+        ProofMaterials::Missing => CheckResult::new(
+            Check::ProofMaterials,
+            Outcome::Fail,
+            "no account of this wallet holds the note, so it has no spending key for it",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::NoteStatus;
+    use crate::Nullifier;
+
+    fn note(pool: Pool, position: u64, value: u64) -> NoteMetadata {
+        NoteMetadata {
+            pool,
+            account: Some(0),
+            value,
+            position,
+            airdrop_nullifier: Nullifier([position as u8; 32]),
+            status: NoteStatus::default(),
+            created_at: 0,
+            last_used: None,
+        }
+    }
+
+    fn outcome(report: &EligibilityReport, note: usize, check: Check) -> Outcome {
+        report.notes[note].checks.iter().find(|result| result.check == check).unwrap().outcome
+    }
+
+    #[test]
+    fn test_report_names_the_rule_excluding_each_note() {
+        let rules = EligibilityRules { snapshot_height: Some(100), dust_threshold: 500, pools: vec![Pool::Sapling] };
+        let policy = AirdropPolicy { rate_numerator: 1, rate_denominator: 2 };
+        let scan = ScanState { last_height: Some(100), sapling_tree_size: 10, orchard_tree_size: 10 };
+        let mut spent = note(Pool::Sapling, 3, 1000);
+        spent.status.is_spent = true;
+        let notes = vec![
+            (note(Pool::Sapling, 0, 1000), false, ProofMaterials::SpendingKey(0), None),
+            (note(Pool::Sapling, 1, 1000), true, ProofMaterials::SpendingKey(0), None),
+            (note(Pool::Sapling, 2, 100), false, ProofMaterials::ViewingKey(0), None),
+            (spent, false, ProofMaterials::SpendingKey(0), None),
+            (note(Pool::Orchard, 4, 1000), false, ProofMaterials::Missing, None),
+            (note(Pool::Sapling, 12, 1000), false, ProofMaterials::SpendingKey(0), None),
+        ];
+        let report = assess_notes(notes, &rules, &policy, &scan).unwrap();
+
+        let eligible: Vec<u64> = report.eligible().map(|note| note.note.position).collect();
+        assert_eq!((eligible, report.eligible_amount), (vec![0], 500));
+        assert_eq!(outcome(&report, 1, Check::Unclaimed), Outcome::Fail);
+        assert_eq!(outcome(&report, 2, Check::Dust), Outcome::Fail);
+        assert_eq!(outcome(&report, 3, Check::Snapshot), Outcome::Fail);
+        let orchard: Vec<Check> = report.notes[4].failures().map(|result| result.check).collect();
+        assert_eq!(orchard, [Check::Pool, Check::ProofMaterials]);
+        assert_eq!(outcome(&report, 5, Check::Snapshot), Outcome::Fail);
+
+        // Before the wallet has scanned to the snapshot, presence is unknown
+        // and does not exclude the note
+        let partial = ScanState { last_height: Some(50), ..scan };
+        let notes = vec![(note(Pool::Sapling, 12, 1000), false, ProofMaterials::SpendingKey(0), None)];
+        let report = assess_notes(notes, &rules, &policy, &partial).unwrap();
+        assert_eq!(outcome(&report, 0, Check::Snapshot), Outcome::Unknown);
+        assert!(report.notes[0].eligible);
+
+        let json = serde_json::to_string(&report).unwrap();
+        assert!(json.contains(r#""check":"snapshot","outcome":"unknown""#), "{}", json);
+    }
+
+    #[test]
+    fn test_spends_are_compared_with_the_snapshot() {
+        let rules = EligibilityRules { snapshot_height: Some(100), ..EligibilityRules::default() };
+        let policy = AirdropPolicy::default();
+        let scan = ScanState { last_height: Some(150), sapling_tree_size: 10, orchard_tree_size: 10 };
+        let spent = |position| {
+            let mut note = note(Pool::Sapling, position, 1000);
+            note.status.is_spent = true;
+            note
+        };
+        let notes = vec![
+            (spent(0), false, ProofMaterials::SpendingKey(0), Some(100)),
+            (spent(1), false, ProofMaterials::SpendingKey(0), Some(101)),
+            (spent(2), false, ProofMaterials::SpendingKey(0), None),
+        ];
+        let report = assess_notes(notes, &rules, &policy, &scan).unwrap();
+        assert_eq!(outcome(&report, 0, Check::Snapshot), Outcome::Fail);
+        assert_eq!(outcome(&report, 1, Check::Snapshot), Outcome::Pass);
+        assert_eq!(outcome(&report, 2, Check::Snapshot), Outcome::Unknown);
+
+        // A wallet that stopped scanning at the snapshot saw the spend before it
+        let stopped = ScanState { last_height: Some(100), ..scan };
+        let notes = vec![(spent(2), false, ProofMaterials::SpendingKey(0), None)];
+        let report = assess_notes(notes, &rules, &policy, &stopped).unwrap();
+        assert_eq!(outcome(&report, 0, Check::Snapshot), Outcome::Fail);
+    }
+}
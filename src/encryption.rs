@@ -0,0 +1,157 @@
+//! Passphrase-based encryption of wallet records at rest.
+//!
+//! A random data key encrypts every sensitive record with ChaCha20-Poly1305.
+//! The data key is stored wrapped under a key derived from the user's
+//! passphrase with Argon2id, so changing the passphrase only rewraps the
+//! data key instead of re-encrypting the whole database.
+
+use std::fmt;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+const NONCE_LEN: usize = 12;
+const WRAP_AAD: &[u8] = b"zec-nam wallet data key";
+
+#[derive(Debug, Error)]
+pub enum EncryptionError {
+    #[error("incorrect passphrase")]
+    WrongPassphrase,
+
+    #[error("record failed authentication")]
+    Decryption,
+
+    #[error("key derivation failed: {0}")]
+    Kdf(String),
+}
+
+/// Argon2id parameters stored alongside the wrapped data key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub salt: [u8; 16],
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+}
+
+impl KdfParams {
+    /// Fresh parameters with a random salt and the Argon2 default costs
+    pub fn generate() -> Self {
+        Self {
+            salt: rand::random(),
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        }
+    }
+
+    fn derive(&self, passphrase: &str) -> Result<DataKey, EncryptionError> {
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, Some(32))
+            .map_err(|e| EncryptionError::Kdf(e.to_string()))?;
+        let mut key = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &self.salt, &mut key)
+            .map_err(|e| EncryptionError::Kdf(e.to_string()))?;
+        Ok(DataKey(key))
+    }
+}
+
+/// Symmetric key used to encrypt wallet records
+#[derive(Clone, Zeroize, ZeroizeOnDrop)]
+pub struct DataKey([u8; 32]);
+
+impl fmt::Debug for DataKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DataKey(..)")
+    }
+}
+
+impl DataKey {
+    pub fn generate() -> Self {
+        DataKey(rand::random())
+    }
+
+    /// Encrypt `plaintext`, binding it to `aad` (typically the record's key)
+    pub fn encrypt(&self, plaintext: &[u8], aad: &[u8]) -> Vec<u8> {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let ciphertext = self
+            .cipher()
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad })
+            .expect("ChaCha20-Poly1305 encryption does not fail for in-memory buffers");
+
+        let mut sealed = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        sealed
+    }
+
+    /// Decrypt a record produced by [`DataKey::encrypt`] with the same `aad`
+    pub fn decrypt(&self, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        if sealed.len() < NONCE_LEN {
+            return Err(EncryptionError::Decryption);
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.cipher()
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+            .map_err(|_| EncryptionError::Decryption)
+    }
+
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(Key::from_slice(&self.0))
+    }
+}
+
+/// Data key encrypted under a passphrase-derived key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WrappedKey {
+    pub kdf: KdfParams,
+    pub ciphertext: Vec<u8>,
+}
+
+impl WrappedKey {
+    pub fn wrap(key: &DataKey, passphrase: &str) -> Result<Self, EncryptionError> {
+        let kdf = KdfParams::generate();
+        let ciphertext = kdf.derive(passphrase)?.encrypt(&key.0, WRAP_AAD);
+        Ok(Self { kdf, ciphertext })
+    }
+
+    pub fn unwrap(&self, passphrase: &str) -> Result<DataKey, EncryptionError> {
+        let mut plaintext = self
+            .kdf
+            .derive(passphrase)?
+            .decrypt(&self.ciphertext, WRAP_AAD)
+            .map_err(|_| EncryptionError::WrongPassphrase)?;
+
+        let key = plaintext
+            .as_slice()
+            .try_into()
+            .map(DataKey)
+            .map_err(|_| EncryptionError::Decryption);
+        plaintext.zeroize();
+        key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_and_encrypt_roundtrip() {
+        let key = DataKey::generate();
+        let wrapped = WrappedKey::wrap(&key, "correct horse").unwrap();
+
+        assert!(matches!(wrapped.unwrap("wrong"), Err(EncryptionError::WrongPassphrase)));
+        let unwrapped = wrapped.unwrap("correct horse").unwrap();
+
+        let sealed = key.encrypt(b"note record", b"sapling_0");
+        assert_eq!(unwrapped.decrypt(&sealed, b"sapling_0").unwrap(), b"note record");
+
+        // Records cannot be moved to another key
+        assert!(unwrapped.decrypt(&sealed, b"sapling_1").is_err());
+    }
+}
@@ -0,0 +1,502 @@
+//! Wallet export files.
+//!
+//! An export carries the wallet's notes with their claim status and Zcash
+//! nullifiers, its claims and the nullifiers it has seen, in one of three
+//! encodings: pretty-printed
+//! JSON, bincode, or bincode encrypted under a passphrase. Binary files start
+//! with a magic tag and the export version; JSON files carry the version in a
+//! `version` field. Files written before exports were versioned (raw bincode
+//! without a tag) are read as version 0.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::encryption::{DataKey, EncryptionError, WrappedKey};
+use crate::wallet::{ClaimStatus, NoteStatus, TransactionRecord, WalletMetadata};
+use crate::{checked_value_sum, ConsumedNullifier, NoteId, Nullifier, OrchardNote, SaplingNote};
+
+/// Version of the export schema written by this release.
+///
+/// 0. Unversioned bincode: notes and nullifiers, without note status or claims
+/// 1. Notes with their claim status, claims, and where each airdrop
+///    nullifier was consumed
+/// 2. Each note's Zcash nullifier and the block it was seen spent in
+pub const EXPORT_VERSION: u32 = 2;
+
+const BINCODE_MAGIC: &[u8; 8] = b"ZECNAM\0B";
+const ENCRYPTED_MAGIC: &[u8; 8] = b"ZECNAM\0E";
+const HEADER_LEN: usize = 12;
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("unknown export format '{0}'; expected 'json', 'bincode' or 'encrypted'")]
+    UnknownFormat(String),
+
+    #[error("export version {0} is newer than the supported version {EXPORT_VERSION}; upgrade zec-nam to import it")]
+    UnsupportedVersion(u32),
+
+    #[error("malformed export: {0}")]
+    Malformed(String),
+
+    #[error("the export is encrypted; a passphrase is required")]
+    PassphraseRequired,
+
+    #[error("cannot decrypt export")]
+    Encryption(#[from] EncryptionError),
+
+    #[error("invalid export: {0}")]
+    Invalid(String),
+}
+
+/// Encoding of an export file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Json,
+    Bincode,
+    /// Bincode encrypted under a passphrase
+    Encrypted,
+}
+
+impl ExportFormat {
+    /// Recognise the format of an export file; untagged binary files are
+    /// unversioned bincode exports
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(ENCRYPTED_MAGIC) {
+            ExportFormat::Encrypted
+        } else if bytes.trim_ascii_start().starts_with(b"{") {
+            ExportFormat::Json
+        } else {
+            ExportFormat::Bincode
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Bincode => "bincode",
+            ExportFormat::Encrypted => "encrypted",
+        })
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = ExportError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(ExportFormat::Json),
+            "bincode" | "binary" => Ok(ExportFormat::Bincode),
+            "encrypted" => Ok(ExportFormat::Encrypted),
+            _ => Err(ExportError::UnknownFormat(s.to_string())),
+        }
+    }
+}
+
+/// A note, its airdrop claim status and what the wallet saw of it on Zcash
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedNote<N> {
+    pub note: N,
+    pub status: NoteStatus,
+    /// Zcash nullifier the wallet watches for the note's spend; notes added
+    /// without scanning have none
+    #[serde(default)]
+    pub nullifier: Option<Nullifier>,
+    /// Zcash block the note was seen spent in, when known
+    #[serde(default)]
+    pub spent_at: Option<u64>,
+}
+
+impl<N> ExportedNote<N> {
+    fn without_chain_state(note: N, status: NoteStatus) -> Self {
+        Self { note, status, nullifier: None, spent_at: None }
+    }
+}
+
+/// Contents of an export file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletExport {
+    pub version: u32,
+    pub metadata: WalletMetadata,
+    pub sapling_notes: Vec<ExportedNote<SaplingNote>>,
+    pub orchard_notes: Vec<ExportedNote<OrchardNote>>,
+    pub claims: Vec<TransactionRecord>,
+    /// Zcash nullifiers seen spent
+    pub nullifier_set: Vec<Nullifier>,
+    pub airdrop_nullifiers: Vec<(Nullifier, ConsumedNullifier)>,
+}
+
+/// Version 1 export, whose notes carry no Zcash nullifier
+#[derive(Deserialize)]
+struct ExportV1 {
+    version: u32,
+    metadata: WalletMetadata,
+    sapling_notes: Vec<NoteV1<SaplingNote>>,
+    orchard_notes: Vec<NoteV1<OrchardNote>>,
+    claims: Vec<TransactionRecord>,
+    nullifier_set: Vec<Nullifier>,
+    airdrop_nullifiers: Vec<(Nullifier, ConsumedNullifier)>,
+}
+
+#[derive(Deserialize)]
+struct NoteV1<N> {
+    note: N,
+    status: NoteStatus,
+}
+
+/// Export written before the schema was versioned
+#[derive(Deserialize)]
+struct LegacyExport {
+    metadata: WalletMetadata,
+    sapling_notes: Vec<SaplingNote>,
+    orchard_notes: Vec<OrchardNote>,
+    nullifier_set: Vec<Vec<u8>>,
+    airdrop_nullifier_set: Vec<Vec<u8>>,
+}
+
+#[derive(Deserialize)]
+struct VersionProbe {
+    version: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncryptedExport {
+    key: WrappedKey,
+    ciphertext: Vec<u8>,
+}
+
+impl WalletExport {
+    /// Encode the export as `format`; encrypted exports need a `passphrase`
+    pub fn encode(&self, format: ExportFormat, passphrase: Option<&str>) -> Result<Vec<u8>, ExportError> {
+        match format {
+            ExportFormat::Json => serde_json::to_vec_pretty(self).map_err(malformed),
+            ExportFormat::Bincode => {
+                let mut bytes = header(BINCODE_MAGIC, self.version);
+                bytes.extend(bincode::serialize(self).map_err(malformed)?);
+                Ok(bytes)
+            }
+            ExportFormat::Encrypted => {
+                let passphrase = passphrase.ok_or(ExportError::PassphraseRequired)?;
+                let data_key = DataKey::generate();
+                let mut bytes = header(ENCRYPTED_MAGIC, self.version);
+                let plaintext = bincode::serialize(self).map_err(malformed)?;
+                let encrypted = EncryptedExport {
+                    key: WrappedKey::wrap(&data_key, passphrase)?,
+                    // The header is authenticated so the version cannot be swapped
+                    ciphertext: data_key.encrypt(&plaintext, &bytes),
+                };
+                bytes.extend(bincode::serialize(&encrypted).map_err(malformed)?);
+                Ok(bytes)
+            }
+        }
+    }
+
+    /// Decode an export file in any format and version; `passphrase` is only
+    /// needed for encrypted exports
+    pub fn decode(bytes: &[u8], passphrase: Option<&str>) -> Result<Self, ExportError> {
+        match ExportFormat::detect(bytes) {
+            ExportFormat::Json => {
+                let probe: VersionProbe = serde_json::from_slice(bytes).map_err(malformed)?;
+                check_version(probe.version)?;
+                serde_json::from_slice(bytes).map_err(malformed)
+            }
+            ExportFormat::Bincode if bytes.starts_with(BINCODE_MAGIC) => {
+                let (version, body) = versioned_body(bytes)?;
+                Self::from_bincode(version, body)
+            }
+            ExportFormat::Bincode => {
+                let legacy: LegacyExport = bincode::deserialize(bytes)
+                    .map_err(|_| ExportError::Malformed("not a zec-nam wallet export".to_string()))?;
+                Self::from_legacy(legacy)
+            }
+            ExportFormat::Encrypted => {
+                let passphrase = passphrase.ok_or(ExportError::PassphraseRequired)?;
+                let (version, body) = versioned_body(bytes)?;
+                let encrypted: EncryptedExport = bincode::deserialize(body).map_err(malformed)?;
+                let data_key = encrypted.key.unwrap(passphrase)?;
+                let plaintext = data_key.decrypt(&encrypted.ciphertext, &bytes[..HEADER_LEN])?;
+                Self::from_bincode(version, &plaintext)
+            }
+        }
+    }
+
+    /// Decode the bincode body of a tagged export of `version`
+    fn from_bincode(version: u32, body: &[u8]) -> Result<Self, ExportError> {
+        if version >= 2 {
+            return bincode::deserialize(body).map_err(malformed);
+        }
+        fn notes<N>(notes: Vec<NoteV1<N>>) -> Vec<ExportedNote<N>> {
+            notes.into_iter().map(|n| ExportedNote::without_chain_state(n.note, n.status)).collect()
+        }
+        let v1: ExportV1 = bincode::deserialize(body).map_err(malformed)?;
+        Ok(Self {
+            version: v1.version,
+            metadata: v1.metadata,
+            sapling_notes: notes(v1.sapling_notes),
+            orchard_notes: notes(v1.orchard_notes),
+            claims: v1.claims,
+            nullifier_set: v1.nullifier_set,
+            airdrop_nullifiers: v1.airdrop_nullifiers,
+        })
+    }
+
+    fn from_legacy(legacy: LegacyExport) -> Result<Self, ExportError> {
+        let nullifier = |what: &str, i: usize, bytes: Vec<u8>| {
+            let len = bytes.len();
+            bytes.try_into().map(Nullifier).map_err(|_| {
+                ExportError::Invalid(format!("{} {} is {} bytes long, expected 32", what, i, len))
+            })
+        };
+        fn unclaimed<N>(note: N) -> ExportedNote<N> {
+            ExportedNote::without_chain_state(note, NoteStatus::default())
+        }
+
+        Ok(Self {
+            version: 0,
+            metadata: legacy.metadata,
+            sapling_notes: legacy.sapling_notes.into_iter().map(unclaimed).collect(),
+            orchard_notes: legacy.orchard_notes.into_iter().map(unclaimed).collect(),
+            claims: Vec::new(),
+            nullifier_set: legacy
+                .nullifier_set
+                .into_iter()
+                .enumerate()
+                .map(|(i, bytes)| nullifier("nullifier", i, bytes))
+                .collect::<Result<_, _>>()?,
+            // Unversioned exports do not record where a nullifier was consumed
+            airdrop_nullifiers: legacy
+                .airdrop_nullifier_set
+                .into_iter()
+                .enumerate()
+                .map(|(i, bytes)| {
+                    let consumed = ConsumedNullifier { block_height: 0, txid: [0u8; 32] };
+                    Ok((nullifier("airdrop nullifier", i, bytes)?, consumed))
+                })
+                .collect::<Result<_, ExportError>>()?,
+        })
+    }
+
+    /// Check that the export is consistent and meant for a wallet on `network`
+    pub fn validate(&self, network: &str) -> Result<(), ExportError> {
+        if self.metadata.network != network {
+            return Err(ExportError::Invalid(format!(
+                "export is from a {} wallet, not {}",
+                self.metadata.network, network
+            )));
+        }
+
+        let mut notes: Vec<(NoteId, &NoteStatus, u64)> = self
+            .sapling_notes
+            .iter()
+            .map(|n| (n.note.id(), &n.status, n.note.value))
+            .chain(self.orchard_notes.iter().map(|n| (n.note.id(), &n.status, n.note.value)))
+            .collect();
+        let spent_at = self
+            .sapling_notes
+            .iter()
+            .map(|n| (n.note.id(), &n.status, n.spent_at))
+            .chain(self.orchard_notes.iter().map(|n| (n.note.id(), &n.status, n.spent_at)));
+        for (id, status, spent_at) in spent_at {
+            if let Some(height) = spent_at.filter(|_| !status.is_spent) {
+                return Err(ExportError::Invalid(format!("note {} was seen spent in block {} but is not marked spent", id, height)));
+            }
+        }
+        checked_value_sum(notes.iter().map(|(_, _, value)| *value))
+            .map_err(|e| ExportError::Invalid(format!("note values: {}", e)))?;
+        notes.sort_by_key(|(id, _, _)| *id);
+        if let Some(pair) = notes.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(ExportError::Invalid(format!("note {} appears more than once", pair[0].0)));
+        }
+
+        for claim in &self.claims {
+            if hex::decode(&claim.tx_hash).map_or(true, |hash| hash.len() != 32) {
+                return Err(ExportError::Invalid(format!("claim hash {:?} is not 32 hex bytes", claim.tx_hash)));
+            }
+            if claim.airdrop_nullifier.len() != 32 {
+                return Err(ExportError::Invalid(format!(
+                    "claim {} has a {}-byte airdrop nullifier, expected 32",
+                    claim.tx_hash,
+                    claim.airdrop_nullifier.len()
+                )));
+            }
+            if let Some(id) = claim.note.filter(|id| notes.binary_search_by_key(id, |(id, _, _)| *id).is_err()) {
+                return Err(ExportError::Invalid(format!("claim {} is for note {}, which is not in the export", claim.tx_hash, id)));
+            }
+        }
+
+        for (id, status, _) in &notes {
+            if let Some(holder) = &status.reserved_by {
+                let unfinished = self.claims.iter().any(|claim| {
+                    claim.tx_hash == *holder && matches!(claim.status, ClaimStatus::Pending | ClaimStatus::Broadcast)
+                });
+                if !unfinished {
+                    return Err(ExportError::Invalid(format!(
+                        "note {} is reserved by claim {}, which is not an unfinished claim in the export",
+                        id, holder
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn header(magic: &[u8; 8], version: u32) -> Vec<u8> {
+    let mut bytes = magic.to_vec();
+    bytes.extend_from_slice(&version.to_be_bytes());
+    bytes
+}
+
+/// The version and body of a tagged binary export, after checking the version
+fn versioned_body(bytes: &[u8]) -> Result<(u32, &[u8]), ExportError> {
+    if bytes.len() < HEADER_LEN {
+        return Err(ExportError::Malformed("truncated header".to_string()));
+    }
+    let version = u32::from_be_bytes(bytes[8..HEADER_LEN].try_into().expect("4-byte slice"));
+    check_version(version)?;
+    Ok((version, &bytes[HEADER_LEN..]))
+}
+
+fn check_version(version: u32) -> Result<(), ExportError> {
+    if version > EXPORT_VERSION {
+        return Err(ExportError::UnsupportedVersion(version));
+    }
+    Ok(())
+}
+
+fn malformed(e: impl fmt::Display) -> ExportError {
+    ExportError::Malformed(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{NoteCommitment, Scalar};
+
+    fn export() -> WalletExport {
+        WalletExport {
+            version: EXPORT_VERSION,
+            metadata: WalletMetadata {
+                name: "test".to_string(),
+                created_at: 0,
+                last_sync: 0,
+                network: "testnet".to_string(),
+                version: "0.1.0".to_string(),
+            },
+            sapling_notes: vec![ExportedNote {
+                note: SaplingNote {
+                    diversifier: [0u8; 11],
+                    value: 1000000,
+                    note_commitment: NoteCommitment([1u8; 32]),
+                    nullifier_key: Scalar([2u8; 32]),
+                    randomness: Scalar([3u8; 32]),
+                    position: 0,
+                },
+                status: NoteStatus { is_spent: true, ..NoteStatus::default() },
+                nullifier: Some(Nullifier([6u8; 32])),
+                spent_at: Some(100),
+            }],
+            orchard_notes: Vec::new(),
+            claims: Vec::new(),
+            nullifier_set: vec![Nullifier([5u8; 32])],
+            airdrop_nullifiers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_formats_round_trip() {
+        for format in [ExportFormat::Json, ExportFormat::Bincode, ExportFormat::Encrypted] {
+            let bytes = export().encode(format, Some("pw")).unwrap();
+            assert_eq!(ExportFormat::detect(&bytes), format);
+
+            let decoded = WalletExport::decode(&bytes, Some("pw")).unwrap();
+            assert_eq!(decoded.sapling_notes[0].note.note_commitment, NoteCommitment([1u8; 32]));
+            assert_eq!(decoded.sapling_notes[0].nullifier, Some(Nullifier([6u8; 32])));
+            assert_eq!(decoded.sapling_notes[0].spent_at, Some(100));
+            assert_eq!(decoded.nullifier_set, vec![Nullifier([5u8; 32])]);
+        }
+        
+        // Version 1 exports carry no note nullifiers
+        #[derive(Serialize)]
+        struct NoteV1<N> {
+            note: N,
+            status: NoteStatus,
+        }
+        #[derive(Serialize)]
+        struct V1 {
+            version: u32,
+            metadata: WalletMetadata,
+            sapling_notes: Vec<NoteV1<SaplingNote>>,
+            orchard_notes: Vec<NoteV1<OrchardNote>>,
+            claims: Vec<TransactionRecord>,
+            nullifier_set: Vec<Nullifier>,
+            airdrop_nullifiers: Vec<(Nullifier, ConsumedNullifier)>,
+        }
+        let v1 = V1 {
+            version: 1,
+            metadata: export().metadata,
+            sapling_notes: vec![NoteV1 { note: export().sapling_notes[0].note.clone(), status: NoteStatus::default() }],
+            orchard_notes: Vec::new(),
+            claims: Vec::new(),
+            nullifier_set: Vec::new(),
+            airdrop_nullifiers: Vec::new(),
+        };
+        let mut bytes = header(BINCODE_MAGIC, 1);
+        bytes.extend(bincode::serialize(&v1).unwrap());
+        for bytes in [bytes, serde_json::to_vec(&v1).unwrap()] {
+            let decoded = WalletExport::decode(&bytes, None).unwrap();
+            assert_eq!((decoded.version, decoded.sapling_notes[0].nullifier), (1, None));
+        }
+
+        let encrypted = export().encode(ExportFormat::Encrypted, Some("pw")).unwrap();
+        assert!(matches!(WalletExport::decode(&encrypted, None), Err(ExportError::PassphraseRequired)));
+        assert!(matches!(
+            WalletExport::decode(&encrypted, Some("wrong")),
+            Err(ExportError::Encryption(EncryptionError::WrongPassphrase))
+        ));
+    }
+
+    #[test]
+    fn test_bad_exports_are_rejected_with_reasons() {
+        // Unversioned exports with a short nullifier no longer panic
+        #[derive(Serialize)]
+        struct V0 {
+            metadata: WalletMetadata,
+            sapling_notes: Vec<SaplingNote>,
+            orchard_notes: Vec<OrchardNote>,
+            nullifier_set: Vec<Vec<u8>>,
+            airdrop_nullifier_set: Vec<Vec<u8>>,
+        }
+        let v0 = V0 {
+            metadata: export().metadata,
+            sapling_notes: Vec::new(),
+            orchard_notes: Vec::new(),
+            nullifier_set: vec![vec![1u8; 32]],
+            airdrop_nullifier_set: vec![vec![2u8; 31]],
+        };
+        let err = WalletExport::decode(&bincode::serialize(&v0).unwrap(), None).unwrap_err();
+        assert_eq!(err.to_string(), "invalid export: airdrop nullifier 0 is 31 bytes long, expected 32");
+
+        let mut newer = export();
+        newer.version = EXPORT_VERSION + 1;
+        let bytes = newer.encode(ExportFormat::Bincode, None).unwrap();
+        assert!(matches!(WalletExport::decode(&bytes, None), Err(ExportError::UnsupportedVersion(_))));
+
+        let mut duplicated = export();
+        duplicated.sapling_notes.push(duplicated.sapling_notes[0].clone());
+        assert!(duplicated.validate("testnet").unwrap_err().to_string().contains("sapling:0"));
+
+        let mut reserved = export();
+        reserved.sapling_notes[0].status.reserved_by = Some(hex::encode([7u8; 32]));
+        assert!(reserved.validate("testnet").is_err());
+
+        let mut unspent = export();
+        unspent.sapling_notes[0].status.is_spent = false;
+        assert!(unspent.validate("testnet").unwrap_err().to_string().contains("block 100"));
+        assert!(export().validate("mainnet").is_err());
+    }
+}
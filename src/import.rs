@@ -0,0 +1,215 @@
+//! Importers for notes held by other Zcash wallets.
+//!
+//! zcashd users provide `z_listunspent` output together with the key dump from
+//! `z_exportwallet`; Zashi and YWallet users provide a viewing-key export. In
+//! both cases the keys are used to trial-decrypt compact blocks, because only
+//! the chain tells us each note's commitment, randomness and tree position.
+
+use std::str::FromStr;
+
+use sapling_crypto::zip32::{ExtendedFullViewingKey, ExtendedSpendingKey};
+use serde::Deserialize;
+use thiserror::Error;
+use zcash_primitives::consensus::{Network, Parameters};
+
+use crate::keys::{KeyError, ViewingAccount};
+use crate::scanner::ScanKeys;
+use crate::Pool;
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("unknown import format '{0}'; expected 'zcashd' or 'viewing-key'")]
+    UnknownFormat(String),
+
+    #[error("malformed z_listunspent output: {0}")]
+    Json(String),
+
+    #[error("unsupported pool '{0}'")]
+    UnsupportedPool(String),
+
+    #[error("z_listunspent entry {txid} is missing '{field}'")]
+    MissingField { txid: String, field: &'static str },
+
+    #[error("invalid key '{0}'")]
+    InvalidKey(String),
+
+    #[error("key '{0}' belongs to a different network")]
+    NetworkMismatch(String),
+
+    #[error("no Sapling or Orchard keys found")]
+    NoKeys,
+}
+
+/// Where imported notes come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// zcashd `z_listunspent` JSON plus a `z_exportwallet` key dump
+    Zcashd,
+    /// Unified or Sapling viewing keys exported by Zashi or YWallet
+    ViewingKey,
+}
+
+impl FromStr for ImportFormat {
+    type Err = ImportError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zcashd" | "z_listunspent" => Ok(ImportFormat::Zcashd),
+            "viewing-key" | "ufvk" | "zashi" | "ywallet" => Ok(ImportFormat::ViewingKey),
+            _ => Err(ImportError::UnknownFormat(s.to_string())),
+        }
+    }
+}
+
+/// An unspent note reported by zcashd, to be located on chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectedNote {
+    /// Transaction hash in internal byte order, as in compact blocks
+    pub txid: [u8; 32],
+    pub pool: Pool,
+    /// Output index for Sapling, action index for Orchard
+    pub index: usize,
+    pub value: u64,
+}
+
+#[derive(Deserialize)]
+struct ListUnspentEntry {
+    txid: String,
+    pool: Option<String>,
+    outindex: Option<usize>,
+    actionindex: Option<usize>,
+    jsindex: Option<usize>,
+    #[serde(rename = "amountZat")]
+    amount_zat: Option<u64>,
+}
+
+/// Parse the JSON array printed by `zcash-cli z_listunspent`
+pub fn parse_listunspent(json: &str) -> Result<Vec<ExpectedNote>, ImportError> {
+    let entries: Vec<ListUnspentEntry> =
+        serde_json::from_str(json).map_err(|e| ImportError::Json(e.to_string()))?;
+
+    entries
+        .into_iter()
+        .map(|entry| {
+            let missing = |field| ImportError::MissingField { txid: entry.txid.clone(), field };
+
+            // Older zcashd releases omit `pool`; Sprout entries carry a JoinSplit index
+            let pool = match (entry.pool.as_deref(), entry.jsindex) {
+                (Some("sapling"), _) | (None, None) => Pool::Sapling,
+                (Some("orchard"), _) => Pool::Orchard,
+                (Some(pool), _) => return Err(ImportError::UnsupportedPool(pool.to_string())),
+                (None, Some(_)) => return Err(ImportError::UnsupportedPool("sprout".to_string())),
+            };
+            let index = match pool {
+                Pool::Sapling => entry.outindex.ok_or_else(|| missing("outindex"))?,
+                Pool::Orchard => entry.actionindex.ok_or_else(|| missing("actionindex"))?,
+            };
+            let value = entry.amount_zat.ok_or_else(|| missing("amountZat"))?;
+
+            // zcashd displays transaction hashes byte-reversed
+            let mut txid: [u8; 32] = hex::decode(&entry.txid)
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or_else(|| ImportError::Json(format!("invalid txid {}", entry.txid)))?;
+            txid.reverse();
+
+            Ok(ExpectedNote { txid, pool, index, value })
+        })
+        .collect()
+}
+
+/// Find every Sapling spending key, Sapling viewing key and unified full
+/// viewing key in a `z_exportwallet` dump or a wallet's key export
+pub fn parse_keys(text: &str, network: Network) -> Result<Vec<ScanKeys>, ImportError> {
+    let main = matches!(network, Network::MainNetwork);
+    let mut keys = Vec::new();
+
+    for token in text.split(|c: char| !(c.is_ascii_alphanumeric() || c == '-')) {
+        let parsed = if token.starts_with("secret-extended-key-") {
+            let hrp = if main { "secret-extended-key-main" } else { "secret-extended-key-test" };
+            let bytes = decode_bech32(token, hrp)?;
+            let extsk = ExtendedSpendingKey::from_bytes(&bytes)
+                .map_err(|_| ImportError::InvalidKey(token.to_string()))?;
+            ScanKeys::new(Some(extsk.to_diversifiable_full_viewing_key()), None)
+        } else if token.starts_with("zxview") {
+            let hrp = if main { "zxviews" } else { "zxviewtestsapling" };
+            let bytes = decode_bech32(token, hrp)?;
+            let extfvk = ExtendedFullViewingKey::read(bytes.as_slice())
+                .map_err(|_| ImportError::InvalidKey(token.to_string()))?;
+            ScanKeys::new(Some(extfvk.to_diversifiable_full_viewing_key()), None)
+        } else if token.starts_with("uview") {
+            decode_ufvk(token, network)?
+        } else {
+            continue;
+        };
+        keys.push(parsed);
+    }
+
+    if keys.is_empty() {
+        return Err(ImportError::NoKeys);
+    }
+    Ok(keys)
+}
+
+fn decode_bech32(token: &str, expected_hrp: &str) -> Result<Vec<u8>, ImportError> {
+    let (hrp, data) = bech32::decode(token).map_err(|_| ImportError::InvalidKey(token.to_string()))?;
+    if hrp.as_str() != expected_hrp {
+        return Err(ImportError::NetworkMismatch(token.to_string()));
+    }
+    Ok(data)
+}
+
+fn decode_ufvk(token: &str, network: Network) -> Result<ScanKeys, ImportError> {
+    let account = ViewingAccount::from_ufvk(0, token, network.network_type()).map_err(|e| match e {
+        KeyError::NetworkMismatch => ImportError::NetworkMismatch(token.to_string()),
+        _ => ImportError::InvalidKey(token.to_string()),
+    })?;
+    ScanKeys::from_viewing_account(&account).map_err(|_| ImportError::InvalidKey(token.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::tests::test_account;
+    use zcash_primitives::consensus::NetworkType;
+
+    #[test]
+    fn test_parse_listunspent() {
+        let json = r#"[
+            {"txid": "00000000000000000000000000000000000000000000000000000000000000ff",
+             "pool": "sapling", "outindex": 1, "confirmations": 10, "spendable": true,
+             "address": "ztestsapling1...", "amount": 0.0001, "amountZat": 10000, "change": false},
+            {"txid": "0100000000000000000000000000000000000000000000000000000000000000",
+             "pool": "orchard", "actionindex": 0, "amountZat": 20000}
+        ]"#;
+        let notes = parse_listunspent(json).unwrap();
+        assert_eq!(notes[0].pool, Pool::Sapling);
+        assert_eq!((notes[0].index, notes[0].value), (1, 10000));
+        assert_eq!(notes[0].txid[0], 0xff);
+        assert_eq!((notes[1].pool, notes[1].txid[31]), (Pool::Orchard, 1));
+
+        let sprout = r#"[{"txid": "00", "jsindex": 0, "jsoutindex": 1, "amountZat": 1}]"#;
+        assert!(matches!(parse_listunspent(sprout), Err(ImportError::UnsupportedPool(_))));
+    }
+
+    #[test]
+    fn test_parse_exported_keys() {
+        let account = test_account(0);
+        let ufvk = account.viewing_account().to_ufvk(NetworkType::Test).unwrap();
+
+        let extsk = bech32::encode::<bech32::Bech32>(
+            bech32::Hrp::parse("secret-extended-key-test").unwrap(),
+            &account.to_record().sapling_extsk,
+        )
+        .unwrap();
+
+        // A z_exportwallet dump and a JSON export embedding a UFVK
+        let dump = format!("# Wallet dump\n{} 2024-01-01T00:00:00Z # zaddr=ztestsapling1\n", extsk);
+        assert_eq!(parse_keys(&dump, Network::TestNetwork).unwrap().len(), 1);
+        let export = format!(r#"{{"name": "Zashi", "ufvk": "{}"}}"#, ufvk);
+        assert_eq!(parse_keys(&export, Network::TestNetwork).unwrap().len(), 1);
+
+        assert!(matches!(parse_keys(&export, Network::MainNetwork), Err(ImportError::NetworkMismatch(_))));
+        assert!(matches!(parse_keys("nothing here", Network::TestNetwork), Err(ImportError::NoKeys)));
+    }
+}
@@ -0,0 +1,228 @@
+//! In-memory indexes over an unlocked wallet's notes.
+//!
+//! Note records are encrypted at rest, so indexing them in the store would
+//! leak the values and claim states the encryption hides. The wallet instead
+//! builds a [`NoteIndex`] when it is unlocked and applies each committed
+//! batch of note writes to it, so queries and balances never decrypt the
+//! note trees again. A batch that fails to commit leaves the index untouched.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::Result;
+
+use crate::wallet::{Balance, NoteMetadata};
+use crate::{NoteId, Pool};
+
+/// A note's airdrop claim state, as indexed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ClaimState {
+    Unclaimed,
+    /// Held by an unfinished claim
+    Reserved,
+    Claimed,
+}
+
+impl ClaimState {
+    pub const ALL: [ClaimState; 3] = [ClaimState::Unclaimed, ClaimState::Reserved, ClaimState::Claimed];
+}
+
+impl fmt::Display for ClaimState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ClaimState::Unclaimed => "unclaimed",
+            ClaimState::Reserved => "reserved",
+            ClaimState::Claimed => "claimed",
+        };
+        f.pad(name)
+    }
+}
+
+impl FromStr for ClaimState {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|state| state.to_string() == s)
+            .ok_or_else(|| anyhow::anyhow!("Unknown claim state: {}", s))
+    }
+}
+
+/// Order of the notes returned by a [`NoteQuery`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NoteSort {
+    /// By pool, then commitment tree position
+    #[default]
+    Position,
+    Value,
+    /// When the note was added to the wallet
+    CreatedAt,
+}
+
+impl FromStr for NoteSort {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "position" => Ok(NoteSort::Position),
+            "value" => Ok(NoteSort::Value),
+            "created" => Ok(NoteSort::CreatedAt),
+            _ => anyhow::bail!("Unknown note order '{}'; expected 'position', 'value' or 'created'", s),
+        }
+    }
+}
+
+/// Filter, order and page of a note listing
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NoteQuery {
+    /// Only notes of this ZIP-32 account
+    pub account: Option<u32>,
+    pub min_value: Option<u64>,
+    pub pool: Option<Pool>,
+    pub claim_state: Option<ClaimState>,
+    /// Only notes unspent on Zcash
+    pub unspent: bool,
+    /// Only notes that can still claim the airdrop
+    pub eligible: bool,
+    pub sort: NoteSort,
+    pub descending: bool,
+    /// Matching notes to skip before the page starts
+    pub offset: usize,
+    /// Page size; all remaining notes when absent
+    pub limit: Option<usize>,
+}
+
+/// One page of a note listing
+#[derive(Debug, Clone, Default)]
+pub struct NotePage {
+    pub notes: Vec<NoteMetadata>,
+    /// Notes matching the query across all pages
+    pub total: usize,
+}
+
+/// Notes of an unlocked wallet by id, value and claim state, with their
+/// running balance overall and per account
+#[derive(Debug, Clone, Default)]
+pub struct NoteIndex {
+    notes: BTreeMap<NoteId, NoteMetadata>,
+    by_value: BTreeSet<(u64, NoteId)>,
+    by_claim_state: BTreeMap<ClaimState, BTreeSet<NoteId>>,
+    balance: Balance,
+    account_balances: BTreeMap<u32, Balance>,
+}
+
+impl NoteIndex {
+    pub fn len(&self) -> usize {
+        self.notes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn get(&self, id: NoteId) -> Option<&NoteMetadata> {
+        self.notes.get(&id)
+    }
+
+    /// Value of the indexed notes by pool, Zcash spend status and claim status
+    pub fn balance(&self) -> Balance {
+        self.balance
+    }
+
+    /// Value of the notes of `account`
+    pub fn account_balance(&self, account: u32) -> Balance {
+        self.account_balances.get(&account).copied().unwrap_or_default()
+    }
+
+    /// Add `note`, replacing any note indexed under the same id
+    pub fn insert(&mut self, note: NoteMetadata) -> Result<()> {
+        self.remove(note.id())?;
+        self.balance.add(note.pool, note.value, &note.status)?;
+        if let Some(account) = note.account {
+            self.account_balances.entry(account).or_default().add(note.pool, note.value, &note.status)?;
+        }
+        self.by_value.insert((note.value, note.id()));
+        self.by_claim_state.entry(note.status.claim_state()).or_default().insert(note.id());
+        self.notes.insert(note.id(), note);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, id: NoteId) -> Result<Option<NoteMetadata>> {
+        let Some(note) = self.notes.remove(&id) else {
+            return Ok(None);
+        };
+        self.balance.subtract(note.pool, note.value, &note.status)?;
+        if let Some(balance) = note.account.and_then(|account| self.account_balances.get_mut(&account)) {
+            balance.subtract(note.pool, note.value, &note.status)?;
+        }
+        self.by_value.remove(&(note.value, id));
+        if let Some(ids) = self.by_claim_state.get_mut(&note.status.claim_state()) {
+            ids.remove(&id);
+        }
+        Ok(Some(note))
+    }
+
+    /// Notes matching every filter of `query` except `eligible`, which needs
+    /// the airdrop nullifier registry, in the query's order and unpaged
+    pub fn select(&self, query: &NoteQuery) -> Vec<&NoteMetadata> {
+        let claim_state = match (query.claim_state, query.eligible) {
+            (Some(state), true) if state != ClaimState::Unclaimed => return Vec::new(),
+            (_, true) => Some(ClaimState::Unclaimed),
+            (state, false) => state,
+        };
+
+        // Start from the narrowest index the query allows
+        let candidates: Vec<&NoteMetadata> = if let Some(state) = claim_state {
+            self.by_claim_state
+                .get(&state)
+                .into_iter()
+                .flatten()
+                .map(|id| &self.notes[id])
+                .collect()
+        } else if let Some(min_value) = query.min_value {
+            self.by_value
+                .range((min_value, NoteId::new(Pool::Sapling, 0))..)
+                .map(|(_, id)| &self.notes[id])
+                .collect()
+        } else {
+            self.notes.values().collect()
+        };
+
+        let mut notes: Vec<&NoteMetadata> = candidates
+            .into_iter()
+            .filter(|note| query.min_value.is_none_or(|min_value| note.value >= min_value))
+            .filter(|note| query.account.is_none_or(|account| note.account == Some(account)))
+            .filter(|note| query.pool.is_none_or(|pool| note.pool == pool))
+            .filter(|note| !query.unspent || !note.status.is_spent)
+            .collect();
+
+        match query.sort {
+            NoteSort::Position => notes.sort_by_key(|note| note.id()),
+            NoteSort::Value => notes.sort_by_key(|note| (note.value, note.id())),
+            NoteSort::CreatedAt => notes.sort_by_key(|note| (note.created_at, note.id())),
+        }
+        if query.descending {
+            notes.reverse();
+        }
+        notes
+    }
+}
+
+impl NoteQuery {
+    /// Cut the page this query asks for out of every matching note
+    pub fn page(&self, notes: Vec<NoteMetadata>) -> NotePage {
+        let total = notes.len();
+        let notes = notes
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect();
+        NotePage { notes, total }
+    }
+}
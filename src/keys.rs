@@ -0,0 +1,422 @@
+//! Hierarchical deterministic keys derived from a BIP-39 seed phrase.
+//!
+//! Each account holds a ZIP-32 Sapling extended spending key at
+//! `m/32'/coin_type'/account'` and the Orchard spending key for the same
+//! path. From these we derive the components the claim circuits need: the
+//! nullifier deriving key `nk`, the spend validating key `ak` (and its
+//! rerandomization for spend-auth signatures), and the incoming and full
+//! viewing keys used for trial decryption.
+//!
+//! Claims are authorized by a RedJubjub (Sapling) or RedPallas (Orchard)
+//! signature over the transaction sighash with `ask + alpha`, verifying under
+//! the claim's published `rk = ak + [alpha] G`.
+
+use std::fmt;
+
+use bip39::Mnemonic;
+use ff::{FromUniformBytes, PrimeField};
+use orchard::keys::{
+    FullViewingKey as OrchardFvk, Scope, SpendAuthorizingKey, SpendValidatingKey,
+    SpendingKey as OrchardSpendingKey,
+};
+use orchard::primitives::redpallas;
+use rand::rngs::OsRng;
+use sapling_crypto::zip32::{DiversifiableFullViewingKey, ExtendedSpendingKey};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zcash_address::unified::{self, Container, Encoding};
+use zcash_primitives::consensus::NetworkType;
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+use zip32::ChildIndex;
+
+use crate::{Pool, PublicKey, Scalar, Signature};
+
+/// ZIP-32 purpose for shielded keys
+const ZIP32_PURPOSE: u32 = 32;
+
+#[derive(Debug, Error)]
+pub enum KeyError {
+    #[error("invalid seed phrase: {0}")]
+    InvalidMnemonic(String),
+
+    #[error("invalid account index {0}")]
+    InvalidAccount(u32),
+
+    #[error("malformed {0} key encoding")]
+    MalformedKey(Pool),
+
+    #[error("invalid unified full viewing key")]
+    InvalidViewingKey,
+
+    #[error("viewing key belongs to a different network")]
+    NetworkMismatch,
+
+    #[error("account has no {0} viewing key")]
+    MissingPool(Pool),
+}
+
+/// SLIP-44 coin type used in ZIP-32 paths for `network`
+pub fn coin_type(network: &str) -> u32 {
+    match network {
+        "mainnet" | "main" => 133,
+        _ => 1,
+    }
+}
+
+/// BIP-39 mnemonic the wallet's accounts are derived from
+#[derive(Clone)]
+pub struct WalletSeed {
+    mnemonic: Mnemonic,
+}
+
+impl fmt::Debug for WalletSeed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "WalletSeed(..)")
+    }
+}
+
+impl WalletSeed {
+    /// Generate a new 24-word mnemonic
+    pub fn generate() -> Self {
+        let entropy = Zeroizing::new(rand::random::<[u8; 32]>());
+        let mnemonic = Mnemonic::from_entropy(entropy.as_ref())
+            .expect("32 bytes of entropy is a valid mnemonic length");
+        Self { mnemonic }
+    }
+
+    /// Restore a mnemonic, checking its word list and checksum
+    pub fn from_phrase(phrase: &str) -> Result<Self, KeyError> {
+        let mnemonic = Mnemonic::parse(phrase.trim())
+            .map_err(|e| KeyError::InvalidMnemonic(e.to_string()))?;
+        Ok(Self { mnemonic })
+    }
+
+    /// The words of the mnemonic, for backup
+    pub fn phrase(&self) -> Zeroizing<String> {
+        Zeroizing::new(self.mnemonic.to_string())
+    }
+
+    /// Derive the spending keys of `account`
+    pub fn derive_account(&self, coin_type: u32, account: u32) -> Result<AccountKeys, KeyError> {
+        let zip32_account =
+            zip32::AccountId::try_from(account).map_err(|_| KeyError::InvalidAccount(account))?;
+        let seed = Zeroizing::new(self.mnemonic.to_seed(""));
+
+        let master = ExtendedSpendingKey::master(seed.as_ref());
+        let sapling = ExtendedSpendingKey::from_path(
+            &master,
+            &[
+                ChildIndex::hardened(ZIP32_PURPOSE),
+                ChildIndex::hardened(coin_type),
+                ChildIndex::hardened(account),
+            ],
+        );
+        let orchard = OrchardSpendingKey::from_zip32_seed(seed.as_ref(), coin_type, zip32_account)
+            .map_err(|_| KeyError::InvalidAccount(account))?;
+
+        Ok(AccountKeys { account, sapling, orchard })
+    }
+}
+
+/// Serialized spending keys of one account, stored encrypted in the wallet
+#[derive(Clone, Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+pub struct AccountKeyRecord {
+    pub account: u32,
+    pub sapling_extsk: Vec<u8>,
+    pub orchard_sk: [u8; 32],
+}
+
+/// Key components of one pool that never grant spending authority
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ViewingKeys {
+    pub pool: Pool,
+    /// Spend validating key
+    pub ak: PublicKey,
+    /// Nullifier deriving key
+    pub nk: Scalar,
+    /// External incoming viewing key, used for trial decryption
+    pub ivk: Vec<u8>,
+    /// Full viewing key encoding; diversifiable for Sapling
+    pub fvk: Vec<u8>,
+}
+
+impl ViewingKeys {
+    /// Split a Sapling diversifiable or Orchard full viewing key encoding
+    pub fn from_fvk(pool: Pool, fvk: &[u8]) -> Result<Self, KeyError> {
+        // Both full viewing key encodings start with ak || nk
+        let ivk = match pool {
+            Pool::Sapling => {
+                let bytes: [u8; 128] = fvk.try_into().map_err(|_| KeyError::MalformedKey(pool))?;
+                let dfvk = DiversifiableFullViewingKey::from_bytes(&bytes)
+                    .ok_or(KeyError::MalformedKey(pool))?;
+                dfvk.to_external_ivk().to_bytes().to_vec()
+            }
+            Pool::Orchard => {
+                let bytes: [u8; 96] = fvk.try_into().map_err(|_| KeyError::MalformedKey(pool))?;
+                let fvk = OrchardFvk::from_bytes(&bytes).ok_or(KeyError::MalformedKey(pool))?;
+                fvk.to_ivk(Scope::External).to_bytes().to_vec()
+            }
+        };
+
+        let mut ak = [0u8; 32];
+        let mut nk = [0u8; 32];
+        ak.copy_from_slice(&fvk[..32]);
+        nk.copy_from_slice(&fvk[32..64]);
+
+        Ok(ViewingKeys { pool, ak: PublicKey(ak), nk: Scalar(nk), ivk, fvk: fvk.to_vec() })
+    }
+
+    /// Nullifier deriving keys of the external and internal scopes, either of
+    /// which a received note may carry
+    pub fn nullifier_keys(&self) -> Vec<Scalar> {
+        let mut keys = vec![self.nk];
+        // Sapling change notes use a separate nsk; Orchard shares nk across scopes
+        if let Some(dfvk) = self.sapling_dfvk() {
+            let mut nk = [0u8; 32];
+            nk.copy_from_slice(&dfvk.to_internal_fvk().to_bytes()[32..64]);
+            keys.push(Scalar(nk));
+        }
+        keys
+    }
+
+    /// `ak` rerandomized by `alpha`; computable without spending authority
+    pub fn randomized_key(&self, alpha: &Scalar) -> Result<PublicKey, KeyError> {
+        let rk: [u8; 32] = match self.pool {
+            Pool::Sapling => {
+                let dfvk = self.sapling_dfvk().ok_or(KeyError::MalformedKey(Pool::Sapling))?;
+                dfvk.fvk().vk.ak.randomize(&sapling_alpha(alpha)).into()
+            }
+            Pool::Orchard => {
+                let fvk: [u8; 96] =
+                    self.fvk.as_slice().try_into().map_err(|_| KeyError::MalformedKey(Pool::Orchard))?;
+                let fvk = OrchardFvk::from_bytes(&fvk).ok_or(KeyError::MalformedKey(Pool::Orchard))?;
+                (&SpendValidatingKey::from(fvk).randomize(&orchard_alpha(alpha))).into()
+            }
+        };
+        Ok(PublicKey(rk))
+    }
+
+    fn sapling_dfvk(&self) -> Option<DiversifiableFullViewingKey> {
+        let bytes: [u8; 128] = self.fvk.as_slice().try_into().ok()?;
+        match self.pool {
+            Pool::Sapling => DiversifiableFullViewingKey::from_bytes(&bytes),
+            Pool::Orchard => None,
+        }
+    }
+}
+
+/// Viewing capability of one account: everything a watch-only wallet holds
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ViewingAccount {
+    pub account: u32,
+    pub sapling: Option<ViewingKeys>,
+    pub orchard: Option<ViewingKeys>,
+}
+
+impl ViewingAccount {
+    /// Decode the Sapling and Orchard items of a ZIP-316 unified full viewing key
+    pub fn from_ufvk(account: u32, ufvk: &str, network: NetworkType) -> Result<Self, KeyError> {
+        let (ufvk_network, ufvk) =
+            unified::Ufvk::decode(ufvk.trim()).map_err(|_| KeyError::InvalidViewingKey)?;
+        if ufvk_network != network {
+            return Err(KeyError::NetworkMismatch);
+        }
+
+        let mut viewing = ViewingAccount { account, sapling: None, orchard: None };
+        for item in ufvk.items() {
+            match item {
+                unified::Fvk::Sapling(bytes) => viewing.sapling = Some(ViewingKeys::from_fvk(Pool::Sapling, &bytes)?),
+                unified::Fvk::Orchard(bytes) => viewing.orchard = Some(ViewingKeys::from_fvk(Pool::Orchard, &bytes)?),
+                _ => {}
+            }
+        }
+
+        if viewing.sapling.is_none() && viewing.orchard.is_none() {
+            return Err(KeyError::InvalidViewingKey);
+        }
+        Ok(viewing)
+    }
+
+    /// Encode the account's viewing keys as a unified full viewing key
+    pub fn to_ufvk(&self, network: NetworkType) -> Result<String, KeyError> {
+        let mut items = Vec::new();
+        if let Some(orchard) = &self.orchard {
+            let bytes = orchard.fvk.as_slice().try_into().map_err(|_| KeyError::MalformedKey(Pool::Orchard))?;
+            items.push(unified::Fvk::Orchard(bytes));
+        }
+        if let Some(sapling) = &self.sapling {
+            let bytes = sapling.fvk.as_slice().try_into().map_err(|_| KeyError::MalformedKey(Pool::Sapling))?;
+            items.push(unified::Fvk::Sapling(bytes));
+        }
+        let ufvk = unified::Ufvk::try_from_items(items).map_err(|_| KeyError::InvalidViewingKey)?;
+        Ok(ufvk.encode(&network))
+    }
+
+    /// Viewing keys of `pool`
+    pub fn pool(&self, pool: Pool) -> Result<&ViewingKeys, KeyError> {
+        match pool {
+            Pool::Sapling => self.sapling.as_ref(),
+            Pool::Orchard => self.orchard.as_ref(),
+        }
+        .ok_or(KeyError::MissingPool(pool))
+    }
+
+    /// Whether a note carrying nullifier deriving key `nk` belongs to this account
+    pub fn owns(&self, pool: Pool, nk: &Scalar) -> bool {
+        self.pool(pool).is_ok_and(|keys| keys.nullifier_keys().contains(nk))
+    }
+}
+
+/// Spending keys of one ZIP-32 account
+#[derive(Clone)]
+pub struct AccountKeys {
+    account: u32,
+    sapling: ExtendedSpendingKey,
+    orchard: OrchardSpendingKey,
+}
+
+impl fmt::Debug for AccountKeys {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "AccountKeys({})", self.account)
+    }
+}
+
+impl AccountKeys {
+    pub fn account(&self) -> u32 {
+        self.account
+    }
+
+    pub fn to_record(&self) -> AccountKeyRecord {
+        AccountKeyRecord {
+            account: self.account,
+            sapling_extsk: self.sapling.to_bytes().to_vec(),
+            orchard_sk: *self.orchard.to_bytes(),
+        }
+    }
+
+    pub fn from_record(record: &AccountKeyRecord) -> Result<Self, KeyError> {
+        let sapling = ExtendedSpendingKey::from_bytes(&record.sapling_extsk)
+            .map_err(|_| KeyError::MalformedKey(Pool::Sapling))?;
+        let orchard = Option::from(OrchardSpendingKey::from_bytes(record.orchard_sk))
+            .ok_or(KeyError::MalformedKey(Pool::Orchard))?;
+        Ok(Self { account: record.account, sapling, orchard })
+    }
+
+    /// Viewing capability of this account in `pool`
+    pub fn viewing_keys(&self, pool: Pool) -> ViewingKeys {
+        let fvk = match pool {
+            Pool::Sapling => self.sapling.to_diversifiable_full_viewing_key().to_bytes().to_vec(),
+            Pool::Orchard => OrchardFvk::from(&self.orchard).to_bytes().to_vec(),
+        };
+        ViewingKeys::from_fvk(pool, &fvk).expect("derived full viewing keys are well formed")
+    }
+
+    /// Viewing capability of this account in both pools
+    pub fn viewing_account(&self) -> ViewingAccount {
+        ViewingAccount {
+            account: self.account,
+            sapling: Some(self.viewing_keys(Pool::Sapling)),
+            orchard: Some(self.viewing_keys(Pool::Orchard)),
+        }
+    }
+
+    /// The spend validating key rerandomized by `alpha`, as published in a claim
+    pub fn randomized_key(&self, pool: Pool, alpha: &Scalar) -> PublicKey {
+        self.viewing_keys(pool)
+            .randomized_key(alpha)
+            .expect("derived spend validating keys are well formed")
+    }
+
+    /// Spend-auth signature over `sighash` with `ask` rerandomized by `alpha`
+    pub fn sign_spend_auth(&self, pool: Pool, alpha: &Scalar, sighash: &[u8; 32]) -> Signature {
+        let signature: [u8; 64] = match pool {
+            Pool::Sapling => {
+                let rsk = self.sapling.expsk.ask.randomize(&sapling_alpha(alpha));
+                rsk.sign(OsRng, sighash).into()
+            }
+            Pool::Orchard => {
+                let rsk = SpendAuthorizingKey::from(&self.orchard).randomize(&orchard_alpha(alpha));
+                (&rsk.sign(OsRng, sighash)).into()
+            }
+        };
+        Signature(signature)
+    }
+
+    /// Single-use keys, for authorizing test claims of notes no account holds
+    #[cfg(test)]
+    pub fn ephemeral() -> Self {
+        let seed = Zeroizing::new(rand::random::<[u8; 32]>());
+        let sapling = ExtendedSpendingKey::master(seed.as_ref());
+        let orchard = OrchardSpendingKey::from_zip32_seed(seed.as_ref(), 1, zip32::AccountId::ZERO)
+            .expect("a random seed yields a valid Orchard spending key");
+        Self { account: 0, sapling, orchard }
+    }
+
+    /// Reduce `bytes` to a canonical Sapling scalar, e.g. to sample `alpha`
+    pub fn sapling_scalar(bytes: &[u8; 32]) -> Scalar {
+        Scalar(sapling_alpha(&Scalar(*bytes)).to_repr())
+    }
+}
+
+/// Check a spend-auth signature over `sighash` against a claim's `rk`
+pub fn verify_spend_auth(pool: Pool, rk: &PublicKey, sighash: &[u8; 32], signature: &Signature) -> bool {
+    match pool {
+        Pool::Sapling => redjubjub::VerificationKey::<redjubjub::SpendAuth>::try_from(rk.0)
+            .is_ok_and(|vk| vk.verify(sighash, &redjubjub::Signature::from(signature.0)).is_ok()),
+        Pool::Orchard => redpallas::VerificationKey::<redpallas::SpendAuth>::try_from(rk.0)
+            .is_ok_and(|vk| vk.verify(sighash, &redpallas::Signature::from(signature.0)).is_ok()),
+    }
+}
+
+/// `alpha` reduced to a Jubjub scalar
+fn sapling_alpha(alpha: &Scalar) -> jubjub::Fr {
+    let mut wide = [0u8; 64];
+    wide[..32].copy_from_slice(&alpha.0);
+    jubjub::Fr::from_bytes_wide(&wide)
+}
+
+/// `alpha` reduced to a Pallas scalar
+fn orchard_alpha(alpha: &Scalar) -> pasta_curves::pallas::Scalar {
+    let mut wide = [0u8; 64];
+    wide[..32].copy_from_slice(&alpha.0);
+    pasta_curves::pallas::Scalar::from_uniform_bytes(&wide)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PHRASE: &str = "abandon abandon abandon abandon abandon abandon abandon abandon \
+                          abandon abandon abandon abandon abandon abandon abandon abandon \
+                          abandon abandon abandon abandon abandon abandon abandon art";
+
+    #[test]
+    fn test_account_derivation_is_deterministic() {
+        let seed = WalletSeed::from_phrase(PHRASE).unwrap();
+        let account = seed.derive_account(coin_type("testnet"), 0).unwrap();
+
+        // Restoring from the stored record or the phrase yields the same keys
+        let restored = AccountKeys::from_record(&account.to_record()).unwrap();
+        let rederived = WalletSeed::from_phrase(&seed.phrase()).unwrap().derive_account(1, 0).unwrap();
+        for pool in [Pool::Sapling, Pool::Orchard] {
+            assert_eq!(restored.viewing_keys(pool), account.viewing_keys(pool));
+            assert_eq!(rederived.viewing_keys(pool), account.viewing_keys(pool));
+        }
+
+        // Accounts and networks are separated
+        let other = seed.derive_account(1, 1).unwrap();
+        let mainnet = seed.derive_account(coin_type("mainnet"), 0).unwrap();
+        assert_ne!(other.viewing_keys(Pool::Sapling).nk, account.viewing_keys(Pool::Sapling).nk);
+        assert_ne!(mainnet.viewing_keys(Pool::Orchard).ak, account.viewing_keys(Pool::Orchard).ak);
+
+        // Rerandomized keys are unlinkable to ak
+        let alpha = AccountKeys::sapling_scalar(&[7u8; 32]);
+        for pool in [Pool::Sapling, Pool::Orchard] {
+            let rk = account.randomized_key(pool, &alpha);
+            assert_ne!(rk, account.viewing_keys(pool).ak);
+            assert_eq!(rk, account.randomized_key(pool, &alpha));
+        }
+
+        assert!(WalletSeed::from_phrase("abandon abandon").is_err());
+    }
+}
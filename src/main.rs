@@ -1,12 +1,28 @@
 use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tracing::info;
 use anyhow::{Result, Context};
 use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use zcash_primitives::consensus::Parameters;
 
+use zec_nam::config::Config;
+use zec_nam::keys::WalletSeed;
+use zec_nam::namada::RpcNamadaClient;
+use zec_nam::import::{parse_keys, parse_listunspent, ImportFormat};
+use zec_nam::scanner::{consensus_network, read_compact_blocks};
+use zec_nam::store::{open_store, WalletStore};
+use zec_nam::export::{ExportFormat, WalletExport};
+use zec_nam::index::{ClaimState, NoteQuery, NoteSort};
+use zec_nam::planner::{PlanOptions, SelectionStrategy};
+use zec_nam::scheduler::{RelayClient, RelayQueue, ScheduledSlot};
+use zec_nam::wallet::{AirdropWallet, Balance, ClaimStatus, ImportMode};
 use zec_nam::{
-    AirdropWallet, ShieldedAirdropTransaction, SaplingNote, OrchardNote,
-    PublicKey, ProtocolError
+    FieldElement, MemoryNullifierRegistry, NoteCommitment, NoteId, NullifierRegistry, OrchardNote,
+    PartialAirdropTransaction, Pool, PublicKey, SaplingNote, Scalar, ShieldedAirdropTransaction,
+    SledNullifierRegistry,
 };
 
 #[derive(Parser)]
@@ -17,6 +33,14 @@ struct Cli {
     #[arg(short, long, value_name = "FILE")]
     config: Option<PathBuf>,
 
+    /// Named wallet in the wallets directory; the default wallet when absent
+    #[arg(short, long, global = true)]
+    wallet: Option<String>,
+
+    /// ZIP-32 account to show or act on; every account when absent
+    #[arg(long, global = true)]
+    account: Option<u32>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -27,98 +51,310 @@ enum Commands {
     InitWallet {
         #[arg(short, long)]
         name: Option<String>,
-        
-        #[arg(short, long)]
+
+        #[arg(long)]
         network: Option<String>,
+
+        /// Restore from an existing seed phrase instead of generating one
+        #[arg(long, conflicts_with = "ufvk")]
+        restore: bool,
+
+        /// Create a watch-only wallet from a unified full viewing key
+        #[arg(long)]
+        ufvk: Option<String>,
     },
-    
+
+    /// List the default and named wallets
+    ListWallets,
+
+    /// Derive the next ZIP-32 account from the wallet seed
+    AddAccount,
+
+    /// Print the unified full viewing key of `--account` (0 by default), e.g.
+    /// for a watch-only wallet
+    ExportViewingKey,
+
     /// Show wallet status and balance
     ShowStatus,
-    
+
+    /// Change the passphrase protecting the wallet database
+    ChangePassphrase,
+
     /// Import notes from Zcash wallet
     ImportNotes {
+        /// z_listunspent JSON (zcashd) or a viewing-key export (Zashi, YWallet)
         #[arg(short, long)]
         file: PathBuf,
-        
-        #[arg(short, long)]
+
+        /// "zcashd" or "viewing-key"
+        #[arg(long)]
         format: Option<String>,
+
+        /// z_exportwallet key dump, required for the zcashd format
+        #[arg(short, long)]
+        keys: Option<PathBuf>,
+
+        /// Compact blocks to locate the notes in
+        #[arg(short, long)]
+        blocks: PathBuf,
     },
-    
+
     /// List all notes in the wallet
     ListNotes {
         #[arg(short, long)]
         min_value: Option<u64>,
-        
-        #[arg(short, long)]
+
+        #[arg(short = 't', long)]
         note_type: Option<String>,
+
+        /// unclaimed, reserved or claimed
+        #[arg(short, long)]
+        status: Option<ClaimState>,
+
+        /// Only notes that can still claim the airdrop
+        #[arg(long)]
+        eligible: bool,
+
+        /// Only notes unspent on Zcash
+        #[arg(long)]
+        unspent: bool,
+
+        /// position, value or created
+        #[arg(long, default_value = "position")]
+        sort: NoteSort,
+
+        #[arg(long)]
+        descending: bool,
+
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+
+    /// List recorded claims and their Namada status
+    ListClaims {
+        /// pending, broadcast, confirmed, failed or expired
+        #[arg(short, long)]
+        status: Option<ClaimStatus>,
+    },
+
+    /// Check each note against the airdrop rules and show why it qualifies or not
+    EligibilityReport {
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Only show notes that qualify
+        #[arg(long)]
+        eligible_only: bool,
     },
-    
+
     /// Create an airdrop transaction
     CreateAirdrop {
+        /// Note to claim, as listed by ListNotes (e.g. sapling:3)
         #[arg(short, long)]
-        note_index: usize,
-        
+        note: NoteId,
+
         #[arg(short, long)]
         amount: u64,
-        
+
         #[arg(short, long)]
         recipient: String,
-        
+
+        #[arg(short, long, default_value = "airdrop_tx.bin")]
+        out_file: PathBuf,
+
+        /// Abandon the unfinished claim reserving the note, if any
+        #[arg(long)]
+        force_release: bool,
+    },
+
+    /// Choose notes to claim by strategy, and preview or build the claims
+    PlanClaims {
+        /// max-value, min-transactions, all-at-once or spread
+        #[arg(long, default_value = "max-value")]
+        strategy: SelectionStrategy,
+
+        /// Airdrop amount to stop at; required by min-transactions
+        #[arg(long)]
+        target: Option<u64>,
+
+        #[arg(long)]
+        max_claims: Option<usize>,
+
+        #[arg(short, long, default_value_t = 0)]
+        min_value: u64,
+
+        /// Build the planned claims instead of only previewing them
+        #[arg(long, requires = "recipient")]
+        execute: bool,
+
         #[arg(short, long)]
-        note_type: Option<String>,
+        recipient: Option<String>,
+
+        /// Only build this batch of the plan
+        #[arg(long)]
+        batch: Option<usize>,
+
+        /// Directory the claim transactions are written to
+        #[arg(short, long, default_value = ".")]
+        out_dir: PathBuf,
+    },
+
+    /// Schedule built claims for submission after random delays
+    ScheduleClaims {
+        /// Claim transactions, as written by CreateAirdrop or PlanClaims
+        #[arg(short, long, required = true, num_args = 1..)]
+        tx_files: Vec<PathBuf>,
+    },
+
+    /// List the scheduled claim submissions
+    ShowSchedule,
+
+    /// Act on the scheduled slots that are due, submitting their claims to
+    /// the node or to the relay queue
+    RunSchedule,
+
+    /// Submit the claims waiting in the relay queue in one shuffled batch
+    RelayFlush {
+        /// Claims to wait for before submitting; from the config when absent
+        #[arg(long)]
+        min_batch: Option<usize>,
+    },
+
+    /// Export an unsigned claim for an offline signer
+    PrepareAirdrop {
+        /// Note to claim, as listed by ListNotes (e.g. sapling:3)
+        #[arg(short, long)]
+        note: NoteId,
+
+        #[arg(short, long)]
+        amount: u64,
+
+        #[arg(short, long)]
+        recipient: String,
+
+        #[arg(short, long, default_value = "airdrop_claim.pczt")]
+        out_file: PathBuf,
     },
-    
-    /// Submit an airdrop transaction to the network
+
+    /// Sign an exported claim with the wallet's spending keys
+    SignAirdrop {
+        #[arg(short, long)]
+        in_file: PathBuf,
+
+        #[arg(short, long)]
+        out_file: PathBuf,
+    },
+
+    /// Add the binding signature to a signed claim and record it
+    FinalizeAirdrop {
+        #[arg(short, long)]
+        in_file: PathBuf,
+
+        #[arg(short, long, default_value = "airdrop_tx.bin")]
+        out_file: PathBuf,
+    },
+
+    /// Broadcast a finalized claim to the Namada node
     SubmitAirdrop {
         #[arg(short, long)]
         tx_file: PathBuf,
+
+        /// Namada blocks the claim may wait for inclusion; from the config
+        /// when absent
+        #[arg(long)]
+        expiry_blocks: Option<u64>,
     },
-    
+
+    /// Ask the Namada node what became of the broadcast claims
+    SyncClaims,
+
     /// Verify an airdrop transaction
     VerifyAirdrop {
         #[arg(short, long)]
         tx_file: PathBuf,
+
+        /// Airdrop nullifier registry to check for double claims
+        #[arg(short, long)]
+        registry: Option<PathBuf>,
     },
-    
+
     /// Show transaction details
     ShowTransaction {
         #[arg(short, long)]
         tx_file: PathBuf,
     },
-    
-    /// Connect to Zcash network and sync
-    SyncWallet,
-    
+
+    /// Scan compact blocks for the wallet's notes up to the snapshot height
+    SyncWallet {
+        /// File of length-delimited lightwalletd CompactBlock messages
+        #[arg(short, long)]
+        blocks: PathBuf,
+    },
+
+    /// Drop what scanning found from a height on and scan the blocks again
+    Rescan {
+        /// File of length-delimited lightwalletd CompactBlock messages
+        #[arg(short, long)]
+        blocks: PathBuf,
+
+        /// First block to scan again; must be in the file
+        #[arg(long)]
+        from_height: u64,
+    },
+
+    /// Check the wallet's notes, nullifiers and claims for discrepancies
+    CheckWallet {
+        /// Fix the discrepancies found
+        #[arg(long)]
+        repair: bool,
+    },
+
     /// Show network status
     NetworkStatus,
-    
+
     /// Export wallet data
     ExportWallet {
         #[arg(short, long)]
         file: PathBuf,
-        
+
+        /// json, bincode or encrypted; json and bincode exports hold note secrets in plaintext
+        #[arg(long, default_value_t = ExportFormat::Encrypted)]
+        format: ExportFormat,
+    },
+
+    /// Import a wallet export, replacing the wallet's notes and claims
+    ImportWallet {
         #[arg(short, long)]
-        format: Option<String>,
+        file: PathBuf,
+
+        /// Keep the wallet's notes and claims, adding only what it lacks
+        #[arg(long)]
+        merge: bool,
     },
-    
+
     /// Generate test data for development
     GenerateTestData {
         #[arg(short, long)]
         count: Option<usize>,
     },
-    
+
     /// Create a Sapling->MASP or Orchard->MASP airdrop transaction
     CreateMaspAirdrop {
         #[arg(short, long)]
-        note_index: usize,
+        note: NoteId,
         #[arg(short, long)]
         amount: u64,
         #[arg(short, long)]
         masp_recipient: String,
         #[arg(short, long)]
-        note_type: String, // "sapling" or "orchard"
-        #[arg(short, long)]
         out_file: PathBuf,
+        /// Abandon the unfinished claim reserving the note, if any
+        #[arg(long)]
+        force_release: bool,
     },
     /// Verify a MASP airdrop transaction
     VerifyMaspAirdrop {
@@ -132,91 +368,728 @@ enum Commands {
     },
 }
 
+impl Commands {
+    /// Whether the command acts on the account given with `--account`; the
+    /// others act on every account of the wallet, or on files alone
+    fn takes_account(&self) -> bool {
+        matches!(
+            self,
+            Commands::ExportViewingKey
+                | Commands::ShowStatus
+                | Commands::ListNotes { .. }
+                | Commands::ListClaims { .. }
+                | Commands::EligibilityReport { .. }
+                | Commands::CreateAirdrop { .. }
+                | Commands::PlanClaims { .. }
+                | Commands::ScheduleClaims { .. }
+                | Commands::ShowSchedule
+                | Commands::PrepareAirdrop { .. }
+                | Commands::SignAirdrop { .. }
+                | Commands::SubmitAirdrop { .. }
+                | Commands::CreateMaspAirdrop { .. }
+        )
+    }
+}
+
+const PASSPHRASE_ENV: &str = "ZEC_NAM_PASSPHRASE";
+const NEW_PASSPHRASE_ENV: &str = "ZEC_NAM_NEW_PASSPHRASE";
+const MNEMONIC_ENV: &str = "ZEC_NAM_MNEMONIC";
+const EXPORT_PASSPHRASE_ENV: &str = "ZEC_NAM_EXPORT_PASSPHRASE";
+
+/// Read a passphrase from `env_var`, or prompt for it on the terminal
+/// without echoing it
+fn read_passphrase(prompt: &str, env_var: &str) -> Result<String> {
+    if let Ok(passphrase) = std::env::var(env_var) {
+        return Ok(passphrase);
+    }
+
+    if io::stdin().is_terminal() {
+        return rpassword::prompt_password(format!("{}: ", prompt))
+            .with_context(|| "Failed to read from the terminal");
+    }
+
+    // Piped input, as from a script, is read a line at a time
+    eprint!("{}: ", prompt);
+    io::stderr().flush()?;
+    let mut passphrase = String::new();
+    io::stdin().read_line(&mut passphrase)?;
+    Ok(passphrase.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn open_wallet(config: &Config) -> Result<AirdropWallet> {
+    if !config.wallet_path.exists() {
+        anyhow::bail!(
+            "No wallet found at {}; run `init-wallet` first",
+            config.wallet_path.display()
+        );
+    }
+
+    let passphrase = read_passphrase("Wallet passphrase", PASSPHRASE_ENV)?;
+    let mut wallet = AirdropWallet::open_store(wallet_store(config)?)?;
+    wallet.unlock(&passphrase).with_context(|| "Failed to unlock wallet")?;
+    wallet.set_policy(config.airdrop.policy);
+    Ok(wallet)
+}
+
+/// The configured store holding the wallet at `wallet_path`
+fn wallet_store(config: &Config) -> Result<Arc<dyn WalletStore>> {
+    open_store(config.storage.backend, &config.wallet_path)
+        .with_context(|| format!("Failed to open wallet database: {}", config.wallet_path.display()))
+}
+
+/// Client for the Namada node at `namada.rpc_url`
+fn namada_client(config: &Config) -> Result<RpcNamadaClient> {
+    RpcNamadaClient::new(&config.namada.rpc_url, Duration::from_secs(config.network.timeout_seconds))
+        .with_context(|| format!("Failed to connect to Namada node: {}", config.namada.rpc_url))
+}
+
+fn parse_recipient(recipient: &str) -> Result<PublicKey> {
+    let bytes = hex::decode(recipient)
+        .with_context(|| "Recipient must be a hex-encoded MASP public key")?;
+    let key: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Recipient must be 32 bytes"))?;
+    Ok(PublicKey(key))
+}
+
+fn read_transaction(tx_file: &Path) -> Result<ShieldedAirdropTransaction> {
+    let data = fs::read(tx_file)
+        .with_context(|| format!("Failed to read transaction file: {}", tx_file.display()))?;
+
+    ShieldedAirdropTransaction::from_bytes(&data)
+        .with_context(|| "Failed to deserialize transaction")
+}
+
+/// Refuse to claim the note `id` on behalf of an account that does not hold it
+fn ensure_note_account(wallet: &AirdropWallet, id: NoteId, account: Option<u32>) -> Result<()> {
+    if let Some(account) = account {
+        if wallet.note_account(id)? != Some(account) {
+            anyhow::bail!("Note {} is not held by account {}", id, account);
+        }
+    }
+    Ok(())
+}
+
+/// Refuse to act on the recorded claim `tx` on behalf of an account that does
+/// not hold its note
+fn ensure_claim_account(wallet: &AirdropWallet, tx: &ShieldedAirdropTransaction, account: Option<u32>) -> Result<()> {
+    if let Some(id) = wallet.claim(&hex::encode(tx.txid()))?.and_then(|claim| claim.note) {
+        ensure_note_account(wallet, id, account)?;
+    }
+    Ok(())
+}
+
+fn print_balance(balance: &Balance, indent: &str) {
+    for pool in [Pool::Sapling, Pool::Orchard] {
+        let balance = balance.pool(pool);
+        println!(
+            "{}{} balance: {} zatoshis unspent on Zcash; airdrop: {} claimable, {} reserved, {} claimed",
+            indent, pool, balance.unspent, balance.claimable, balance.reserved, balance.claimed
+        );
+    }
+}
+
+fn create_airdrop(
+    config: &Config,
+    account: Option<u32>,
+    note: NoteId,
+    amount: u64,
+    recipient: &str,
+    out_file: &Path,
+    force_release: bool,
+) -> Result<()> {
+    let recipient = parse_recipient(recipient)?;
+    let mut wallet = open_wallet(config)?;
+    ensure_note_account(&wallet, note, account)?;
+
+    if force_release {
+        if let Some(abandoned) = wallet.force_release(note)? {
+            println!("Released note {} from claim {}", note, abandoned);
+        }
+    }
+    let tx = wallet.create_airdrop_tx(note, amount, &recipient)?;
+    let txid = hex::encode(tx.txid());
+
+    fs::write(out_file, tx.to_bytes()?)
+        .with_context(|| format!("Failed to write transaction file: {}", out_file.display()))?;
+
+    println!("Created {} airdrop transaction {}", note.pool, txid);
+    println!("  Airdrop nullifier: {}", tx.get_airdrop_nullifier());
+    println!("  Written to: {}", out_file.display());
+    Ok(())
+}
+
+fn read_partial(in_file: &Path) -> Result<PartialAirdropTransaction> {
+    let data = fs::read(in_file)
+        .with_context(|| format!("Failed to read claim file: {}", in_file.display()))?;
+
+    PartialAirdropTransaction::from_bytes(&data)
+        .with_context(|| "Failed to deserialize claim")
+}
+
+fn write_partial(out_file: &Path, partial: &PartialAirdropTransaction) -> Result<()> {
+    fs::write(out_file, partial.to_bytes()?)
+        .with_context(|| format!("Failed to write claim file: {}", out_file.display()))
+}
+
+fn print_claim(partial: &PartialAirdropTransaction) {
+    let amount = partial.masp_mint_description.value_commitment.mock_value().unwrap_or_default();
+    println!("  Pool: {}", partial.pool());
+    println!("  Amount: {} zatoshis", amount);
+    println!("  MASP recipient: {}", hex::encode(partial.masp_mint_description.recipient.0));
+    println!("  Airdrop nullifier: {}", partial.get_airdrop_nullifier());
+}
+
+fn import_notes(
+    config: &Config,
+    file: &Path,
+    format: Option<&str>,
+    keys: Option<&Path>,
+    blocks: &Path,
+) -> Result<()> {
+    let read = |path: &Path| {
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))
+    };
+    let format = format.unwrap_or("zcashd").parse::<ImportFormat>()?;
+    let blocks = read_compact_blocks(blocks)?;
+    let mut wallet = open_wallet(config)?;
+    let network = consensus_network(&wallet.get_metadata().network);
+
+    let (scan_keys, expected) = match format {
+        ImportFormat::Zcashd => {
+            let keys = keys.ok_or_else(|| anyhow::anyhow!("--keys is required for zcashd imports"))?;
+            (parse_keys(&read(keys)?, network)?, Some(parse_listunspent(&read(file)?)?))
+        }
+        ImportFormat::ViewingKey => (parse_keys(&read(file)?, network)?, None),
+    };
+
+    let summary = wallet.import_notes(
+        &blocks,
+        scan_keys,
+        expected.as_deref(),
+        config.airdrop.snapshot_height,
+    )?;
+
+    println!("Imported {} notes", summary.imported);
+    println!("  Already in wallet: {}", summary.duplicates);
+    for note in &summary.missing {
+        let mut txid = note.txid;
+        txid.reverse();
+        println!("  Not found: {} {}:{} ({} zatoshis)", note.pool, hex::encode(txid), note.index, note.value);
+    }
+    Ok(())
+}
+
+fn verify_airdrop(config: &Config, tx_file: &Path, registry: Option<&Path>) -> Result<()> {
+    let tx = read_transaction(tx_file)?;
+
+    let registry: Box<dyn NullifierRegistry> = match registry {
+        Some(path) => Box::new(SledNullifierRegistry::open(path)
+            .with_context(|| format!("Failed to open nullifier registry: {}", path.display()))?),
+        None => Box::new(MemoryNullifierRegistry::new()),
+    };
+
+    // A rejection is returned so the process exits non-zero
+    tx.validate(registry.as_ref(), &config.airdrop.policy)
+        .with_context(|| "Transaction rejected")?;
+    println!("Transaction is valid");
+    Ok(())
+}
+
+fn show_transaction(tx_file: &Path) -> Result<()> {
+    let tx = read_transaction(tx_file)?;
+
+    println!("Transaction details:");
+    println!("  Transaction ID: {}", hex::encode(tx.txid()));
+    println!("  Claim description: {:?}", tx.claim_description);
+    println!("  MASP mint description: {:?}", tx.masp_mint_description);
+    println!("  Equivalence description: {:?}", tx.equivalence_description);
+    println!("  Spend authorization signature: {:?}", tx.spend_auth_signature);
+    println!("  Binding signature: {:?}", tx.binding_signature);
+    Ok(())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+fn print_schedule<'a>(slots: impl Iterator<Item = &'a ScheduledSlot>) {
+    for slot in slots {
+        match &slot.claim {
+            Some(claim) => {
+                let account = claim.account.map_or_else(|| "-".to_string(), |account| account.to_string());
+                println!("{:<12} claim {} account {}", slot.not_before, claim.tx_hash, account);
+            }
+            None => println!("{:<12} decoy", slot.not_before),
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize logging
     tracing_subscriber::fmt::init();
-    
+
     let cli = Cli::parse();
-    
-    // Load configuration
-    let config_path = cli.config;
-    // TODO: Implement config loading
-    let _config = match config_path {
-        Some(ref _path) => {
-            // TODO: Load config from file
-            println!("Config loading not yet implemented");
-        }
-        None => {
-            // TODO: Use default config
-            println!("Default config not yet implemented");
-        }
-    };
-    
-    info!("Starting ZEC-NAM wallet with config: {:?}", config_path);
-    
+
+    // Load configuration and resolve the selected wallet
+    let mut config = Config::load(cli.config.as_deref())?;
+    config.select_wallet(cli.wallet.as_deref())?;
+    let account = cli.account;
+    if account.is_some() && !cli.command.takes_account() {
+        anyhow::bail!("--account is not supported by this command, which acts on every account");
+    }
+
+    info!("Starting ZEC-NAM wallet with config: {:?}", cli.config);
+
     match cli.command {
-        Commands::InitWallet { name, network } => {
+        Commands::InitWallet { name, network, restore, ufvk } => {
             info!("Initializing wallet: {:?} on network: {:?}", name, network);
-            // TODO: Implement wallet initialization
-            println!("Wallet initialization not yet implemented");
+
+            if config.wallet_path.exists() {
+                anyhow::bail!("Wallet already exists at {}", config.wallet_path.display());
+            }
+
+            let name = name.or(cli.wallet).unwrap_or_else(|| "default".to_string());
+            let network = network.unwrap_or_else(|| config.network.zcash_network.clone());
+            let passphrase = read_passphrase("New wallet passphrase", PASSPHRASE_ENV)?;
+            if passphrase.is_empty() {
+                anyhow::bail!("Wallet passphrase must not be empty");
+            }
+
+            if let Some(parent) = config.wallet_path.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create wallet directory: {}", parent.display()))?;
+            }
+
+            if let Some(ufvk) = ufvk {
+                let mut wallet = AirdropWallet::create(wallet_store(&config)?, &name, &network, &passphrase)?;
+                wallet.import_viewing_key(&ufvk)?;
+                println!("Initialized watch-only wallet '{}' on {} at {}", name, network, config.wallet_path.display());
+                return Ok(());
+            }
+
+            let seed = if restore {
+                WalletSeed::from_phrase(&read_passphrase("Seed phrase", MNEMONIC_ENV)?)?
+            } else {
+                WalletSeed::generate()
+            };
+
+            let mut wallet = AirdropWallet::create(wallet_store(&config)?, &name, &network, &passphrase)?;
+            wallet.import_seed(&seed)?;
+
+            println!("Initialized wallet '{}' on {} at {}", name, network, config.wallet_path.display());
+            if !restore {
+                println!();
+                println!("Write down this seed phrase and keep it offline. It is the only way");
+                println!("to recover the wallet's keys and will not be shown again:");
+                println!();
+                println!("  {}", seed.phrase().as_str());
+            }
+        }
+        Commands::ListWallets => {
+            info!("Listing wallets");
+
+            let default = config.named_wallet_path(None)?;
+            if default.exists() {
+                println!("(default) {}", default.display());
+            }
+            for name in config.wallet_names()? {
+                println!("{:<9} {}", name, config.named_wallet_path(Some(&name))?.display());
+            }
+        }
+        Commands::AddAccount => {
+            info!("Adding account");
+
+            let mut wallet = open_wallet(&config)?;
+            let account = wallet.add_account()?;
+
+            println!("Added account {}", account);
+        }
+        Commands::ExportViewingKey => {
+            let account = account.unwrap_or(0);
+            info!("Exporting viewing key of account {}", account);
+
+            let wallet = open_wallet(&config)?;
+            let network = consensus_network(&wallet.get_metadata().network).network_type();
+
+            println!("{}", wallet.viewing_account(account)?.to_ufvk(network)?);
         }
         Commands::ShowStatus => {
             info!("Showing wallet status");
-            // TODO: Implement status display
-            println!("Wallet status display not yet implemented");
+
+            let wallet = open_wallet(&config)?;
+            let metadata = wallet.get_metadata();
+
+            println!("Wallet: {}", metadata.name);
+            println!("  Network: {}", metadata.network);
+            println!("  Version: {}", metadata.version);
+            println!("  Schema version: {}", wallet.schema_version()?);
+            println!("  Last sync: {}", metadata.last_sync);
+            println!("  Accounts: {:?}", wallet.accounts());
+            if wallet.is_watch_only() {
+                println!("  Watch-only: claims must be signed by the spending key holder");
+            }
+            match account {
+                Some(account) => {
+                    println!("  Account {}:", account);
+                    print_balance(&wallet.account_balance(account)?, "    ");
+                }
+                None => {
+                    print_balance(&wallet.get_balance()?, "  ");
+                    for account in wallet.accounts() {
+                        println!("  Account {}:", account);
+                        print_balance(&wallet.account_balance(account)?, "    ");
+                    }
+                }
+            }
         }
-        Commands::ImportNotes { file, format } => {
+        Commands::ChangePassphrase => {
+            info!("Changing wallet passphrase");
+
+            if !config.wallet_path.exists() {
+                anyhow::bail!("No wallet found at {}", config.wallet_path.display());
+            }
+            let mut wallet = AirdropWallet::open_store(wallet_store(&config)?)?;
+            let old_passphrase = read_passphrase("Current passphrase", PASSPHRASE_ENV)?;
+            let new_passphrase = read_passphrase("New passphrase", NEW_PASSPHRASE_ENV)?;
+            if new_passphrase.is_empty() {
+                anyhow::bail!("Wallet passphrase must not be empty");
+            }
+            wallet.change_passphrase(&old_passphrase, &new_passphrase)?;
+
+            println!("Wallet passphrase changed");
+        }
+        Commands::ImportNotes { file, format, keys, blocks } => {
             info!("Importing notes from file: {} with format: {:?}", file.display(), format);
-            // TODO: Implement note import
-            println!("Note import not yet implemented");
+            import_notes(&config, &file, format.as_deref(), keys.as_deref(), &blocks)?;
         }
-        Commands::ListNotes { min_value, note_type } => {
+        Commands::ListNotes { min_value, note_type, status, eligible, unspent, sort, descending, offset, limit } => {
             info!("Listing notes with min_value: {:?}, note_type: {:?}", min_value, note_type);
-            // TODO: Implement note listing
-            println!("Note listing not yet implemented");
+
+            let query = NoteQuery {
+                account,
+                min_value,
+                pool: note_type.as_deref().map(str::parse::<Pool>).transpose()?,
+                claim_state: status,
+                unspent,
+                eligible,
+                sort,
+                descending,
+                offset,
+                limit,
+            };
+            let wallet = open_wallet(&config)?;
+            let page = wallet.query_notes(&query)?;
+
+            for note in &page.notes {
+                println!(
+                    "{:<18} value {:<16} {:<8} {:<9} airdrop nullifier {}",
+                    note.id().to_string(),
+                    note.value,
+                    if note.status.is_spent { "spent" } else { "unspent" },
+                    note.status.claim_state(),
+                    note.airdrop_nullifier
+                );
+            }
+            if page.notes.len() < page.total {
+                println!("Showing {} of {} notes from offset {}", page.notes.len(), page.total, offset);
+            }
+        }
+        Commands::ListClaims { status } => {
+            info!("Listing claims with status: {:?}", status);
+
+            let wallet = open_wallet(&config)?;
+            let claims = match account {
+                Some(account) => wallet.account_claims(account, status)?,
+                None => wallet.claims(status)?,
+            };
+            for claim in claims {
+                let note = claim.note.map_or_else(|| "-".to_string(), |id| id.to_string());
+                print!("{} {:<10} note {:<12} amount {:<16}", claim.tx_hash, claim.status, note, claim.amount);
+                if let Some(height) = claim.block_height {
+                    print!(" height {}", height);
+                }
+                if let Some(reason) = &claim.failure {
+                    print!(" ({})", reason);
+                }
+                println!();
+            }
+        }
+        Commands::EligibilityReport { json, eligible_only } => {
+            info!("Building eligibility report");
+
+            let wallet = open_wallet(&config)?;
+            let mut report = wallet.eligibility_report(&config.airdrop.eligibility_rules(), account)?;
+            let total = report.notes.len();
+            if eligible_only {
+                report.notes.retain(|note| note.eligible);
+            }
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+                return Ok(());
+            }
+
+            let height = |height: Option<u64>| height.map_or_else(|| "-".to_string(), |height| height.to_string());
+            println!("Snapshot height {}, wallet scanned to {}", height(report.snapshot_height), height(report.scanned_height));
+            for note in &report.notes {
+                println!(
+                    "{:<18} account {:<4} value {:<16} {:<12} mints {}",
+                    note.note.to_string(),
+                    note.account.map_or_else(|| "-".to_string(), |account| account.to_string()),
+                    note.value,
+                    if note.eligible { "eligible" } else { "not eligible" },
+                    note.expected_amount
+                );
+                for check in &note.checks {
+                    println!("  {:<7} {:<15} {}", check.outcome, check.check, check.detail);
+                }
+            }
+            println!(
+                "{} of {} notes eligible, minting {}",
+                report.eligible().count(), total, report.eligible_amount
+            );
+        }
+        Commands::CreateAirdrop { note, amount, recipient, out_file, force_release } => {
+            info!("Creating airdrop transaction for note {}", note);
+            create_airdrop(&config, account, note, amount, &recipient, &out_file, force_release)?;
+        }
+        Commands::PlanClaims { strategy, target, max_claims, min_value, execute, recipient, batch, out_dir } => {
+            info!("Planning claims with strategy {}", strategy);
+
+            let options = PlanOptions { strategy, account, min_value, target, max_claims };
+            let mut wallet = open_wallet(&config)?;
+            let plan = wallet.plan_claims(&options)?;
+
+            println!(
+                "{} plan: {} claims in {} batches, {} zatoshis claimed for {} minted",
+                plan.strategy, plan.claims.len(), plan.batches(), plan.total_value, plan.total_amount
+            );
+            for claim in &plan.claims {
+                println!("  batch {:<4} {:<18} value {:<16} mints {}", claim.batch, claim.id().to_string(), claim.note.value, claim.amount);
+            }
+
+            if let Some(recipient) = recipient.filter(|_| execute) {
+                let recipient = parse_recipient(&recipient)?;
+                let txs = wallet.execute_plan(&plan, batch, &recipient)?;
+                fs::create_dir_all(&out_dir)
+                    .with_context(|| format!("Failed to create output directory: {}", out_dir.display()))?;
+                for (claim, tx) in plan.claims_in(batch).zip(&txs) {
+                    let out_file = out_dir.join(format!("claim_{}.bin", claim.id().key()));
+                    fs::write(&out_file, tx.to_bytes()?)
+                        .with_context(|| format!("Failed to write transaction file: {}", out_file.display()))?;
+                    println!("Built claim {} of note {}, written to {}", hex::encode(tx.txid()), claim.id(), out_file.display());
+                }
+                if config.scheduling.enabled {
+                    let slots = wallet.schedule_claims(&txs, &config.scheduling, unix_now(), &mut rand::thread_rng())?;
+                    print_schedule(slots.iter());
+                }
+            }
+        }
+        Commands::ScheduleClaims { tx_files } => {
+            info!("Scheduling {} claims", tx_files.len());
+
+            let mut txs = Vec::new();
+            for tx_file in &tx_files {
+                let data = fs::read(tx_file)
+                    .with_context(|| format!("Failed to read transaction file: {}", tx_file.display()))?;
+                txs.push(ShieldedAirdropTransaction::from_bytes(&data)?);
+            }
+            let mut wallet = open_wallet(&config)?;
+            for tx in &txs {
+                ensure_claim_account(&wallet, tx, account)?;
+            }
+            let slots = wallet.schedule_claims(&txs, &config.scheduling, unix_now(), &mut rand::thread_rng())?;
+            print_schedule(slots.iter());
+        }
+        Commands::ShowSchedule => {
+            info!("Showing claim schedule");
+
+            let wallet = open_wallet(&config)?;
+            let mut schedule = wallet.schedule()?;
+            // Decoys belong to no account
+            if let Some(account) = account {
+                schedule.retain(|(_, slot)| slot.claim.as_ref().is_some_and(|claim| claim.account == Some(account)));
+            }
+            if schedule.is_empty() {
+                println!("No claims scheduled");
+            }
+            print_schedule(schedule.iter().map(|(_, slot)| slot));
+        }
+        Commands::RunSchedule => {
+            info!("Running claim schedule");
+
+            let mut wallet = open_wallet(&config)?;
+            let mut node = namada_client(&config)?;
+            let run = match &config.scheduling.relay_queue {
+                Some(dir) => {
+                    let queue = RelayQueue::open(dir)?;
+                    wallet.run_schedule(&mut RelayClient::new(&node, &queue), &config.scheduling, unix_now())?
+                }
+                None => wallet.run_schedule(&mut node, &config.scheduling, unix_now())?,
+            };
+
+            for (tx_hash, status) in &run.submitted {
+                println!("Claim {} is now {}", tx_hash, status);
+            }
+            println!("Submitted {} claims", run.submitted.len());
+            println!("  Postponed to a later block: {}", run.postponed);
+            println!("  Decoy queries: {}", run.decoys);
+            println!("  Dropped, no longer pending: {}", run.dropped);
+        }
+        Commands::RelayFlush { min_batch } => {
+            info!("Flushing relay queue");
+
+            let dir = config.scheduling.relay_queue.as_ref()
+                .ok_or_else(|| anyhow::anyhow!("No relay queue configured; set scheduling.relay_queue"))?;
+            let queue = RelayQueue::open(dir)?;
+            let mut node = namada_client(&config)?;
+            let min_batch = min_batch.unwrap_or(config.scheduling.min_relay_batch);
+            let waiting = queue.len()?;
+            if waiting < min_batch.max(1) {
+                println!("{} claims queued; waiting for {}", waiting, min_batch.max(1));
+                return Ok(());
+            }
+            let flush = queue.flush(&mut node, min_batch, config.scheduling.expiry_blocks, &mut rand::thread_rng())?;
+
+            println!("Submitted {} claims", flush.submitted);
+            println!("  Postponed to a later block: {}", flush.postponed);
+            for (tx_hash, reason) in &flush.rejected {
+                println!("  Rejected {}: {}", tx_hash, reason);
+            }
         }
-        Commands::CreateAirdrop { note_index, amount, recipient, note_type } => {
-            info!("Creating airdrop transaction");
-            // TODO: Implement airdrop creation
-            println!("Airdrop creation not yet implemented");
+        Commands::PrepareAirdrop { note, amount, recipient, out_file } => {
+            info!("Preparing unsigned airdrop claim for note {}", note);
+
+            let recipient = parse_recipient(&recipient)?;
+            let wallet = open_wallet(&config)?;
+            ensure_note_account(&wallet, note, account)?;
+            let partial = wallet.prepare_airdrop_tx(note, amount, &recipient)?;
+            write_partial(&out_file, &partial)?;
+
+            println!("Prepared unsigned claim, written to {}", out_file.display());
+            print_claim(&partial);
+        }
+        Commands::SignAirdrop { in_file, out_file } => {
+            info!("Signing airdrop claim from file: {}", in_file.display());
+
+            let mut partial = read_partial(&in_file)?;
+            let wallet = open_wallet(&config)?;
+            let signer = wallet.sign_airdrop_tx(&mut partial)?;
+            if let Some(account) = account.filter(|account| *account != signer) {
+                anyhow::bail!("Claim is authorized by account {}, not account {}", signer, account);
+            }
+            write_partial(&out_file, &partial)?;
+
+            println!("Signed claim with account {}, written to {}", signer, out_file.display());
+            print_claim(&partial);
+        }
+        Commands::FinalizeAirdrop { in_file, out_file } => {
+            info!("Finalizing airdrop claim from file: {}", in_file.display());
+
+            let partial = read_partial(&in_file)?;
+            let mut wallet = open_wallet(&config)?;
+            let tx = wallet.finalize_airdrop_tx(partial)?;
+            let txid = hex::encode(tx.txid());
+
+            fs::write(&out_file, tx.to_bytes()?)
+                .with_context(|| format!("Failed to write transaction file: {}", out_file.display()))?;
+
+            println!("Finalized {} airdrop transaction {}", tx.claim_description.pool(), txid);
+            println!("  Written to: {}", out_file.display());
         }
-        Commands::SubmitAirdrop { tx_file } => {
+        Commands::SubmitAirdrop { tx_file, expiry_blocks } => {
             info!("Submitting airdrop transaction from file: {}", tx_file.display());
-            // TODO: Implement airdrop submission
-            println!("Airdrop submission not yet implemented");
+
+            let tx = read_transaction(&tx_file)?;
+            let tx_hash = hex::encode(tx.txid());
+            let mut wallet = open_wallet(&config)?;
+            ensure_claim_account(&wallet, &tx, account)?;
+            let mut client = namada_client(&config)?;
+            let expiry_blocks = expiry_blocks.unwrap_or(config.scheduling.expiry_blocks);
+            match wallet.broadcast_claim(&mut client, &tx, expiry_blocks)? {
+                ClaimStatus::Failed => {
+                    let reason = wallet.claim(&tx_hash)?.and_then(|claim| claim.failure).unwrap_or_default();
+                    anyhow::bail!("Claim {} rejected by the node: {}", tx_hash, reason);
+                }
+                status => println!("Claim {} is now {}", tx_hash, status),
+            }
+        }
+        Commands::SyncClaims => {
+            info!("Syncing broadcast claims with the Namada node");
+
+            let mut wallet = open_wallet(&config)?;
+            let client = namada_client(&config)?;
+            let updated = wallet.sync_claims(&client)?;
+            if updated.is_empty() {
+                println!("No claim changed status");
+            }
+            for claim in updated {
+                print!("{} {}", claim.tx_hash, claim.status);
+                if let Some(height) = claim.block_height {
+                    print!(" at height {}", height);
+                }
+                if let Some(reason) = &claim.failure {
+                    print!(" ({})", reason);
+                }
+                println!();
+            }
         }
-        Commands::VerifyAirdrop { tx_file } => {
+        Commands::VerifyAirdrop { tx_file, registry } => {
             info!("Verifying airdrop transaction from file: {}", tx_file.display());
-            
-            let data = fs::read(&tx_file)
-                .with_context(|| format!("Failed to read transaction file: {}", tx_file.display()))?;
-            
-            let tx: ShieldedAirdropTransaction = bincode::deserialize(&data)
-                .with_context(|| "Failed to deserialize transaction")?;
-            
-            // TODO: Implement actual verification logic
-            println!("Transaction verification not yet implemented");
+            verify_airdrop(&config, &tx_file, registry.as_deref())?;
         }
         Commands::ShowTransaction { tx_file } => {
             info!("Showing transaction from file: {}", tx_file.display());
-            
-            let data = fs::read(&tx_file)
-                .with_context(|| format!("Failed to read transaction file: {}", tx_file.display()))?;
-            
-            let tx: ShieldedAirdropTransaction = bincode::deserialize(&data)
-                .with_context(|| "Failed to deserialize transaction")?;
-            
-            println!("Transaction details:");
-            println!("  Claim description: {:?}", tx.claim_description);
-            println!("  MASP mint description: {:?}", tx.masp_mint_description);
-            println!("  Equivalence description: {:?}", tx.equivalence_description);
-            println!("  Binding signature: {:?}", tx.binding_signature);
-        }
-        Commands::SyncWallet => {
-            info!("Syncing wallet");
-            // TODO: Implement wallet sync
-            println!("Wallet sync not yet implemented");
+            show_transaction(&tx_file)?;
+        }
+        Commands::SyncWallet { blocks } => {
+            info!("Syncing wallet from {}", blocks.display());
+
+            let blocks = read_compact_blocks(&blocks)?;
+            let mut wallet = open_wallet(&config)?;
+            let summary = wallet.scan_blocks(&blocks, config.airdrop.snapshot_height)?;
+
+            println!("Scanned {} blocks", summary.blocks_scanned);
+            println!("  New Sapling notes: {}", summary.sapling_notes);
+            println!("  New Orchard notes: {}", summary.orchard_notes);
+            println!("  Spends detected: {}", summary.spends);
+            match summary.last_height {
+                Some(height) => println!("  Synced to height: {}", height),
+                None => println!("  No blocks scanned yet"),
+            }
+        }
+        Commands::Rescan { blocks, from_height } => {
+            info!("Rescanning wallet from height {} with {}", from_height, blocks.display());
+
+            let blocks = read_compact_blocks(&blocks)?;
+            let mut wallet = open_wallet(&config)?;
+            let summary = wallet.rescan(&blocks, from_height, config.airdrop.snapshot_height)?;
+
+            println!("Rewound to block {}", from_height);
+            println!("  Notes dropped: {}", summary.notes_removed);
+            println!("  Spent flags cleared: {}", summary.notes_reset);
+            println!("Scanned {} blocks", summary.scan.blocks_scanned);
+            println!("  New Sapling notes: {}", summary.scan.sapling_notes);
+            println!("  New Orchard notes: {}", summary.scan.orchard_notes);
+            println!("  Spends detected: {}", summary.scan.spends);
+            if let Some(height) = summary.scan.last_height {
+                println!("  Synced to height: {}", height);
+            }
+        }
+        Commands::CheckWallet { repair } => {
+            info!("Checking wallet consistency");
+
+            let mut wallet = open_wallet(&config)?;
+            let found = if repair { wallet.repair()? } else { wallet.check_consistency()? };
+            for discrepancy in &found {
+                println!("{}", discrepancy);
+            }
+            match (found.len(), repair) {
+                (0, _) => println!("No discrepancies found"),
+                (count, true) => println!("Repaired {} discrepancies", count),
+                (count, false) => println!("Found {} discrepancies; run with --repair to fix them", count),
+            }
         }
         Commands::NetworkStatus => {
             info!("Checking network status");
@@ -225,33 +1098,115 @@ async fn main() -> Result<()> {
         }
         Commands::ExportWallet { file, format } => {
             info!("Exporting wallet to file: {} with format: {:?}", file.display(), format);
-            // TODO: Implement wallet export
-            println!("Wallet export not yet implemented");
+
+            let wallet = open_wallet(&config)?;
+            let passphrase = match format {
+                ExportFormat::Encrypted => {
+                    let passphrase = read_passphrase("Export passphrase", EXPORT_PASSPHRASE_ENV)?;
+                    if passphrase.is_empty() {
+                        anyhow::bail!("Export passphrase must not be empty");
+                    }
+                    Some(passphrase)
+                }
+                _ => None,
+            };
+            let bytes = wallet.export_data()?.encode(format, passphrase.as_deref())?;
+            fs::write(&file, bytes)
+                .with_context(|| format!("Failed to write export file: {}", file.display()))?;
+
+            println!("Exported wallet to {} ({})", file.display(), format);
+        }
+        Commands::ImportWallet { file, merge } => {
+            info!("Importing wallet from file: {} (merge: {})", file.display(), merge);
+
+            let bytes = fs::read(&file)
+                .with_context(|| format!("Failed to read export file: {}", file.display()))?;
+            let passphrase = match ExportFormat::detect(&bytes) {
+                ExportFormat::Encrypted => Some(read_passphrase("Export passphrase", EXPORT_PASSPHRASE_ENV)?),
+                _ => None,
+            };
+            let export = WalletExport::decode(&bytes, passphrase.as_deref())
+                .with_context(|| format!("Failed to read export file: {}", file.display()))?;
+
+            let mut wallet = open_wallet(&config)?;
+            let mode = if merge { ImportMode::Merge } else { ImportMode::Replace };
+            let summary = wallet.import_data(export, mode)?;
+
+            println!("Imported {} notes and {} claims from {}", summary.notes, summary.claims, file.display());
+            if summary.duplicates > 0 {
+                println!("  {} notes were already in the wallet", summary.duplicates);
+            }
         }
         Commands::GenerateTestData { count } => {
             info!("Generating test data with count: {:?}", count);
-            // TODO: Implement test data generation
-            println!("Test data generation not yet implemented");
+
+            let mut wallet = open_wallet(&config)?;
+            let count = count.unwrap_or(10);
+            let next_position = |positions: Vec<u64>| positions.into_iter().max().map_or(0, |p| p + 1);
+
+            for i in 0..count {
+                let value = rand::random::<u64>() % 10_000_000 + 10_000;
+                if i % 2 == 0 {
+                    let position = next_position(wallet.core().sapling_notes.iter().map(|n| n.position).collect());
+                    wallet.add_sapling_note(SaplingNote {
+                        diversifier: rand::random(),
+                        value,
+                        note_commitment: NoteCommitment(rand::random()),
+                        nullifier_key: Scalar(rand::random()),
+                        randomness: Scalar(rand::random()),
+                        position,
+                    })?;
+                } else {
+                    let position = next_position(wallet.core().orchard_notes.iter().map(|n| n.position).collect());
+                    wallet.add_orchard_note(OrchardNote {
+                        diversifier: rand::random(),
+                        value,
+                        note_commitment: NoteCommitment(rand::random()),
+                        nullifier_key: Scalar(rand::random()),
+                        randomness: Scalar(rand::random()),
+                        position,
+                        rho: FieldElement(rand::random()),
+                        psi: FieldElement(rand::random()),
+                    })?;
+                }
+            }
+
+            println!("Generated {} test notes", count);
         }
-        Commands::CreateMaspAirdrop { note_index, amount, masp_recipient, note_type, out_file } => {
-            info!("Creating MASP airdrop transaction");
-            // TODO: Implement MASP airdrop creation
-            println!("MASP airdrop creation not yet implemented");
+        Commands::CreateMaspAirdrop { note, amount, masp_recipient, out_file, force_release } => {
+            info!("Creating MASP airdrop transaction for note {}", note);
+            create_airdrop(&config, account, note, amount, &masp_recipient, &out_file, force_release)?;
         }
         Commands::VerifyMaspAirdrop { tx_file } => {
             info!("Verifying MASP airdrop transaction from file: {}", tx_file.display());
-            // TODO: Implement MASP airdrop verification
-            println!("MASP airdrop verification not yet implemented");
+            verify_airdrop(&config, &tx_file, None)?;
         }
         Commands::ShowMaspAirdropTx { tx_file } => {
             info!("Showing MASP airdrop transaction from file: {}", tx_file.display());
-            // TODO: Implement MASP airdrop transaction display
-            println!("MASP airdrop transaction display not yet implemented");
+            show_transaction(&tx_file)?;
         }
     }
-    
+
     Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
 
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
 
+    #[test]
+    fn test_account_is_refused_by_whole_wallet_commands() {
+        let parse = |args: &[&str]| Cli::try_parse_from(args).unwrap().command;
+        assert!(parse(&["zec-nam", "--account", "1", "list-notes"]).takes_account());
+        assert!(parse(&["zec-nam", "sign-airdrop", "-i", "claim.pczt", "-o", "signed.pczt", "--account", "1"]).takes_account());
+        assert!(!parse(&["zec-nam", "--account", "1", "sync-wallet", "--blocks", "blocks.bin"]).takes_account());
+        assert!(!parse(&["zec-nam", "--account", "1", "export-wallet", "--file", "wallet.json"]).takes_account());
+        assert!(!parse(&["zec-nam", "--account", "1", "add-account"]).takes_account());
+    }
+}
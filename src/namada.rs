@@ -0,0 +1,292 @@
+//! Submission of airdrop claims to the Namada chain.
+//!
+//! The wallet only needs three things from a Namada node: its latest block
+//! height, a way to broadcast a claim, and whether a broadcast claim was
+//! included or rejected. [`NamadaClient`] captures exactly that so claim
+//! tracking can be driven by [`RpcNamadaClient`], which talks to a node's RPC
+//! endpoint, or by [`LocalNamadaChain`], an in-process stand-in that
+//! validates claims like a verifier would.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use base64::Engine;
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::{
+    AirdropPolicy, ConsumedNullifier, MemoryNullifierRegistry, NullifierRegistry,
+    ShieldedAirdropTransaction,
+};
+
+/// Errors returned by a [`NamadaClient`]
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum NamadaError {
+    /// The node refused the claim; it will never be included
+    #[error("claim rejected: {0}")]
+    Rejected(String),
+
+    /// The node could not be reached; the request may be retried
+    #[error("node unavailable: {0}")]
+    Unavailable(String),
+}
+
+/// What the chain knows about a broadcast claim
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainStatus {
+    /// Not (yet) included in a block
+    Unknown,
+    Included { block_height: u64 },
+    Rejected { reason: String },
+}
+
+/// Connection to a Namada node
+pub trait NamadaClient {
+    fn latest_height(&self) -> Result<u64, NamadaError>;
+
+    /// Submit a claim to the node's mempool
+    fn broadcast(&mut self, tx: &ShieldedAirdropTransaction) -> Result<(), NamadaError>;
+
+    /// Submit a claim of `owner`, an opaque tag shared by the claims that
+    /// must not be submitted at the same height. A node has no use for it;
+    /// a relay submitting the claims of several wallets does.
+    fn broadcast_owned(
+        &mut self,
+        tx: &ShieldedAirdropTransaction,
+        _owner: Option<[u8; 32]>,
+    ) -> Result<(), NamadaError> {
+        self.broadcast(tx)
+    }
+
+    /// Status of the claim with transaction ID `txid`
+    fn claim_status(&self, txid: &[u8; 32]) -> Result<ChainStatus, NamadaError>;
+}
+
+/// In-process chain that validates claims on broadcast and includes them
+/// when a block is produced
+#[derive(Debug, Default)]
+pub struct LocalNamadaChain {
+    height: u64,
+    policy: AirdropPolicy,
+    registry: MemoryNullifierRegistry,
+    mempool: Vec<ShieldedAirdropTransaction>,
+    statuses: HashMap<[u8; 32], ChainStatus>,
+}
+
+impl LocalNamadaChain {
+    pub fn new(policy: AirdropPolicy) -> Self {
+        Self { policy, ..Self::default() }
+    }
+
+    /// Produce a block including every valid claim in the mempool; claims
+    /// that became invalid since broadcast (e.g. a double claim within the
+    /// block) are rejected
+    pub fn produce_block(&mut self) -> u64 {
+        self.height += 1;
+        for tx in std::mem::take(&mut self.mempool) {
+            let status = match tx.validate(&self.registry, &self.policy).and_then(|()| {
+                self.registry.insert(
+                    tx.get_airdrop_nullifier(),
+                    ConsumedNullifier { block_height: self.height, txid: tx.txid() },
+                )
+            }) {
+                Ok(()) => ChainStatus::Included { block_height: self.height },
+                Err(e) => ChainStatus::Rejected { reason: e.to_string() },
+            };
+            self.statuses.insert(tx.txid(), status);
+        }
+        self.height
+    }
+
+    /// Drop a claim from the mempool without including it, as a node does
+    /// when the mempool is full or the claim times out
+    pub fn evict(&mut self, txid: &[u8; 32]) {
+        self.mempool.retain(|tx| tx.txid() != *txid);
+    }
+}
+
+impl NamadaClient for LocalNamadaChain {
+    fn latest_height(&self) -> Result<u64, NamadaError> {
+        Ok(self.height)
+    }
+
+    fn broadcast(&mut self, tx: &ShieldedAirdropTransaction) -> Result<(), NamadaError> {
+        tx.validate(&self.registry, &self.policy)
+            .map_err(|e| NamadaError::Rejected(e.to_string()))?;
+        self.mempool.push(tx.clone());
+        Ok(())
+    }
+
+    fn claim_status(&self, txid: &[u8; 32]) -> Result<ChainStatus, NamadaError> {
+        Ok(self.statuses.get(txid).cloned().unwrap_or(ChainStatus::Unknown))
+    }
+}
+
+/// Client for the CometBFT JSON-RPC endpoint of a Namada node.
+///
+/// Claims are broadcast with `broadcast_tx_sync`, so a claim the node's
+/// mempool check refuses is reported as rejected straight away. Inclusion is
+/// looked up with `tx_search` on the `airdrop.txid` attribute of the event
+/// the node emits for every processed claim.
+///
+/// The trait is synchronous while requests are async, so calls block the
+/// current thread of a multi-threaded tokio runtime until the node answers.
+#[derive(Debug, Clone)]
+pub struct RpcNamadaClient {
+    url: String,
+    http: reqwest::Client,
+}
+
+impl RpcNamadaClient {
+    pub fn new(url: &str, timeout: Duration) -> Result<Self, NamadaError> {
+        let http = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| NamadaError::Unavailable(e.to_string()))?;
+        Ok(Self { url: url.to_string(), http })
+    }
+
+    /// Call `method` and return the `result` of the response
+    fn call(&self, method: &str, params: Value) -> Result<Value, NamadaError> {
+        let request = self.http.post(&self.url).json(&json!({
+            "jsonrpc": "2.0",
+            "id": 0,
+            "method": method,
+            "params": params,
+        }));
+        let response: Value = tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(async {
+                request.send().await?.error_for_status()?.json().await
+            })
+        })
+        .map_err(|e| NamadaError::Unavailable(format!("{}: {}", self.url, e)))?;
+        rpc_result(response)
+    }
+}
+
+impl NamadaClient for RpcNamadaClient {
+    fn latest_height(&self) -> Result<u64, NamadaError> {
+        parse_height(&self.call("status", json!({}))?)
+    }
+
+    fn broadcast(&mut self, tx: &ShieldedAirdropTransaction) -> Result<(), NamadaError> {
+        let bytes = tx.to_bytes().map_err(|e| NamadaError::Rejected(e.to_string()))?;
+        let tx = base64::engine::general_purpose::STANDARD.encode(bytes);
+        parse_check_tx(&self.call("broadcast_tx_sync", json!({ "tx": tx }))?)
+    }
+
+    fn claim_status(&self, txid: &[u8; 32]) -> Result<ChainStatus, NamadaError> {
+        let query = format!("airdrop.txid='{}'", hex::encode(txid));
+        parse_claim_status(&self.call("tx_search", json!({ "query": query, "per_page": "1" }))?)
+    }
+}
+
+/// The `result` of a JSON-RPC response, or its `error` as
+/// [`NamadaError::Unavailable`] since the node did not process the request
+fn rpc_result(mut response: Value) -> Result<Value, NamadaError> {
+    if let Some(error) = response.get("error").filter(|error| !error.is_null()) {
+        let message = error.get("data").and_then(Value::as_str)
+            .or_else(|| error.get("message").and_then(Value::as_str))
+            .unwrap_or("unknown error");
+        return Err(NamadaError::Unavailable(message.to_string()));
+    }
+    match response.get_mut("result") {
+        Some(result) => Ok(result.take()),
+        None => Err(NamadaError::Unavailable("response without a result".to_string())),
+    }
+}
+
+/// CometBFT encodes 64-bit integers as strings
+fn rpc_u64(value: &Value) -> Option<u64> {
+    value.as_str().and_then(|s| s.parse().ok()).or_else(|| value.as_u64())
+}
+
+fn parse_height(status: &Value) -> Result<u64, NamadaError> {
+    rpc_u64(&status["sync_info"]["latest_block_height"])
+        .ok_or_else(|| NamadaError::Unavailable("status without a block height".to_string()))
+}
+
+/// A non-zero code from the mempool check means the claim was refused
+fn parse_check_tx(result: &Value) -> Result<(), NamadaError> {
+    match rpc_u64(&result["code"]).unwrap_or(0) {
+        0 => Ok(()),
+        code => Err(NamadaError::Rejected(rpc_log(result, code))),
+    }
+}
+
+fn parse_claim_status(search: &Value) -> Result<ChainStatus, NamadaError> {
+    let Some(tx) = search["txs"].as_array().and_then(|txs| txs.first()) else {
+        return Ok(ChainStatus::Unknown);
+    };
+    let block_height = rpc_u64(&tx["height"])
+        .ok_or_else(|| NamadaError::Unavailable("claim without a block height".to_string()))?;
+    Ok(match rpc_u64(&tx["tx_result"]["code"]).unwrap_or(0) {
+        0 => ChainStatus::Included { block_height },
+        code => ChainStatus::Rejected { reason: rpc_log(&tx["tx_result"], code) },
+    })
+}
+
+fn rpc_log(result: &Value, code: u64) -> String {
+    match result["log"].as_str().filter(|log| !log.is_empty()) {
+        Some(log) => log.to_string(),
+        None => format!("code {}", code),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AirdropWallet, NoteCommitment, NoteId, Pool, SaplingNote, Scalar};
+
+    #[test]
+    fn test_double_claim_in_one_block_is_rejected() {
+        let mut wallet = AirdropWallet::new();
+        wallet.add_sapling_note(SaplingNote {
+            diversifier: [0u8; 11],
+            value: 1000000,
+            note_commitment: NoteCommitment([1u8; 32]),
+            nullifier_key: Scalar([2u8; 32]),
+            randomness: Scalar([3u8; 32]),
+            position: 0,
+        });
+        let id = NoteId::new(Pool::Sapling, 0);
+        let first = wallet.create_airdrop_tx(id, 1000, &[4u8; 32]).unwrap();
+        let second = wallet.create_airdrop_tx(id, 2000, &[4u8; 32]).unwrap();
+
+        // Both claims are valid on their own, so both enter the mempool
+        let mut chain = LocalNamadaChain::new(AirdropPolicy::default());
+        chain.broadcast(&first).unwrap();
+        chain.broadcast(&second).unwrap();
+        assert_eq!(chain.produce_block(), 1);
+
+        assert_eq!(chain.claim_status(&first.txid()), Ok(ChainStatus::Included { block_height: 1 }));
+        assert!(matches!(chain.claim_status(&second.txid()), Ok(ChainStatus::Rejected { .. })));
+        assert!(matches!(chain.broadcast(&second), Err(NamadaError::Rejected(_))));
+    }
+
+    #[test]
+    fn test_rpc_responses_are_parsed() {
+        let status = rpc_result(json!({ "result": { "sync_info": { "latest_block_height": "1042" } } }));
+        assert_eq!(parse_height(&status.unwrap()), Ok(1042));
+        assert!(matches!(
+            rpc_result(json!({ "error": { "code": -32603, "message": "Internal error", "data": "mempool is full" } })),
+            Err(NamadaError::Unavailable(reason)) if reason == "mempool is full"
+        ));
+
+        assert_eq!(parse_check_tx(&json!({ "code": 0, "log": "" })), Ok(()));
+        assert_eq!(
+            parse_check_tx(&json!({ "code": 3, "log": "airdrop nullifier already consumed" })),
+            Err(NamadaError::Rejected("airdrop nullifier already consumed".to_string()))
+        );
+
+        assert_eq!(parse_claim_status(&json!({ "txs": [], "total_count": "0" })), Ok(ChainStatus::Unknown));
+        assert_eq!(
+            parse_claim_status(&json!({ "txs": [{ "height": "77", "tx_result": { "code": 0 } }] })),
+            Ok(ChainStatus::Included { block_height: 77 })
+        );
+        assert_eq!(
+            parse_claim_status(&json!({ "txs": [{ "height": "78", "tx_result": { "code": 1, "log": "" } }] })),
+            Ok(ChainStatus::Rejected { reason: "code 1".to_string() })
+        );
+    }
+}
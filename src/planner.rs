@@ -0,0 +1,236 @@
+//! Choosing which notes to claim.
+//!
+//! Every claim spends exactly one note, so a claim plan is an ordered list
+//! of notes with the amount each may mint under the airdrop policy. The
+//! [`SelectionStrategy`] trades airdrop value against the number of claims
+//! and against what the claims reveal: claiming largest-first links a
+//! wallet's notes by value order, so the privacy strategies shuffle them and
+//! either claim them in a single batch or one per batch.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::Result;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::wallet::NoteMetadata;
+use crate::{checked_value_sum, AirdropPolicy, NoteId};
+
+/// How [`plan_claims`] picks and groups notes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SelectionStrategy {
+    /// Claim every eligible note, largest first
+    #[default]
+    MaximizeValue,
+    /// Claim the fewest notes reaching a target amount, largest first
+    MinimizeTransactions,
+    /// Claim every eligible note in one batch, in random order
+    AllAtOnce,
+    /// Claim every eligible note in random order, one per batch, so the
+    /// batches can be submitted at different times
+    Spread,
+}
+
+impl SelectionStrategy {
+    pub const ALL: [SelectionStrategy; 4] = [
+        SelectionStrategy::MaximizeValue,
+        SelectionStrategy::MinimizeTransactions,
+        SelectionStrategy::AllAtOnce,
+        SelectionStrategy::Spread,
+    ];
+}
+
+impl fmt::Display for SelectionStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SelectionStrategy::MaximizeValue => "max-value",
+            SelectionStrategy::MinimizeTransactions => "min-transactions",
+            SelectionStrategy::AllAtOnce => "all-at-once",
+            SelectionStrategy::Spread => "spread",
+        };
+        f.pad(name)
+    }
+}
+
+impl FromStr for SelectionStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|strategy| strategy.to_string() == s)
+            .ok_or_else(|| anyhow::anyhow!(
+                "Unknown selection strategy '{}'; expected 'max-value', 'min-transactions', 'all-at-once' or 'spread'", s
+            ))
+    }
+}
+
+/// What a claim plan is built for
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlanOptions {
+    pub strategy: SelectionStrategy,
+    /// Only notes of this ZIP-32 account
+    pub account: Option<u32>,
+    /// Smallest note worth claiming
+    pub min_value: u64,
+    /// Airdrop amount to stop at; everything eligible when absent
+    pub target: Option<u64>,
+    /// Most claims to plan
+    pub max_claims: Option<usize>,
+}
+
+/// One claim of a plan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedClaim {
+    pub note: NoteMetadata,
+    /// Largest amount the note may mint under the airdrop policy
+    pub amount: u64,
+    /// Claims of the same batch are meant to be submitted together
+    pub batch: usize,
+}
+
+impl PlannedClaim {
+    pub fn id(&self) -> NoteId {
+        self.note.id()
+    }
+}
+
+/// Claims chosen by [`plan_claims`], in the order to build them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClaimPlan {
+    pub strategy: SelectionStrategy,
+    pub claims: Vec<PlannedClaim>,
+    /// Value of the claimed notes
+    pub total_value: u64,
+    /// Airdrop amount minted by all the claims
+    pub total_amount: u64,
+}
+
+impl ClaimPlan {
+    pub fn batches(&self) -> usize {
+        self.claims.iter().map(|claim| claim.batch + 1).max().unwrap_or(0)
+    }
+
+    /// Claims of `batch`, or every claim when none is given
+    pub fn claims_in(&self, batch: Option<usize>) -> impl Iterator<Item = &PlannedClaim> {
+        self.claims
+            .iter()
+            .filter(move |claim| batch.is_none_or(|batch| claim.batch == batch))
+    }
+}
+
+/// Pick which of the eligible `notes` to claim under `options`.
+///
+/// Notes below `min_value`, of another account, or too small to mint
+/// anything under `policy` are left out. `rng` orders the privacy
+/// strategies' claims.
+pub fn plan_claims(
+    notes: Vec<NoteMetadata>,
+    policy: &AirdropPolicy,
+    options: &PlanOptions,
+    rng: &mut impl Rng,
+) -> Result<ClaimPlan> {
+    if options.strategy == SelectionStrategy::MinimizeTransactions && options.target.is_none() {
+        anyhow::bail!("The {} strategy needs a target amount", options.strategy);
+    }
+
+    let mut candidates = Vec::new();
+    for note in notes {
+        if note.value < options.min_value || options.account.is_some_and(|account| note.account != Some(account)) {
+            continue;
+        }
+        let amount = policy.max_mint(note.value)?;
+        if amount > 0 {
+            candidates.push(PlannedClaim { note, amount, batch: 0 });
+        }
+    }
+
+    match options.strategy {
+        SelectionStrategy::MaximizeValue | SelectionStrategy::MinimizeTransactions => {
+            candidates.sort_by(|a, b| b.amount.cmp(&a.amount).then(a.id().cmp(&b.id())));
+        }
+        SelectionStrategy::AllAtOnce | SelectionStrategy::Spread => candidates.shuffle(rng),
+    }
+
+    let mut claims = Vec::new();
+    let mut total_amount = 0;
+    for mut claim in candidates {
+        if options.target.is_some_and(|target| total_amount >= target)
+            || options.max_claims.is_some_and(|max| claims.len() >= max)
+        {
+            break;
+        }
+        if options.strategy == SelectionStrategy::Spread {
+            claim.batch = claims.len();
+        }
+        total_amount = checked_value_sum([total_amount, claim.amount])?;
+        claims.push(claim);
+    }
+
+    Ok(ClaimPlan {
+        strategy: options.strategy,
+        total_value: checked_value_sum(claims.iter().map(|claim| claim.note.value))?,
+        total_amount,
+        claims,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::NoteStatus;
+    use crate::{Nullifier, Pool};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn note(position: u64, value: u64, account: u32) -> NoteMetadata {
+        NoteMetadata {
+            pool: Pool::Sapling,
+            account: Some(account),
+            value,
+            position,
+            airdrop_nullifier: Nullifier([position as u8; 32]),
+            status: NoteStatus::default(),
+            created_at: 0,
+            last_used: None,
+        }
+    }
+
+    fn positions(plan: &ClaimPlan) -> Vec<u64> {
+        plan.claims.iter().map(|claim| claim.note.position).collect()
+    }
+
+    #[test]
+    fn test_strategies_pick_and_group_notes() {
+        let notes = vec![note(0, 300, 0), note(1, 5000, 0), note(2, 2000, 0), note(3, 900, 1), note(4, 1, 0)];
+        let policy = AirdropPolicy { rate_numerator: 1, rate_denominator: 2 };
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut plan = |options: PlanOptions| plan_claims(notes.clone(), &policy, &options, &mut rng).unwrap();
+
+        // Largest first; a note too small to mint anything is skipped
+        let all = plan(PlanOptions::default());
+        assert_eq!(positions(&all), [1, 2, 3, 0]);
+        assert_eq!((all.total_value, all.total_amount, all.batches()), (8200, 4100, 1));
+
+        // The fewest notes reaching the target
+        let few = plan(PlanOptions {
+            strategy: SelectionStrategy::MinimizeTransactions,
+            target: Some(3000),
+            ..PlanOptions::default()
+        });
+        assert_eq!(positions(&few), [1, 2]);
+        let untargeted = PlanOptions { strategy: SelectionStrategy::MinimizeTransactions, ..PlanOptions::default() };
+        assert!(plan_claims(notes.clone(), &policy, &untargeted, &mut StdRng::seed_from_u64(7)).is_err());
+
+        // Privacy strategies claim the same notes, shuffled, together or one per batch
+        let together = plan(PlanOptions { strategy: SelectionStrategy::AllAtOnce, ..PlanOptions::default() });
+        let mut claimed = positions(&together);
+        claimed.sort();
+        assert_eq!((claimed, together.batches()), (vec![0, 1, 2, 3], 1));
+        let spread = plan(PlanOptions { strategy: SelectionStrategy::Spread, account: Some(0), ..PlanOptions::default() });
+        assert_eq!(spread.batches(), 3);
+        assert_eq!(spread.claims_in(Some(2)).count(), 1);
+        assert!(spread.claims.iter().all(|claim| claim.note.account == Some(0)));
+    }
+}
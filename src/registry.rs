@@ -0,0 +1,415 @@
+//! Registry of airdrop nullifiers consumed by accepted claims.
+//!
+//! Verifiers (the wallet's own validation path or a relayer) record every
+//! accepted claim here so that a second claim of the same note is rejected
+//! even across restarts. Each entry remembers the block height and the
+//! transaction that consumed it so a chain reorganisation can be undone.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use sled::transaction::{ConflictableTransactionError, TransactionError};
+use sled::Transactional;
+
+use crate::store::{StoreBatch, StoreError, Table, WalletStore};
+use crate::{Nullifier, ProtocolError};
+
+const NULLIFIERS_TREE: &str = "airdrop_nullifier_set";
+const HEIGHTS_TREE: &str = "airdrop_nullifier_heights";
+
+/// Where an airdrop nullifier was consumed on the Namada chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsumedNullifier {
+    pub block_height: u64,
+    pub txid: [u8; 32],
+}
+
+/// Storage for consumed airdrop nullifiers
+pub trait NullifierRegistry: fmt::Debug {
+    /// Look up where `nullifier` was consumed, if it was
+    fn get(&self, nullifier: &Nullifier) -> Result<Option<ConsumedNullifier>, ProtocolError>;
+
+    fn contains(&self, nullifier: &Nullifier) -> Result<bool, ProtocolError> {
+        Ok(self.get(nullifier)?.is_some())
+    }
+
+    /// Atomically record `nullifier` as consumed, failing with
+    /// [`ProtocolError::DoubleClaim`] if it already is
+    fn insert(
+        &mut self,
+        nullifier: Nullifier,
+        consumed: ConsumedNullifier,
+    ) -> Result<(), ProtocolError>;
+
+    /// Forget every nullifier consumed above `height`, returning them
+    fn rollback_to(&mut self, height: u64) -> Result<Vec<Nullifier>, ProtocolError>;
+
+    /// Every consumed nullifier, in no particular order
+    fn entries(&self) -> Result<Vec<(Nullifier, ConsumedNullifier)>, ProtocolError>;
+}
+
+/// In-memory registry, lost when the process exits
+#[derive(Debug, Clone, Default)]
+pub struct MemoryNullifierRegistry {
+    nullifiers: HashMap<Nullifier, ConsumedNullifier>,
+}
+
+impl MemoryNullifierRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl NullifierRegistry for MemoryNullifierRegistry {
+    fn get(&self, nullifier: &Nullifier) -> Result<Option<ConsumedNullifier>, ProtocolError> {
+        Ok(self.nullifiers.get(nullifier).copied())
+    }
+
+    fn insert(
+        &mut self,
+        nullifier: Nullifier,
+        consumed: ConsumedNullifier,
+    ) -> Result<(), ProtocolError> {
+        if self.nullifiers.contains_key(&nullifier) {
+            return Err(ProtocolError::DoubleClaim(nullifier));
+        }
+        self.nullifiers.insert(nullifier, consumed);
+        Ok(())
+    }
+
+    fn rollback_to(&mut self, height: u64) -> Result<Vec<Nullifier>, ProtocolError> {
+        let removed: Vec<Nullifier> = self
+            .nullifiers
+            .iter()
+            .filter(|(_, consumed)| consumed.block_height > height)
+            .map(|(nullifier, _)| *nullifier)
+            .collect();
+        for nullifier in &removed {
+            self.nullifiers.remove(nullifier);
+        }
+        Ok(removed)
+    }
+
+    fn entries(&self) -> Result<Vec<(Nullifier, ConsumedNullifier)>, ProtocolError> {
+        Ok(self.nullifiers.iter().map(|(n, c)| (*n, *c)).collect())
+    }
+}
+
+/// Sled-backed registry that survives restarts.
+///
+/// Nullifiers are stored in one tree and indexed by `height || nullifier` in
+/// another; both are updated in a single sled transaction and flushed before
+/// returning.
+#[derive(Debug, Clone)]
+pub struct SledNullifierRegistry {
+    nullifiers: sled::Tree,
+    heights: sled::Tree,
+}
+
+impl SledNullifierRegistry {
+    /// Open a standalone registry database, e.g. for a relayer
+    pub fn open(path: &Path) -> Result<Self, ProtocolError> {
+        let db = sled::open(path).map_err(registry_error)?;
+        Self::from_db(&db)
+    }
+
+    /// Use the registry trees inside an existing database
+    pub fn from_db(db: &sled::Db) -> Result<Self, ProtocolError> {
+        Ok(Self {
+            nullifiers: db.open_tree(NULLIFIERS_TREE).map_err(registry_error)?,
+            heights: db.open_tree(HEIGHTS_TREE).map_err(registry_error)?,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.nullifiers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nullifiers.is_empty()
+    }
+
+    fn flush(&self) -> Result<(), ProtocolError> {
+        self.nullifiers.flush().map_err(registry_error)?;
+        self.heights.flush().map_err(registry_error)?;
+        Ok(())
+    }
+}
+
+impl NullifierRegistry for SledNullifierRegistry {
+    fn get(&self, nullifier: &Nullifier) -> Result<Option<ConsumedNullifier>, ProtocolError> {
+        match self.nullifiers.get(nullifier.0).map_err(registry_error)? {
+            Some(bytes) => bincode::deserialize(&bytes)
+                .map(Some)
+                .map_err(|e| ProtocolError::MalformedEncoding(e.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    fn insert(
+        &mut self,
+        nullifier: Nullifier,
+        consumed: ConsumedNullifier,
+    ) -> Result<(), ProtocolError> {
+        let record = bincode::serialize(&consumed)
+            .map_err(|e| ProtocolError::MalformedEncoding(e.to_string()))?;
+        let height_key = height_key(consumed.block_height, &nullifier);
+
+        (&self.nullifiers, &self.heights)
+            .transaction(|(nullifiers, heights)| {
+                if nullifiers.get(nullifier.0)?.is_some() {
+                    return Err(ConflictableTransactionError::Abort(
+                        ProtocolError::DoubleClaim(nullifier),
+                    ));
+                }
+                nullifiers.insert(&nullifier.0, record.as_slice())?;
+                heights.insert(&height_key[..], &[])?;
+                Ok(())
+            })
+            .map_err(transaction_error)?;
+
+        self.flush()
+    }
+
+    fn rollback_to(&mut self, height: u64) -> Result<Vec<Nullifier>, ProtocolError> {
+        let Some(start) = height.checked_add(1) else {
+            return Ok(Vec::new());
+        };
+
+        let mut removed = Vec::new();
+        for entry in self.heights.range(start.to_be_bytes()..) {
+            let (key, _) = entry.map_err(registry_error)?;
+            let mut nullifier = [0u8; 32];
+            nullifier.copy_from_slice(&key[8..]);
+            removed.push((key, Nullifier(nullifier)));
+        }
+
+        (&self.nullifiers, &self.heights)
+            .transaction(|(nullifiers, heights)| {
+                for (key, nullifier) in &removed {
+                    nullifiers.remove(&nullifier.0)?;
+                    heights.remove(key)?;
+                }
+                Ok::<_, ConflictableTransactionError<ProtocolError>>(())
+            })
+            .map_err(transaction_error)?;
+
+        self.flush()?;
+        Ok(removed.into_iter().map(|(_, nullifier)| nullifier).collect())
+    }
+
+    fn entries(&self) -> Result<Vec<(Nullifier, ConsumedNullifier)>, ProtocolError> {
+        let mut entries = Vec::new();
+        for entry in self.nullifiers.iter() {
+            let (key, value) = entry.map_err(registry_error)?;
+            let nullifier: [u8; 32] = key
+                .as_ref()
+                .try_into()
+                .map_err(|_| ProtocolError::MalformedEncoding("nullifier key".to_string()))?;
+            let consumed = bincode::deserialize(&value)
+                .map_err(|e| ProtocolError::MalformedEncoding(e.to_string()))?;
+            entries.push((Nullifier(nullifier), consumed));
+        }
+        Ok(entries)
+    }
+}
+
+/// Registry kept in a wallet's [`WalletStore`], with the same layout as
+/// [`SledNullifierRegistry`]
+#[derive(Debug, Clone)]
+pub struct StoreNullifierRegistry {
+    store: Arc<dyn WalletStore>,
+}
+
+impl StoreNullifierRegistry {
+    pub fn new(store: Arc<dyn WalletStore>) -> Self {
+        Self { store }
+    }
+
+    /// Stage the writes recording `nullifier` as consumed, for callers
+    /// committing them together with other wallet records
+    pub fn stage(
+        batch: &mut StoreBatch,
+        nullifier: &Nullifier,
+        consumed: &ConsumedNullifier,
+    ) -> Result<(), ProtocolError> {
+        let record = bincode::serialize(consumed)
+            .map_err(|e| ProtocolError::MalformedEncoding(e.to_string()))?;
+        batch.insert(Table::AirdropNullifiers, nullifier.0, record);
+        batch.insert(Table::AirdropNullifierHeights, height_key(consumed.block_height, nullifier), []);
+        Ok(())
+    }
+}
+
+impl NullifierRegistry for StoreNullifierRegistry {
+    fn get(&self, nullifier: &Nullifier) -> Result<Option<ConsumedNullifier>, ProtocolError> {
+        match self.store.get(Table::AirdropNullifiers, &nullifier.0).map_err(store_error)? {
+            Some(bytes) => bincode::deserialize(&bytes)
+                .map(Some)
+                .map_err(|e| ProtocolError::MalformedEncoding(e.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    fn insert(
+        &mut self,
+        nullifier: Nullifier,
+        consumed: ConsumedNullifier,
+    ) -> Result<(), ProtocolError> {
+        // Clones of the registry share the store, so the check that the
+        // nullifier is new is left to the commit itself
+        let record = bincode::serialize(&consumed)
+            .map_err(|e| ProtocolError::MalformedEncoding(e.to_string()))?;
+        let mut batch = StoreBatch::default();
+        batch.insert_new(Table::AirdropNullifiers, nullifier.0, record);
+        batch.insert(Table::AirdropNullifierHeights, height_key(consumed.block_height, &nullifier), []);
+        match self.store.commit(&batch) {
+            Err(StoreError::KeyExists { .. }) => Err(ProtocolError::DoubleClaim(nullifier)),
+            result => result.map_err(store_error),
+        }
+    }
+
+    fn rollback_to(&mut self, height: u64) -> Result<Vec<Nullifier>, ProtocolError> {
+        let Some(start) = height.checked_add(1) else {
+            return Ok(Vec::new());
+        };
+
+        let mut batch = StoreBatch::default();
+        let mut removed = Vec::new();
+        for (key, _) in self.store.entries(Table::AirdropNullifierHeights).map_err(store_error)? {
+            if key[..8] < start.to_be_bytes()[..] {
+                continue;
+            }
+            let mut nullifier = [0u8; 32];
+            nullifier.copy_from_slice(&key[8..]);
+            batch.remove(Table::AirdropNullifiers, nullifier);
+            batch.remove(Table::AirdropNullifierHeights, key);
+            removed.push(Nullifier(nullifier));
+        }
+
+        self.store.commit(&batch).map_err(store_error)?;
+        Ok(removed)
+    }
+
+    fn entries(&self) -> Result<Vec<(Nullifier, ConsumedNullifier)>, ProtocolError> {
+        let mut entries = Vec::new();
+        for (key, value) in self.store.entries(Table::AirdropNullifiers).map_err(store_error)? {
+            let nullifier: [u8; 32] = key
+                .as_slice()
+                .try_into()
+                .map_err(|_| ProtocolError::MalformedEncoding("nullifier key".to_string()))?;
+            let consumed = bincode::deserialize(&value)
+                .map_err(|e| ProtocolError::MalformedEncoding(e.to_string()))?;
+            entries.push((Nullifier(nullifier), consumed));
+        }
+        Ok(entries)
+    }
+}
+
+fn height_key(height: u64, nullifier: &Nullifier) -> [u8; 40] {
+    let mut key = [0u8; 40];
+    key[..8].copy_from_slice(&height.to_be_bytes());
+    key[8..].copy_from_slice(&nullifier.0);
+    key
+}
+
+fn store_error(e: StoreError) -> ProtocolError {
+    ProtocolError::Registry(e.to_string())
+}
+
+fn registry_error(e: sled::Error) -> ProtocolError {
+    ProtocolError::Registry(e.to_string())
+}
+
+fn transaction_error(e: TransactionError<ProtocolError>) -> ProtocolError {
+    match e {
+        TransactionError::Abort(e) => e,
+        TransactionError::Storage(e) => registry_error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn consumed(block_height: u64) -> ConsumedNullifier {
+        ConsumedNullifier {
+            block_height,
+            txid: [block_height as u8; 32],
+        }
+    }
+
+    #[test]
+    fn test_sled_registry_persists_and_rejects_double_claims() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("registry");
+
+        {
+            let mut registry = SledNullifierRegistry::open(&path).unwrap();
+            registry.insert(Nullifier([1u8; 32]), consumed(10)).unwrap();
+            assert_eq!(
+                registry.insert(Nullifier([1u8; 32]), consumed(11)),
+                Err(ProtocolError::DoubleClaim(Nullifier([1u8; 32])))
+            );
+        }
+
+        // A restarted verifier still knows about the claim
+        let registry = SledNullifierRegistry::open(&path).unwrap();
+        assert_eq!(registry.get(&Nullifier([1u8; 32])).unwrap(), Some(consumed(10)));
+        assert_eq!(registry.len(), 1);
+    }
+
+    #[test]
+    fn test_shared_store_registry_accepts_one_of_racing_claims() {
+        use crate::store::{open_store, StorageBackend};
+
+        let dir = tempfile::tempdir().unwrap();
+        for backend in [StorageBackend::Sled, StorageBackend::Sqlite, StorageBackend::Memory] {
+            let store = open_store(backend, &dir.path().join(format!("{:?}", backend))).unwrap();
+            let registry = StoreNullifierRegistry::new(store);
+
+            // Clones share the store, so only one of them may record the claim
+            let inserts: Vec<_> = (0..8u64)
+                .map(|i| {
+                    let mut registry = registry.clone();
+                    std::thread::spawn(move || registry.insert(Nullifier([1u8; 32]), consumed(i)))
+                })
+                .collect();
+            let results: Vec<_> = inserts.into_iter().map(|insert| insert.join().unwrap()).collect();
+            assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1, "{:?}", backend);
+            assert!(results
+                .iter()
+                .all(|result| matches!(result, Ok(()) | Err(ProtocolError::DoubleClaim(_)))));
+            assert_eq!(registry.entries().unwrap().len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_rollback_on_reorg() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sled_registry = SledNullifierRegistry::open(&dir.path().join("registry")).unwrap();
+        let mut memory_registry = MemoryNullifierRegistry::new();
+        let mut store_registry = StoreNullifierRegistry::new(Arc::new(crate::store::MemoryStore::new()));
+
+        let registries: [&mut dyn NullifierRegistry; 3] =
+            [&mut sled_registry, &mut memory_registry, &mut store_registry];
+        for registry in registries {
+            registry.insert(Nullifier([1u8; 32]), consumed(10)).unwrap();
+            registry.insert(Nullifier([2u8; 32]), consumed(11)).unwrap();
+            registry.insert(Nullifier([3u8; 32]), consumed(12)).unwrap();
+
+            let mut removed = registry.rollback_to(10).unwrap();
+            removed.sort_by_key(|n| n.0);
+            assert_eq!(removed, vec![Nullifier([2u8; 32]), Nullifier([3u8; 32])]);
+
+            assert!(registry.contains(&Nullifier([1u8; 32])).unwrap());
+            assert!(!registry.contains(&Nullifier([2u8; 32])).unwrap());
+
+            // Reorged claims can be included again
+            registry.insert(Nullifier([2u8; 32]), consumed(11)).unwrap();
+        }
+    }
+}
@@ -0,0 +1,672 @@
+//! Discovery of the wallet's notes in lightwalletd compact blocks.
+//!
+//! Blocks are read from a file of length-delimited `CompactBlock` protobuf
+//! messages, as served by lightwalletd's `GetBlockRange`. Every Sapling output
+//! and Orchard action is trial-decrypted with the wallet's incoming viewing
+//! keys; decrypted notes are assigned their position in the note commitment
+//! tree, and their Zcash nullifiers are tracked so that later spends by the
+//! wallet are recognised. Scanning stops at the snapshot height.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use ff::{FromUniformBytes, PrimeField};
+use orchard::keys::{
+    FullViewingKey as OrchardFvk, PreparedIncomingViewingKey as OrchardPreparedIvk,
+};
+use orchard::note_encryption::{CompactAction, OrchardDomain};
+use prost::Message;
+use sapling_crypto::keys::PreparedIncomingViewingKey as SaplingPreparedIvk;
+use sapling_crypto::note_encryption::{try_sapling_compact_note_decryption, CompactOutputDescription};
+use sapling_crypto::zip32::DiversifiableFullViewingKey;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zcash_note_encryption::{try_compact_note_decryption, EphemeralKeyBytes, COMPACT_NOTE_SIZE};
+use zcash_primitives::consensus::{BlockHeight, Network};
+use zcash_primitives::transaction::components::sapling::zip212_enforcement;
+use zcash_spec::PrfExpand;
+use zip32::Scope;
+
+use crate::keys::ViewingAccount;
+use crate::{FieldElement, NoteCommitment, NoteId, Nullifier, OrchardNote, Pool, SaplingNote, Scalar};
+
+/// Compact block messages from lightwalletd's `compact_formats.proto`
+pub mod proto {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ChainMetadata {
+        /// Sapling note commitment tree size at the end of the block
+        #[prost(uint32, tag = "1")]
+        pub sapling_commitment_tree_size: u32,
+        /// Orchard note commitment tree size at the end of the block
+        #[prost(uint32, tag = "2")]
+        pub orchard_commitment_tree_size: u32,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CompactBlock {
+        #[prost(uint32, tag = "1")]
+        pub proto_version: u32,
+        #[prost(uint64, tag = "2")]
+        pub height: u64,
+        #[prost(bytes = "vec", tag = "3")]
+        pub hash: Vec<u8>,
+        #[prost(bytes = "vec", tag = "4")]
+        pub prev_hash: Vec<u8>,
+        #[prost(uint32, tag = "5")]
+        pub time: u32,
+        #[prost(bytes = "vec", tag = "6")]
+        pub header: Vec<u8>,
+        #[prost(message, repeated, tag = "7")]
+        pub vtx: Vec<CompactTx>,
+        #[prost(message, optional, tag = "8")]
+        pub chain_metadata: Option<ChainMetadata>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CompactTx {
+        #[prost(uint64, tag = "1")]
+        pub index: u64,
+        #[prost(bytes = "vec", tag = "2")]
+        pub hash: Vec<u8>,
+        #[prost(uint32, tag = "3")]
+        pub fee: u32,
+        #[prost(message, repeated, tag = "4")]
+        pub spends: Vec<CompactSaplingSpend>,
+        #[prost(message, repeated, tag = "5")]
+        pub outputs: Vec<CompactSaplingOutput>,
+        #[prost(message, repeated, tag = "6")]
+        pub actions: Vec<CompactOrchardAction>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CompactSaplingSpend {
+        #[prost(bytes = "vec", tag = "1")]
+        pub nf: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CompactSaplingOutput {
+        #[prost(bytes = "vec", tag = "1")]
+        pub cmu: Vec<u8>,
+        #[prost(bytes = "vec", tag = "2")]
+        pub ephemeral_key: Vec<u8>,
+        #[prost(bytes = "vec", tag = "3")]
+        pub ciphertext: Vec<u8>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CompactOrchardAction {
+        #[prost(bytes = "vec", tag = "1")]
+        pub nullifier: Vec<u8>,
+        #[prost(bytes = "vec", tag = "2")]
+        pub cmx: Vec<u8>,
+        #[prost(bytes = "vec", tag = "3")]
+        pub ephemeral_key: Vec<u8>,
+        #[prost(bytes = "vec", tag = "4")]
+        pub ciphertext: Vec<u8>,
+    }
+}
+
+pub use proto::CompactBlock;
+
+#[derive(Debug, Error)]
+pub enum ScanError {
+    #[error("failed to read compact blocks: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("malformed compact block: {0}")]
+    Decode(String),
+
+    #[error("expected block {expected}, got {actual}")]
+    NonSequentialBlock { expected: u64, actual: u64 },
+
+    #[error("{pool} commitment tree size at block {height} is inconsistent with the scanned outputs")]
+    TreeSizeMismatch { pool: Pool, height: u64 },
+
+    #[error("block {0} has no chain metadata to take the commitment tree sizes from")]
+    MissingChainMetadata(u64),
+
+    #[error("malformed {pool} output {index} in block {height}")]
+    MalformedOutput { pool: Pool, height: u64, index: usize },
+
+    #[error("invalid {0} viewing key")]
+    InvalidViewingKey(Pool),
+}
+
+/// Consensus parameters for a wallet network name
+pub fn consensus_network(network: &str) -> Network {
+    match network {
+        "mainnet" | "main" => Network::MainNetwork,
+        _ => Network::TestNetwork,
+    }
+}
+
+/// Read a file of length-delimited `CompactBlock` messages
+pub fn read_compact_blocks(path: &Path) -> Result<Vec<CompactBlock>, ScanError> {
+    let data = fs::read(path)?;
+    let mut buf = data.as_slice();
+    let mut blocks = Vec::new();
+    while !buf.is_empty() {
+        blocks.push(
+            CompactBlock::decode_length_delimited(&mut buf)
+                .map_err(|e| ScanError::Decode(e.to_string()))?,
+        );
+    }
+    Ok(blocks)
+}
+
+/// Write `blocks` in the format read by [`read_compact_blocks`]
+pub fn write_compact_blocks(path: &Path, blocks: &[CompactBlock]) -> Result<(), ScanError> {
+    let mut data = Vec::new();
+    for block in blocks {
+        block
+            .encode_length_delimited(&mut data)
+            .map_err(|e| ScanError::Decode(e.to_string()))?;
+    }
+    fs::write(path, data)?;
+    Ok(())
+}
+
+/// Where the scanner is in the chain, persisted between runs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanState {
+    /// Last block scanned, if any
+    pub last_height: Option<u64>,
+    pub sapling_tree_size: u64,
+    pub orchard_tree_size: u64,
+}
+
+impl ScanState {
+    /// Progress of a scan that stopped just before `block`, taken from the
+    /// commitment tree sizes in the block's chain metadata
+    pub fn before(block: &CompactBlock) -> Result<Self, ScanError> {
+        let metadata = block.chain_metadata.as_ref().ok_or(ScanError::MissingChainMetadata(block.height))?;
+        let start = |pool, end: u32, outputs: usize| {
+            (end as u64)
+                .checked_sub(outputs as u64)
+                .ok_or(ScanError::TreeSizeMismatch { pool, height: block.height })
+        };
+        Ok(Self {
+            last_height: block.height.checked_sub(1),
+            sapling_tree_size: start(
+                Pool::Sapling,
+                metadata.sapling_commitment_tree_size,
+                block.vtx.iter().map(|tx| tx.outputs.len()).sum(),
+            )?,
+            orchard_tree_size: start(
+                Pool::Orchard,
+                metadata.orchard_commitment_tree_size,
+                block.vtx.iter().map(|tx| tx.actions.len()).sum(),
+            )?,
+        })
+    }
+
+    /// Size of the `pool` commitment tree after the last scanned block
+    pub fn tree_size(&self, pool: Pool) -> u64 {
+        match pool {
+            Pool::Sapling => self.sapling_tree_size,
+            Pool::Orchard => self.orchard_tree_size,
+        }
+    }
+}
+
+/// Viewing keys of one account prepared for trial decryption
+pub struct ScanKeys {
+    sapling: Option<SaplingScanKeys>,
+    orchard: Option<OrchardScanKeys>,
+}
+
+struct SaplingScanKeys {
+    dfvk: DiversifiableFullViewingKey,
+    ivks: Vec<(Scope, SaplingPreparedIvk)>,
+}
+
+struct OrchardScanKeys {
+    fvk: OrchardFvk,
+    ivks: Vec<OrchardPreparedIvk>,
+}
+
+/// Change outputs are sent to the internal scope
+const SCOPES: [Scope; 2] = [Scope::External, Scope::Internal];
+
+impl ScanKeys {
+    /// Keys for whichever pools have a full viewing key
+    pub fn new(sapling: Option<DiversifiableFullViewingKey>, orchard: Option<OrchardFvk>) -> Self {
+        Self {
+            sapling: sapling.map(|dfvk| SaplingScanKeys {
+                ivks: SCOPES
+                    .iter()
+                    .map(|scope| (*scope, SaplingPreparedIvk::new(&dfvk.to_ivk(*scope))))
+                    .collect(),
+                dfvk,
+            }),
+            orchard: orchard.map(|fvk| OrchardScanKeys {
+                ivks: SCOPES
+                    .iter()
+                    .map(|scope| OrchardPreparedIvk::new(&fvk.to_ivk(*scope)))
+                    .collect(),
+                fvk,
+            }),
+        }
+    }
+
+    /// Keys for the pools a wallet account can view
+    pub fn from_viewing_account(account: &ViewingAccount) -> Result<Self, ScanError> {
+        let sapling = account
+            .sapling
+            .as_ref()
+            .map(|keys| {
+                let fvk: [u8; 128] = keys
+                    .fvk
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| ScanError::InvalidViewingKey(Pool::Sapling))?;
+                DiversifiableFullViewingKey::from_bytes(&fvk).ok_or(ScanError::InvalidViewingKey(Pool::Sapling))
+            })
+            .transpose()?;
+
+        let orchard = account
+            .orchard
+            .as_ref()
+            .map(|keys| {
+                let fvk: [u8; 96] = keys
+                    .fvk
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| ScanError::InvalidViewingKey(Pool::Orchard))?;
+                OrchardFvk::from_bytes(&fvk).ok_or(ScanError::InvalidViewingKey(Pool::Orchard))
+            })
+            .transpose()?;
+
+        Ok(Self::new(sapling, orchard))
+    }
+}
+
+impl SaplingScanKeys {
+    fn nk(&self, scope: Scope) -> Scalar {
+        let fvk = match scope {
+            Scope::External => self.dfvk.fvk().to_bytes(),
+            Scope::Internal => self.dfvk.to_internal_fvk().to_bytes(),
+        };
+        let mut nk = [0u8; 32];
+        nk.copy_from_slice(&fvk[32..64]);
+        Scalar(nk)
+    }
+}
+
+impl OrchardScanKeys {
+    fn nk(&self) -> Scalar {
+        let mut nk = [0u8; 32];
+        nk.copy_from_slice(&self.fvk.to_bytes()[32..64]);
+        Scalar(nk)
+    }
+}
+
+/// A note discovered while scanning, with its Zcash nullifier
+#[derive(Debug, Clone)]
+pub struct Discovered<N> {
+    pub note: N,
+    pub nullifier: Nullifier,
+    pub height: u64,
+    /// Transaction hash in lightwalletd (internal) byte order
+    pub txid: [u8; 32],
+    /// Index of the output or action within its transaction
+    pub output_index: usize,
+}
+
+/// What a single block contained for the wallet
+#[derive(Debug, Default)]
+pub struct BlockScan {
+    pub height: u64,
+    pub sapling_notes: Vec<Discovered<SaplingNote>>,
+    pub orchard_notes: Vec<Discovered<OrchardNote>>,
+    /// The wallet's notes spent in this block
+    pub spends: Vec<NoteId>,
+}
+
+/// Totals over a scanning run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanSummary {
+    pub blocks_scanned: u64,
+    pub sapling_notes: usize,
+    pub orchard_notes: usize,
+    pub spends: usize,
+    pub last_height: Option<u64>,
+}
+
+/// Trial-decrypts compact blocks up to the snapshot height
+pub struct Scanner {
+    network: Network,
+    keys: Vec<ScanKeys>,
+    state: ScanState,
+    snapshot_height: Option<u64>,
+    nullifiers: HashMap<Nullifier, NoteId>,
+}
+
+impl Scanner {
+    pub fn new(network: Network, keys: Vec<ScanKeys>, state: ScanState, snapshot_height: Option<u64>) -> Self {
+        Self {
+            network,
+            keys,
+            state,
+            snapshot_height,
+            nullifiers: HashMap::new(),
+        }
+    }
+
+    /// Watch for spends of a note the wallet already knows about
+    pub fn track_nullifier(&mut self, nullifier: Nullifier, note: NoteId) {
+        self.nullifiers.insert(nullifier, note);
+    }
+
+    pub fn state(&self) -> ScanState {
+        self.state
+    }
+
+    /// Whether `height` lies beyond the snapshot and must not be scanned
+    pub fn past_snapshot(&self, height: u64) -> bool {
+        self.snapshot_height.is_some_and(|snapshot| height > snapshot)
+    }
+
+    /// Scan the next block, which must directly follow the last one scanned
+    pub fn scan_block(&mut self, block: &CompactBlock) -> Result<BlockScan, ScanError> {
+        if let Some(last) = self.state.last_height {
+            if block.height != last + 1 {
+                return Err(ScanError::NonSequentialBlock { expected: last + 1, actual: block.height });
+            }
+        }
+
+        let sapling_outputs: usize = block.vtx.iter().map(|tx| tx.outputs.len()).sum();
+        let orchard_actions: usize = block.vtx.iter().map(|tx| tx.actions.len()).sum();
+        let mut sapling_position =
+            self.tree_start(Pool::Sapling, block, sapling_outputs as u64)?;
+        let mut orchard_position =
+            self.tree_start(Pool::Orchard, block, orchard_actions as u64)?;
+
+        let zip212 = zip212_enforcement(&self.network, BlockHeight::from_u32(block.height as u32));
+        let mut scan = BlockScan { height: block.height, ..Default::default() };
+
+        for tx in &block.vtx {
+            let txid = <[u8; 32]>::try_from(tx.hash.as_slice()).unwrap_or_default();
+            for spend in &tx.spends {
+                self.check_spend(Pool::Sapling, &spend.nf, &mut scan);
+            }
+
+            for (index, output) in tx.outputs.iter().enumerate() {
+                let compact = sapling_output(output)
+                    .ok_or(ScanError::MalformedOutput { pool: Pool::Sapling, height: block.height, index })?;
+                if let Some(found) = self.decrypt_sapling(&compact, sapling_position, zip212) {
+                    self.track_nullifier(found.nullifier, NoteId::new(Pool::Sapling, sapling_position));
+                    scan.sapling_notes.push(Discovered { height: block.height, txid, output_index: index, ..found });
+                }
+                sapling_position += 1;
+            }
+
+            for (index, action) in tx.actions.iter().enumerate() {
+                self.check_spend(Pool::Orchard, &action.nullifier, &mut scan);
+
+                let compact = orchard_action(action)
+                    .ok_or(ScanError::MalformedOutput { pool: Pool::Orchard, height: block.height, index })?;
+                if let Some(found) = self.decrypt_orchard(&compact, orchard_position) {
+                    self.track_nullifier(found.nullifier, NoteId::new(Pool::Orchard, orchard_position));
+                    scan.orchard_notes.push(Discovered { height: block.height, txid, output_index: index, ..found });
+                }
+                orchard_position += 1;
+            }
+        }
+
+        self.state = ScanState {
+            last_height: Some(block.height),
+            sapling_tree_size: sapling_position,
+            orchard_tree_size: orchard_position,
+        };
+        Ok(scan)
+    }
+
+    /// Tree size before `block`, cross-checked against the block's metadata
+    fn tree_start(&self, pool: Pool, block: &CompactBlock, outputs: u64) -> Result<u64, ScanError> {
+        let known = self.state.tree_size(pool);
+        let Some(metadata) = &block.chain_metadata else {
+            return Ok(known);
+        };
+
+        let end = match pool {
+            Pool::Sapling => metadata.sapling_commitment_tree_size,
+            Pool::Orchard => metadata.orchard_commitment_tree_size,
+        } as u64;
+        let start = end
+            .checked_sub(outputs)
+            .ok_or(ScanError::TreeSizeMismatch { pool, height: block.height })?;
+
+        // The first block scanned establishes the tree size
+        if self.state.last_height.is_some() && start != known {
+            return Err(ScanError::TreeSizeMismatch { pool, height: block.height });
+        }
+        Ok(start)
+    }
+
+    fn check_spend(&self, pool: Pool, nf: &[u8], scan: &mut BlockScan) {
+        let Ok(nf) = <[u8; 32]>::try_from(nf) else {
+            return;
+        };
+        if let Some(note) = self.nullifiers.get(&Nullifier(nf)) {
+            if note.pool == pool {
+                scan.spends.push(*note);
+            }
+        }
+    }
+
+    fn decrypt_sapling(
+        &self,
+        output: &CompactOutputDescription,
+        position: u64,
+        zip212: sapling_crypto::note_encryption::Zip212Enforcement,
+    ) -> Option<Discovered<SaplingNote>> {
+        for keys in self.keys.iter().filter_map(|keys| keys.sapling.as_ref()) {
+            for (scope, ivk) in &keys.ivks {
+                let Some((note, address)) = try_sapling_compact_note_decryption(ivk, output, zip212) else {
+                    continue;
+                };
+                let nullifier = note.nf(&keys.dfvk.to_nk(*scope), position);
+
+                return Some(Discovered {
+                    note: SaplingNote {
+                        diversifier: address.diversifier().0,
+                        value: note.value().inner(),
+                        note_commitment: NoteCommitment(note.cmu().to_bytes()),
+                        nullifier_key: keys.nk(*scope),
+                        randomness: Scalar(note.rcm().to_repr()),
+                        position,
+                    },
+                    nullifier: Nullifier(nullifier.0),
+                    height: 0,
+                    txid: [0u8; 32],
+                    output_index: 0,
+                });
+            }
+        }
+        None
+    }
+
+    fn decrypt_orchard(&self, action: &CompactAction, position: u64) -> Option<Discovered<OrchardNote>> {
+        let domain = OrchardDomain::for_compact_action(action);
+        for keys in self.keys.iter().filter_map(|keys| keys.orchard.as_ref()) {
+            for ivk in &keys.ivks {
+                let Some((note, address)) = try_compact_note_decryption(&domain, ivk, action) else {
+                    continue;
+                };
+                let rho = note.rho().to_bytes();
+                let rseed = note.rseed().as_bytes();
+                let psi = pasta_curves::pallas::Base::from_uniform_bytes(&PrfExpand::PSI.with(rseed, &rho));
+
+                return Some(Discovered {
+                    note: OrchardNote {
+                        diversifier: *address.diversifier().as_array(),
+                        value: note.value().inner(),
+                        note_commitment: NoteCommitment(action.cmx().to_bytes()),
+                        nullifier_key: keys.nk(),
+                        // rcm and psi are both derived from rseed
+                        randomness: Scalar(*rseed),
+                        position,
+                        rho: FieldElement(rho),
+                        psi: FieldElement(psi.to_repr()),
+                    },
+                    nullifier: Nullifier(note.nullifier(&keys.fvk).to_bytes()),
+                    height: 0,
+                    txid: [0u8; 32],
+                    output_index: 0,
+                });
+            }
+        }
+        None
+    }
+}
+
+fn sapling_output(output: &proto::CompactSaplingOutput) -> Option<CompactOutputDescription> {
+    let cmu: [u8; 32] = output.cmu.as_slice().try_into().ok()?;
+    Some(CompactOutputDescription {
+        ephemeral_key: EphemeralKeyBytes(output.ephemeral_key.as_slice().try_into().ok()?),
+        cmu: Option::from(sapling_crypto::note::ExtractedNoteCommitment::from_bytes(&cmu))?,
+        enc_ciphertext: output.ciphertext.as_slice().try_into().ok()?,
+    })
+}
+
+fn orchard_action(action: &proto::CompactOrchardAction) -> Option<CompactAction> {
+    let nullifier: [u8; 32] = action.nullifier.as_slice().try_into().ok()?;
+    let cmx: [u8; 32] = action.cmx.as_slice().try_into().ok()?;
+    let ciphertext: [u8; COMPACT_NOTE_SIZE] = action.ciphertext.as_slice().try_into().ok()?;
+    Some(CompactAction::from_parts(
+        Option::from(orchard::note::Nullifier::from_bytes(&nullifier))?,
+        Option::from(orchard::note::ExtractedNoteCommitment::from_bytes(&cmx))?,
+        EphemeralKeyBytes(action.ephemeral_key.as_slice().try_into().ok()?),
+        ciphertext,
+    ))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::keys::{AccountKeys, WalletSeed};
+    use ff::Field;
+    use orchard::note::{RandomSeed, Rho};
+    use orchard::note_encryption::OrchardNoteEncryption;
+    use sapling_crypto::note_encryption::{sapling_note_encryption, SaplingDomain};
+    use zcash_note_encryption::Domain;
+
+    /// Testnet height past the ZIP-212 grace period
+    pub(crate) const HEIGHT: u64 = 3_000_000;
+
+    pub(crate) fn test_seed_phrase() -> String {
+        ["abandon"; 23].join(" ") + " art"
+    }
+
+    pub(crate) fn test_account(account: u32) -> AccountKeys {
+        WalletSeed::from_phrase(&test_seed_phrase())
+            .unwrap()
+            .derive_account(1, account)
+            .unwrap()
+    }
+
+    /// A compact Sapling output paying `value` to `account`'s default address
+    pub(crate) fn sapling_output_to(account: &AccountKeys, value: u64) -> proto::CompactSaplingOutput {
+        let fvk: [u8; 128] = account.viewing_keys(Pool::Sapling).fvk.try_into().unwrap();
+        let (_, address) = DiversifiableFullViewingKey::from_bytes(&fvk).unwrap().default_address();
+        let note = address.create_note(
+            sapling_crypto::value::NoteValue::from_raw(value),
+            sapling_crypto::Rseed::AfterZip212(rand::random()),
+        );
+
+        let encryption = sapling_note_encryption(None, note.clone(), [0u8; 512], &mut rand::rngs::OsRng);
+        proto::CompactSaplingOutput {
+            cmu: note.cmu().to_bytes().to_vec(),
+            ephemeral_key: SaplingDomain::epk_bytes(encryption.epk()).0.to_vec(),
+            ciphertext: encryption.encrypt_note_plaintext()[..COMPACT_NOTE_SIZE].to_vec(),
+        }
+    }
+
+    /// A compact Orchard action paying `value` to `account`'s default address
+    pub(crate) fn orchard_action_to(account: &AccountKeys, value: u64) -> proto::CompactOrchardAction {
+        let fvk: [u8; 96] = account.viewing_keys(Pool::Orchard).fvk.try_into().unwrap();
+        let address = OrchardFvk::from_bytes(&fvk).unwrap().address_at(0u32, Scope::External);
+
+        let nf_old = pasta_curves::pallas::Base::random(&mut rand::rngs::OsRng).to_repr();
+        let rho = Rho::from_bytes(&nf_old).unwrap();
+        let note = loop {
+            let rseed = RandomSeed::from_bytes(rand::random(), &rho);
+            if let Some(rseed) = Option::from(rseed) {
+                let note = orchard::Note::from_parts(
+                    address,
+                    orchard::value::NoteValue::from_raw(value),
+                    rho,
+                    rseed,
+                );
+                if let Some(note) = Option::from(note) {
+                    break note;
+                }
+            }
+        };
+
+        let encryption = OrchardNoteEncryption::new(None, note, [0u8; 512]);
+        proto::CompactOrchardAction {
+            nullifier: nf_old.to_vec(),
+            cmx: orchard::note::ExtractedNoteCommitment::from(note.commitment()).to_bytes().to_vec(),
+            ephemeral_key: OrchardDomain::epk_bytes(encryption.epk()).0.to_vec(),
+            ciphertext: encryption.encrypt_note_plaintext()[..COMPACT_NOTE_SIZE].to_vec(),
+        }
+    }
+
+    pub(crate) fn block(height: u64, vtx: Vec<proto::CompactTx>) -> CompactBlock {
+        CompactBlock { height, vtx, ..Default::default() }
+    }
+
+    pub(crate) fn scan_keys(account: &AccountKeys) -> ScanKeys {
+        ScanKeys::from_viewing_account(&account.viewing_account()).unwrap()
+    }
+
+    #[test]
+    fn test_scanner_finds_notes_and_spends() {
+        let ours = test_account(0);
+        let theirs = test_account(1);
+
+        let first = block(HEIGHT, vec![proto::CompactTx {
+            outputs: vec![sapling_output_to(&theirs, 5), sapling_output_to(&ours, 1000)],
+            actions: vec![orchard_action_to(&ours, 2000)],
+            ..Default::default()
+        }]);
+        let mut scanner = Scanner::new(
+            Network::TestNetwork,
+            vec![scan_keys(&ours)],
+            ScanState { last_height: None, sapling_tree_size: 10, orchard_tree_size: 20 },
+            None,
+        );
+
+        let scan = scanner.scan_block(&first).unwrap();
+        assert_eq!(scan.sapling_notes.len(), 1);
+        let sapling = &scan.sapling_notes[0];
+        assert_eq!((sapling.note.value, sapling.note.position), (1000, 11));
+        assert_eq!(sapling.note.nullifier_key, ours.viewing_keys(Pool::Sapling).nk);
+        let orchard = &scan.orchard_notes[0];
+        assert_eq!((orchard.note.value, orchard.note.position), (2000, 20));
+        assert_eq!(scanner.state().sapling_tree_size, 12);
+
+        // Spending the Sapling note is recognised by its Zcash nullifier
+        let spend = block(HEIGHT + 1, vec![proto::CompactTx {
+            spends: vec![proto::CompactSaplingSpend { nf: sapling.nullifier.0.to_vec() }],
+            ..Default::default()
+        }]);
+        assert_eq!(scanner.scan_block(&spend).unwrap().spends, vec![NoteId::new(Pool::Sapling, 11)]);
+
+        // Gaps and inconsistent tree sizes are rejected
+        assert!(matches!(
+            scanner.scan_block(&block(HEIGHT + 5, vec![])),
+            Err(ScanError::NonSequentialBlock { .. })
+        ));
+        let mut bad = block(HEIGHT + 2, vec![]);
+        bad.chain_metadata = Some(proto::ChainMetadata {
+            sapling_commitment_tree_size: 99,
+            orchard_commitment_tree_size: 21,
+        });
+        assert!(matches!(scanner.scan_block(&bad), Err(ScanError::TreeSizeMismatch { .. })));
+    }
+}
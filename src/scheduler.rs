@@ -0,0 +1,407 @@
+//! Spreading claim submissions over time.
+//!
+//! Submitting all of a user's claims back-to-back from one connection links
+//! their notes together. The wallet can instead schedule its pending claims:
+//! each gets a submission time after a random delay from the previous one,
+//! decoy slots that only query the node are mixed in so the timing of the
+//! node traffic does not reveal how many claims there are, and a claim of an
+//! account is only submitted once the account's previous claim has been
+//! included, rejected or has expired, so no two can land in the same block.
+//!
+//! Due claims are submitted through any [`NamadaClient`]. With a
+//! [`RelayQueue`] configured they are dropped into a local directory shared
+//! with other users instead, and the relay submits everything queued there
+//! in one shuffled batch, still one claim per account and height.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::namada::{ChainStatus, NamadaClient, NamadaError};
+use crate::wallet::ClaimStatus;
+use crate::{NoteId, ShieldedAirdropTransaction};
+
+/// How claims are scheduled, from the `[scheduling]` section of the config
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SchedulePolicy {
+    /// Schedule claims instead of submitting them as soon as they are built
+    pub enabled: bool,
+    /// Shortest delay between two scheduled submissions
+    pub min_delay_secs: u64,
+    /// Longest delay between two scheduled submissions
+    pub max_delay_secs: u64,
+    /// Decoy slots added to every scheduled set of claims
+    pub decoys: usize,
+    /// Namada blocks a submitted claim may wait for inclusion
+    pub expiry_blocks: u64,
+    /// Directory of the local relay queue to hand due claims to
+    pub relay_queue: Option<PathBuf>,
+    /// Claims the relay waits for before submitting a batch
+    pub min_relay_batch: usize,
+}
+
+impl Default for SchedulePolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_delay_secs: 10 * 60,
+            max_delay_secs: 6 * 60 * 60,
+            decoys: 2,
+            expiry_blocks: 100,
+            relay_queue: None,
+            min_relay_batch: 5,
+        }
+    }
+}
+
+impl SchedulePolicy {
+    /// A random delay between the policy's bounds
+    pub fn delay(&self, rng: &mut impl Rng) -> Result<u64> {
+        if self.min_delay_secs > self.max_delay_secs {
+            anyhow::bail!(
+                "Scheduling min_delay_secs ({}) exceeds max_delay_secs ({})",
+                self.min_delay_secs,
+                self.max_delay_secs
+            );
+        }
+        Ok(rng.gen_range(self.min_delay_secs..=self.max_delay_secs))
+    }
+}
+
+/// A claim waiting in the schedule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledClaim {
+    pub tx_hash: String,
+    /// Account holding the claimed note, if known
+    pub account: Option<u32>,
+    pub tx: ShieldedAirdropTransaction,
+}
+
+/// Whose claims must not be under way at the same time: those of one
+/// account, or those of one note held by no account
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClaimOwner {
+    Account(u32),
+    Note(NoteId),
+}
+
+/// A point in time at which the wallet contacts the node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledSlot {
+    /// Unix time from which the slot is due
+    pub not_before: u64,
+    /// The claim to submit; none for a decoy slot
+    pub claim: Option<ScheduledClaim>,
+}
+
+impl ScheduledSlot {
+    pub fn is_decoy(&self) -> bool {
+        self.claim.is_none()
+    }
+}
+
+/// What one pass over the due slots did
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScheduleRun {
+    /// Submitted claims and the status they reached
+    pub submitted: Vec<(String, ClaimStatus)>,
+    /// Due claims left for a later run because another claim of their
+    /// account is still under way
+    pub postponed: usize,
+    /// Decoy slots that queried the node
+    pub decoys: usize,
+    /// Slots dropped because their claim was no longer pending
+    pub dropped: usize,
+}
+
+/// Directory of claims waiting for a relay to submit them together.
+///
+/// Each claim is a file named after its transaction ID, so claims from
+/// several wallets can share the directory. A claim carries the opaque tag
+/// of its owner, and the height at which an owner last had a claim
+/// submitted, with that claim's transaction ID, is kept next to the claims
+/// in a `.height` file until the claim is resolved.
+#[derive(Debug, Clone)]
+pub struct RelayQueue {
+    dir: PathBuf,
+}
+
+/// A claim waiting in a [`RelayQueue`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct QueuedClaim {
+    /// Tag shared by the claims of one account; none for a note held by no
+    /// account, whose airdrop nullifier stands in for it
+    owner: Option<[u8; 32]>,
+    tx: ShieldedAirdropTransaction,
+}
+
+impl QueuedClaim {
+    fn owner(&self) -> [u8; 32] {
+        self.owner.unwrap_or(self.tx.get_airdrop_nullifier().0)
+    }
+}
+
+/// Outcome of [`RelayQueue::flush`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RelayFlush {
+    pub submitted: usize,
+    /// Claims left queued because a claim of their owner is still under way
+    pub postponed: usize,
+    /// Claims the node refused, with its reason
+    pub rejected: Vec<(String, String)>,
+}
+
+impl RelayQueue {
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create relay queue: {}", dir.display()))?;
+        Ok(Self { dir: dir.to_path_buf() })
+    }
+
+    pub fn enqueue(&self, tx: &ShieldedAirdropTransaction, owner: Option<[u8; 32]>) -> Result<()> {
+        let path = self.dir.join(format!("{}.bin", hex::encode(tx.txid())));
+        let claim = bincode::serialize(&QueuedClaim { owner, tx: tx.clone() })
+            .with_context(|| "Failed to serialize queued claim")?;
+        // Written aside and renamed so the relay never reads half a claim
+        let partial = path.with_extension("part");
+        fs::write(&partial, claim)
+            .with_context(|| format!("Failed to write to relay queue: {}", partial.display()))?;
+        fs::rename(&partial, &path)
+            .with_context(|| format!("Failed to write to relay queue: {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Paths of the queue's files with extension `ext`
+    fn files(&self, ext: &str) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(&self.dir)
+            .with_context(|| format!("Failed to read relay queue: {}", self.dir.display()))?
+        {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == ext) {
+                paths.push(path);
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Paths of the queued claims
+    fn queued(&self) -> Result<Vec<PathBuf>> {
+        self.files("bin")
+    }
+
+    /// Owners whose last submitted claim the node has neither included nor
+    /// rejected, and that has not expired by `height`; the records of
+    /// resolved claims are no longer needed and are removed
+    fn busy_owners(&self, client: &dyn NamadaClient, height: u64, expiry_blocks: u64) -> Result<HashSet<[u8; 32]>> {
+        let mut busy = HashSet::new();
+        for path in self.files("height")? {
+            let submitted = fs::read_to_string(&path).ok().and_then(|record| parse_submitted(&record));
+            let owner = path.file_stem().and_then(|stem| hex::decode(stem.to_string_lossy().as_ref()).ok());
+            let owner = match (submitted, owner.and_then(|owner| <[u8; 32]>::try_from(owner).ok())) {
+                (Some((submitted_at, txid)), Some(owner)) if height <= submitted_at.saturating_add(expiry_blocks) => {
+                    (client.claim_status(&txid)? == ChainStatus::Unknown).then_some(owner)
+                }
+                _ => None,
+            };
+            match owner {
+                Some(owner) => {
+                    busy.insert(owner);
+                }
+                None => fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove from relay queue: {}", path.display()))?,
+            }
+        }
+        Ok(busy)
+    }
+
+    fn mark_submitted(&self, owner: &[u8; 32], height: u64, txid: &[u8; 32]) -> Result<()> {
+        let path = self.dir.join(format!("{}.height", hex::encode(owner)));
+        fs::write(&path, format!("{} {}", height, hex::encode(txid)))
+            .with_context(|| format!("Failed to write to relay queue: {}", path.display()))
+    }
+
+    pub fn len(&self) -> Result<usize> {
+        Ok(self.queued()?.len())
+    }
+
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Submit every queued claim in random order once at least `min_batch`
+    /// are waiting, removing them from the queue. A claim whose owner has an
+    /// earlier claim not yet included, rejected or expired after
+    /// `expiry_blocks` stays queued for a later flush, as do claims the node
+    /// cannot be reached for.
+    pub fn flush(
+        &self,
+        client: &mut dyn NamadaClient,
+        min_batch: usize,
+        expiry_blocks: u64,
+        rng: &mut impl Rng,
+    ) -> Result<RelayFlush> {
+        let mut paths = self.queued()?;
+        let mut flush = RelayFlush::default();
+        if paths.len() < min_batch.max(1) {
+            return Ok(flush);
+        }
+
+        let height = client.latest_height()?;
+        let mut busy = self.busy_owners(client, height, expiry_blocks)?;
+        paths.shuffle(rng);
+        for path in paths {
+            let data = fs::read(&path)
+                .with_context(|| format!("Failed to read queued claim: {}", path.display()))?;
+            let claim: QueuedClaim = bincode::deserialize(&data)
+                .with_context(|| format!("Malformed queued claim: {}", path.display()))?;
+            let owner = claim.owner();
+            if !busy.insert(owner) {
+                flush.postponed += 1;
+                continue;
+            }
+            match client.broadcast(&claim.tx) {
+                Ok(()) => {
+                    self.mark_submitted(&owner, height, &claim.tx.txid())?;
+                    flush.submitted += 1;
+                }
+                Err(NamadaError::Rejected(reason)) => {
+                    busy.remove(&owner);
+                    flush.rejected.push((hex::encode(claim.tx.txid()), reason));
+                }
+                Err(e) => return Err(e.into()),
+            }
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove queued claim: {}", path.display()))?;
+        }
+        Ok(flush)
+    }
+}
+
+/// The height and transaction ID in an owner's `.height` record
+fn parse_submitted(record: &str) -> Option<(u64, [u8; 32])> {
+    let (height, txid) = record.trim().split_once(' ')?;
+    let txid = hex::decode(txid).ok()?.try_into().ok()?;
+    Some((height.parse().ok()?, txid))
+}
+
+/// A client whose broadcasts go to a [`RelayQueue`] while heights and claim
+/// statuses come from the node
+pub struct RelayClient<'a> {
+    node: &'a dyn NamadaClient,
+    queue: &'a RelayQueue,
+}
+
+impl<'a> RelayClient<'a> {
+    pub fn new(node: &'a dyn NamadaClient, queue: &'a RelayQueue) -> Self {
+        Self { node, queue }
+    }
+}
+
+impl NamadaClient for RelayClient<'_> {
+    fn latest_height(&self) -> Result<u64, NamadaError> {
+        self.node.latest_height()
+    }
+
+    fn broadcast(&mut self, tx: &ShieldedAirdropTransaction) -> Result<(), NamadaError> {
+        self.broadcast_owned(tx, None)
+    }
+
+    fn broadcast_owned(&mut self, tx: &ShieldedAirdropTransaction, owner: Option<[u8; 32]>) -> Result<(), NamadaError> {
+        self.queue.enqueue(tx, owner).map_err(|e| NamadaError::Unavailable(format!("{:#}", e)))
+    }
+
+    fn claim_status(&self, txid: &[u8; 32]) -> Result<ChainStatus, NamadaError> {
+        self.node.claim_status(txid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use crate::namada::LocalNamadaChain;
+    use crate::{AirdropPolicy, AirdropWallet, NoteCommitment, Pool, SaplingNote, Scalar};
+
+    /// Claims of `count` different notes
+    fn claims(count: u8) -> Vec<ShieldedAirdropTransaction> {
+        let mut wallet = AirdropWallet::new();
+        let mut txs = Vec::new();
+        for position in 0..count {
+            wallet.add_sapling_note(SaplingNote {
+                diversifier: [0u8; 11],
+                value: 1000000,
+                note_commitment: NoteCommitment([position; 32]),
+                nullifier_key: Scalar([2u8; 32]),
+                randomness: Scalar([position; 32]),
+                position: position.into(),
+            });
+            txs.push(wallet.create_airdrop_tx(NoteId::new(Pool::Sapling, position.into()), 1000, &[4u8; 32]).unwrap());
+        }
+        txs
+    }
+
+    #[test]
+    fn test_relay_spreads_an_owners_claims_over_blocks() {
+        let txs = claims(3);
+
+        // Two claims of one owner, and one of a note held by no account
+        let dir = tempfile::tempdir().unwrap();
+        let queue = RelayQueue::open(dir.path()).unwrap();
+        queue.enqueue(&txs[0], Some([7u8; 32])).unwrap();
+        queue.enqueue(&txs[1], Some([7u8; 32])).unwrap();
+        queue.enqueue(&txs[2], None).unwrap();
+
+        let mut chain = LocalNamadaChain::new(AirdropPolicy::default());
+        let mut rng = StdRng::seed_from_u64(1);
+        let flush = queue.flush(&mut chain, 3, 10, &mut rng).unwrap();
+        assert_eq!((flush.submitted, flush.postponed), (2, 1));
+
+        // A later flush at the same height still holds the owner's claim back
+        assert_eq!(queue.flush(&mut chain, 1, 10, &mut rng).unwrap().postponed, 1);
+        chain.produce_block();
+        assert_eq!(queue.flush(&mut chain, 1, 10, &mut rng).unwrap().submitted, 1);
+        assert!(queue.is_empty().unwrap());
+        chain.produce_block();
+
+        let included = |tx: &ShieldedAirdropTransaction| match chain.claim_status(&tx.txid()).unwrap() {
+            ChainStatus::Included { block_height } => block_height,
+            status => panic!("claim not included: {:?}", status),
+        };
+        assert_ne!(included(&txs[0]), included(&txs[1]));
+        assert_eq!(included(&txs[2]), 1);
+    }
+
+    #[test]
+    fn test_relay_holds_an_owner_back_until_its_claim_resolves() {
+        let txs = claims(2);
+        let dir = tempfile::tempdir().unwrap();
+        let queue = RelayQueue::open(dir.path()).unwrap();
+        queue.enqueue(&txs[0], Some([7u8; 32])).unwrap();
+        queue.enqueue(&txs[1], Some([7u8; 32])).unwrap();
+
+        let mut chain = LocalNamadaChain::new(AirdropPolicy::default());
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(queue.flush(&mut chain, 1, 2, &mut rng).unwrap().submitted, 1);
+
+        // The node produces no block, then one without the claim it dropped
+        assert_eq!(queue.flush(&mut chain, 1, 2, &mut rng).unwrap().postponed, 1);
+        txs.iter().for_each(|tx| chain.evict(&tx.txid()));
+        chain.produce_block();
+        assert_eq!(queue.flush(&mut chain, 1, 2, &mut rng).unwrap().postponed, 1);
+
+        // Once the dropped claim has expired the owner's next claim goes out
+        chain.produce_block();
+        chain.produce_block();
+        assert_eq!(queue.flush(&mut chain, 1, 2, &mut rng).unwrap().submitted, 1);
+        assert!(queue.is_empty().unwrap());
+    }
+}
@@ -0,0 +1,505 @@
+//! Storage backends for the wallet database.
+//!
+//! A wallet keeps a handful of record kinds — notes, their nullifiers, claim
+//! transactions, metadata and keys — each in its own [`Table`] of byte keys
+//! and values. Records are sealed by the wallet before they reach the store,
+//! so a backend only has to read, list and atomically write opaque blobs.
+//! [`WalletStore`] is implemented by [`SledStore`], the original layout,
+//! [`SqliteStore`], one SQLite table per record kind, and [`MemoryStore`] for
+//! tests.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use sled::transaction::{ConflictableTransactionError, TransactionError};
+use sled::Transactional;
+use thiserror::Error;
+
+/// A kind of wallet record
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Table {
+    /// Wallet metadata, schema version, scan progress and the wrapped key
+    Metadata,
+    SaplingNotes,
+    OrchardNotes,
+    /// Zcash nullifiers of the wallet's notes, by note id
+    NoteNullifiers,
+    /// Zcash heights at which the wallet's notes were seen spent, by note id
+    NoteSpends,
+    /// Airdrop claims, by transaction hash
+    Transactions,
+    /// Seed phrase and account keys
+    Keys,
+    /// Zcash nullifiers seen spent
+    NullifierSet,
+    /// Airdrop nullifiers consumed by confirmed claims
+    AirdropNullifiers,
+    /// `height || nullifier` index of [`Table::AirdropNullifiers`]
+    AirdropNullifierHeights,
+    /// Scheduled claim submissions and decoy slots
+    ClaimSchedule,
+}
+
+impl Table {
+    pub const ALL: [Table; 11] = [
+        Table::Metadata,
+        Table::SaplingNotes,
+        Table::OrchardNotes,
+        Table::NoteNullifiers,
+        Table::NoteSpends,
+        Table::Transactions,
+        Table::Keys,
+        Table::NullifierSet,
+        Table::AirdropNullifiers,
+        Table::AirdropNullifierHeights,
+        Table::ClaimSchedule,
+    ];
+
+    /// Name of the table, and of its sled tree
+    pub fn name(self) -> &'static str {
+        match self {
+            Table::Metadata => "metadata",
+            Table::SaplingNotes => "sapling_notes",
+            Table::OrchardNotes => "orchard_notes",
+            Table::NoteNullifiers => "note_nullifiers",
+            Table::NoteSpends => "note_spends",
+            Table::Transactions => "transactions",
+            Table::Keys => "keys",
+            Table::NullifierSet => "nullifier_set",
+            Table::AirdropNullifiers => "airdrop_nullifier_set",
+            Table::AirdropNullifierHeights => "airdrop_nullifier_heights",
+            Table::ClaimSchedule => "claim_schedule",
+        }
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Errors returned by a [`WalletStore`]
+#[derive(Debug, Error)]
+pub enum StoreError {
+    #[error("sled error: {0}")]
+    Sled(#[from] sled::Error),
+
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+
+    /// A batch was abandoned part way; none of its writes were applied
+    #[error("wallet write interrupted")]
+    Interrupted,
+
+    /// A key written with [`StoreBatch::insert_new`] was already present;
+    /// none of the batch's writes were applied
+    #[error("{table} already holds key {}", hex::encode(.key))]
+    KeyExists { table: Table, key: Vec<u8> },
+}
+
+/// What a write does to its key
+#[derive(Debug)]
+enum Op {
+    Put(Vec<u8>),
+    /// Put, failing the whole batch if the key is already present
+    PutNew(Vec<u8>),
+    Remove,
+}
+
+impl Op {
+    fn value(&self) -> Option<&[u8]> {
+        match self {
+            Op::Put(value) | Op::PutNew(value) => Some(value),
+            Op::Remove => None,
+        }
+    }
+}
+
+/// A `(table, key, op)` write
+type Write = (Table, Vec<u8>, Op);
+
+/// A `(key, value)` entry of a table
+pub type Entry = (Vec<u8>, Vec<u8>);
+
+/// Writes across tables, applied together by [`WalletStore::commit`]
+#[derive(Debug, Default)]
+pub struct StoreBatch {
+    writes: Vec<Write>,
+    /// Abandon the commit before the write at this index, as a crash would
+    #[cfg(test)]
+    abort_at: Option<usize>,
+}
+
+impl StoreBatch {
+    pub fn insert(&mut self, table: Table, key: impl AsRef<[u8]>, value: impl Into<Vec<u8>>) {
+        self.writes.push((table, key.as_ref().to_vec(), Op::Put(value.into())));
+    }
+
+    /// Insert `key` only if it is absent when the batch commits; otherwise
+    /// the commit fails with [`StoreError::KeyExists`]. The check and the
+    /// write happen in one step, so two writers cannot both insert the key.
+    pub fn insert_new(&mut self, table: Table, key: impl AsRef<[u8]>, value: impl Into<Vec<u8>>) {
+        self.writes.push((table, key.as_ref().to_vec(), Op::PutNew(value.into())));
+    }
+
+    pub fn remove(&mut self, table: Table, key: impl AsRef<[u8]>) {
+        self.writes.push((table, key.as_ref().to_vec(), Op::Remove));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    /// The latest write staged for `key`: `Some(None)` if it is removed
+    pub fn staged(&self, table: Table, key: &[u8]) -> Option<Option<&[u8]>> {
+        self.writes
+            .iter()
+            .rev()
+            .find(|(t, k, _)| *t == table && k == key)
+            .map(|(_, _, op)| op.value())
+    }
+
+    /// Writes to `table` in the order they were staged, as `(key, value)`
+    /// with a missing value for removals
+    pub fn writes_to(&self, table: Table) -> impl Iterator<Item = (&[u8], Option<&[u8]>)> {
+        self.writes
+            .iter()
+            .filter(move |(t, _, _)| *t == table)
+            .map(|(_, key, op)| (key.as_slice(), op.value()))
+    }
+
+    fn tables(&self) -> Vec<Table> {
+        let mut tables: Vec<Table> = self.writes.iter().map(|(table, _, _)| *table).collect();
+        tables.sort_unstable();
+        tables.dedup();
+        tables
+    }
+
+    #[cfg(test)]
+    pub(crate) fn aborting_at(self, abort_at: Option<usize>) -> Self {
+        Self { abort_at, ..self }
+    }
+
+    #[cfg(test)]
+    fn aborts_at(&self, write: usize) -> bool {
+        self.abort_at == Some(write)
+    }
+
+    #[cfg(not(test))]
+    fn aborts_at(&self, _write: usize) -> bool {
+        false
+    }
+}
+
+/// Persistence for a wallet's records
+pub trait WalletStore: fmt::Debug + Send + Sync {
+    fn get(&self, table: Table, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError>;
+
+    fn contains_key(&self, table: Table, key: &[u8]) -> Result<bool, StoreError> {
+        Ok(self.get(table, key)?.is_some())
+    }
+
+    /// Every entry of `table`, ordered by key
+    fn entries(&self, table: Table) -> Result<Vec<Entry>, StoreError>;
+
+    /// Durably apply every write in `batch`, or none of them
+    fn commit(&self, batch: &StoreBatch) -> Result<(), StoreError>;
+}
+
+/// Which [`WalletStore`] holds the wallet at `wallet_path`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// sled database directory
+    #[default]
+    Sled,
+    /// SQLite database file
+    Sqlite,
+    /// Nothing is persisted past the process; for tests
+    Memory,
+}
+
+/// Open the `backend` store at `path`
+pub fn open_store(backend: StorageBackend, path: &Path) -> Result<Arc<dyn WalletStore>, StoreError> {
+    Ok(match backend {
+        StorageBackend::Sled => Arc::new(SledStore::open(path)?),
+        StorageBackend::Sqlite => Arc::new(SqliteStore::open(path)?),
+        StorageBackend::Memory => Arc::new(MemoryStore::new()),
+    })
+}
+
+/// Open the sled database at `path`.
+///
+/// sled releases its file lock from background threads after the last
+/// handle is dropped, so a database closed just before is retried briefly
+/// instead of being reported as in use.
+pub(crate) fn open_sled(path: &Path) -> Result<sled::Db, sled::Error> {
+    let deadline = Instant::now() + Duration::from_secs(2);
+    loop {
+        match sled::open(path) {
+            Err(sled::Error::Io(e))
+                if e.to_string().starts_with("could not acquire lock") && Instant::now() < deadline =>
+            {
+                thread::sleep(Duration::from_millis(10));
+            }
+            result => return result,
+        }
+    }
+}
+
+/// sled database with a tree per table; metadata lives in the default tree
+#[derive(Debug, Clone)]
+pub struct SledStore {
+    db: sled::Db,
+}
+
+impl SledStore {
+    pub fn open(path: &Path) -> Result<Self, StoreError> {
+        Ok(Self { db: open_sled(path)? })
+    }
+
+    fn tree(&self, table: Table) -> Result<sled::Tree, StoreError> {
+        if table == Table::Metadata {
+            return Ok((*self.db).clone());
+        }
+        Ok(self.db.open_tree(table.name())?)
+    }
+}
+
+impl WalletStore for SledStore {
+    fn get(&self, table: Table, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        Ok(self.tree(table)?.get(key)?.map(|value| value.to_vec()))
+    }
+
+    fn entries(&self, table: Table) -> Result<Vec<Entry>, StoreError> {
+        self.tree(table)?
+            .iter()
+            .map(|entry| {
+                let (key, value) = entry?;
+                Ok((key.to_vec(), value.to_vec()))
+            })
+            .collect()
+    }
+
+    fn commit(&self, batch: &StoreBatch) -> Result<(), StoreError> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        let tables = batch.tables();
+        let trees = tables.iter().map(|table| self.tree(*table)).collect::<Result<Vec<_>, _>>()?;
+        trees
+            .as_slice()
+            .transaction(|views| {
+                for (i, (table, key, op)) in batch.writes.iter().enumerate() {
+                    if batch.aborts_at(i) {
+                        return Err(ConflictableTransactionError::Abort(StoreError::Interrupted));
+                    }
+                    let view = &views[tables.binary_search(table).expect("tree opened for batch")];
+                    // The previous value is read inside the transaction, so
+                    // a concurrent insert of the key conflicts and retries
+                    let previous = match op {
+                        Op::Put(value) | Op::PutNew(value) => view.insert(key.as_slice(), value.as_slice())?,
+                        Op::Remove => view.remove(key.as_slice())?,
+                    };
+                    if matches!(op, Op::PutNew(_)) && previous.is_some() {
+                        return Err(ConflictableTransactionError::Abort(StoreError::KeyExists {
+                            table: *table,
+                            key: key.clone(),
+                        }));
+                    }
+                }
+                Ok(())
+            })
+            .map_err(|e| match e {
+                TransactionError::Abort(e) => e,
+                TransactionError::Storage(e) => StoreError::Sled(e),
+            })?;
+
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+/// SQLite database with a `(key, value)` table per record kind
+#[derive(Debug)]
+pub struct SqliteStore {
+    conn: Mutex<rusqlite::Connection>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &Path) -> Result<Self, StoreError> {
+        Self::init(rusqlite::Connection::open(path)?)
+    }
+
+    fn init(conn: rusqlite::Connection) -> Result<Self, StoreError> {
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        for table in Table::ALL {
+            conn.execute(
+                &format!(
+                    "CREATE TABLE IF NOT EXISTS {} (key BLOB PRIMARY KEY NOT NULL, value BLOB NOT NULL) WITHOUT ROWID",
+                    table.name()
+                ),
+                [],
+            )?;
+        }
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    fn conn(&self) -> std::sync::MutexGuard<'_, rusqlite::Connection> {
+        self.conn.lock().expect("SQLite connection poisoned")
+    }
+}
+
+impl WalletStore for SqliteStore {
+    fn get(&self, table: Table, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        let conn = self.conn();
+        let mut statement = conn.prepare_cached(&format!("SELECT value FROM {} WHERE key = ?1", table.name()))?;
+        Ok(statement.query_row([key], |row| row.get(0)).optional()?)
+    }
+
+    fn entries(&self, table: Table) -> Result<Vec<Entry>, StoreError> {
+        let conn = self.conn();
+        let mut statement = conn.prepare_cached(&format!("SELECT key, value FROM {} ORDER BY key", table.name()))?;
+        let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    fn commit(&self, batch: &StoreBatch) -> Result<(), StoreError> {
+        let mut conn = self.conn();
+        // Dropping the transaction without committing rolls it back
+        let tx = conn.transaction()?;
+        for (i, (table, key, op)) in batch.writes.iter().enumerate() {
+            if batch.aborts_at(i) {
+                return Err(StoreError::Interrupted);
+            }
+            match op {
+                Op::Put(value) => tx.execute(
+                    &format!("INSERT OR REPLACE INTO {} (key, value) VALUES (?1, ?2)", table.name()),
+                    (key, value),
+                )?,
+                Op::PutNew(value) => {
+                    let inserted = tx.execute(
+                        &format!("INSERT INTO {} (key, value) VALUES (?1, ?2) ON CONFLICT DO NOTHING", table.name()),
+                        (key, value),
+                    )?;
+                    if inserted == 0 {
+                        return Err(StoreError::KeyExists { table: *table, key: key.clone() });
+                    }
+                    inserted
+                }
+                Op::Remove => tx.execute(&format!("DELETE FROM {} WHERE key = ?1", table.name()), [key])?,
+            };
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+type MemoryTable = BTreeMap<Vec<u8>, Vec<u8>>;
+
+/// In-memory store, lost when the process exits
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    tables: Mutex<BTreeMap<Table, MemoryTable>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl WalletStore for MemoryStore {
+    fn get(&self, table: Table, key: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        let tables = self.tables.lock().expect("memory store poisoned");
+        Ok(tables.get(&table).and_then(|entries| entries.get(key)).cloned())
+    }
+
+    fn entries(&self, table: Table) -> Result<Vec<Entry>, StoreError> {
+        let tables = self.tables.lock().expect("memory store poisoned");
+        Ok(tables
+            .get(&table)
+            .map(|entries| entries.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+            .unwrap_or_default())
+    }
+
+    fn commit(&self, batch: &StoreBatch) -> Result<(), StoreError> {
+        let mut tables = self.tables.lock().expect("memory store poisoned");
+        // Apply to a copy so an abandoned batch leaves the store untouched
+        let mut updated = tables.clone();
+        for (i, (table, key, op)) in batch.writes.iter().enumerate() {
+            if batch.aborts_at(i) {
+                return Err(StoreError::Interrupted);
+            }
+            let entries = updated.entry(*table).or_default();
+            let previous = match op {
+                Op::Put(value) | Op::PutNew(value) => entries.insert(key.clone(), value.clone()),
+                Op::Remove => entries.remove(key),
+            };
+            if matches!(op, Op::PutNew(_)) && previous.is_some() {
+                return Err(StoreError::KeyExists { table: *table, key: key.clone() });
+            }
+        }
+        *tables = updated;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backends_commit_batches_atomically() {
+        let dir = tempfile::tempdir().unwrap();
+        let stores: [Arc<dyn WalletStore>; 3] = [
+            open_store(StorageBackend::Sled, &dir.path().join("wallet.db")).unwrap(),
+            open_store(StorageBackend::Sqlite, &dir.path().join("wallet.sqlite")).unwrap(),
+            open_store(StorageBackend::Memory, dir.path()).unwrap(),
+        ];
+
+        for store in stores {
+            let mut batch = StoreBatch::default();
+            batch.insert(Table::Metadata, "b", b"2".to_vec());
+            batch.insert(Table::Metadata, "a", b"1".to_vec());
+            batch.insert(Table::Keys, "seed", b"sealed".to_vec());
+            store.commit(&batch).unwrap();
+            assert_eq!(
+                store.entries(Table::Metadata).unwrap(),
+                vec![(b"a".to_vec(), b"1".to_vec()), (b"b".to_vec(), b"2".to_vec())]
+            );
+            assert!(store.entries(Table::SaplingNotes).unwrap().is_empty());
+
+            // An abandoned batch applies none of its writes
+            let mut batch = StoreBatch::default();
+            batch.remove(Table::Keys, "seed");
+            batch.insert(Table::Transactions, "tx", b"claim".to_vec());
+            batch.abort_at = Some(1);
+            assert!(matches!(store.commit(&batch), Err(StoreError::Interrupted)));
+            assert!(store.contains_key(Table::Keys, b"seed").unwrap());
+            assert_eq!(store.get(Table::Transactions, b"tx").unwrap(), None);
+
+            // A key inserted only when new fails the batch once present
+            let mut batch = StoreBatch::default();
+            batch.insert_new(Table::AirdropNullifiers, "nf", b"first".to_vec());
+            store.commit(&batch).unwrap();
+            let mut batch = StoreBatch::default();
+            batch.insert(Table::Transactions, "tx", b"claim".to_vec());
+            batch.insert_new(Table::AirdropNullifiers, "nf", b"second".to_vec());
+            assert!(matches!(
+                store.commit(&batch),
+                Err(StoreError::KeyExists { table: Table::AirdropNullifiers, .. })
+            ));
+            assert_eq!(store.get(Table::AirdropNullifiers, b"nf").unwrap(), Some(b"first".to_vec()));
+            assert_eq!(store.get(Table::Transactions, b"tx").unwrap(), None);
+        }
+    }
+}
@@ -1,15 +1,35 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::Path;
-use std::collections::HashMap;
+use std::sync::Arc;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use anyhow::{Result, Context};
-use sled;
-use tracing::{info, warn, error};
+use tracing::{error, info};
+use zeroize::Zeroizing;
+use rand::seq::SliceRandom;
+use rand::Rng;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::consistency::Discrepancy;
+use crate::eligibility::{assess_notes, EligibilityReport, EligibilityRules, ProofMaterials};
+use crate::encryption::{DataKey, WrappedKey};
+use crate::export::{ExportedNote, WalletExport, EXPORT_VERSION};
+use crate::keys::{coin_type, AccountKeyRecord, AccountKeys, ViewingAccount, WalletSeed};
+use crate::import::ExpectedNote;
+use crate::index::{ClaimState, NoteIndex, NotePage, NoteQuery};
+use crate::namada::{ChainStatus, NamadaClient, NamadaError};
+use crate::planner::{plan_claims, ClaimPlan, PlanOptions};
+use crate::scheduler::{ClaimOwner, ScheduleRun, ScheduledClaim, ScheduledSlot, SchedulePolicy};
+use crate::store::{SledStore, StoreBatch, Table, WalletStore};
+use crate::scanner::{
+    consensus_network, CompactBlock, Discovered, ScanKeys, ScanState, ScanSummary, Scanner,
+};
 use crate::{
-    AirdropWallet as CoreWallet, SaplingNote, OrchardNote, 
-    ShieldedAirdropTransaction, NullifierSet, ProtocolError, PublicKey, ClaimDescription
+    checked_value_sum, AirdropPolicy, AirdropWallet as CoreWallet, ConsumedNullifier, NoteCommitment, NoteId, Nullifier,
+    OrchardNote, Pool, PublicKey, SaplingNote, Scalar, ShieldedAirdropTransaction,
+    StoreNullifierRegistry, PartialAirdropTransaction,
 };
+use zcash_primitives::consensus::{NetworkType, Parameters};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WalletMetadata {
@@ -20,33 +40,253 @@ pub struct WalletMetadata {
     pub version: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Where a note stands on Zcash and in the airdrop.
+///
+/// The two are independent: a note spent on Zcash after the snapshot can
+/// still claim, and claiming does not spend the note on Zcash.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoteStatus {
+    /// Spent on the Zcash chain
+    pub is_spent: bool,
+    /// Claimed in the airdrop by a confirmed claim
+    pub is_claimed: bool,
+    /// Hash of the unfinished claim holding the note
+    pub reserved_by: Option<String>,
+}
+
+impl NoteStatus {
+    /// Whether a new claim of the note may be built
+    pub fn is_claimable(&self) -> bool {
+        !self.is_claimed && self.reserved_by.is_none()
+    }
+    
+    /// The note's airdrop claim state
+    pub fn claim_state(&self) -> ClaimState {
+        match (self.is_claimed, &self.reserved_by) {
+            (true, _) => ClaimState::Claimed,
+            (false, Some(_)) => ClaimState::Reserved,
+            (false, None) => ClaimState::Unclaimed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct NoteMetadata {
-    pub note_type: String, // "sapling" or "orchard"
+    pub pool: Pool,
+    /// Account whose viewing keys find the note; none for notes added without one
+    #[serde(default)]
+    pub account: Option<u32>,
     pub value: u64,
     pub position: u64,
-    pub is_spent: bool,
+    pub airdrop_nullifier: Nullifier,
+    pub status: NoteStatus,
     pub created_at: u64,
     pub last_used: Option<u64>,
 }
 
+impl NoteMetadata {
+    pub fn id(&self) -> NoteId {
+        NoteId::new(self.pool, self.position)
+    }
+}
+
+/// Note values of one pool by Zcash and airdrop status
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolBalance {
+    /// Notes not spent on Zcash
+    pub unspent: u64,
+    /// Notes that can still claim the airdrop
+    pub claimable: u64,
+    /// Notes held by unfinished claims
+    pub reserved: u64,
+    /// Notes already claimed
+    pub claimed: u64,
+}
+
+impl PoolBalance {
+    fn add(&mut self, value: u64, status: &NoteStatus) -> Result<()> {
+        if !status.is_spent {
+            self.unspent = checked_value_sum([self.unspent, value])?;
+        }
+        let total = self.claim_total(status);
+        *total = checked_value_sum([*total, value])?;
+        Ok(())
+    }
+    
+    fn subtract(&mut self, value: u64, status: &NoteStatus) -> Result<()> {
+        let underflow = || anyhow::anyhow!("Balance underflow removing a note of value {}", value);
+        if !status.is_spent {
+            self.unspent = self.unspent.checked_sub(value).ok_or_else(underflow)?;
+        }
+        let total = self.claim_total(status);
+        *total = total.checked_sub(value).ok_or_else(underflow)?;
+        Ok(())
+    }
+    
+    fn claim_total(&mut self, status: &NoteStatus) -> &mut u64 {
+        match status.claim_state() {
+            ClaimState::Claimed => &mut self.claimed,
+            ClaimState::Reserved => &mut self.reserved,
+            ClaimState::Unclaimed => &mut self.claimable,
+        }
+    }
+}
+
+/// Balance of the wallet's notes per pool
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Balance {
+    pub sapling: PoolBalance,
+    pub orchard: PoolBalance,
+}
+
+impl Balance {
+    pub fn pool(&self, pool: Pool) -> &PoolBalance {
+        match pool {
+            Pool::Sapling => &self.sapling,
+            Pool::Orchard => &self.orchard,
+        }
+    }
+    
+    fn pool_mut(&mut self, pool: Pool) -> &mut PoolBalance {
+        match pool {
+            Pool::Sapling => &mut self.sapling,
+            Pool::Orchard => &mut self.orchard,
+        }
+    }
+    
+    pub(crate) fn add(&mut self, pool: Pool, value: u64, status: &NoteStatus) -> Result<()> {
+        self.pool_mut(pool).add(value, status)
+    }
+    
+    pub(crate) fn subtract(&mut self, pool: Pool, value: u64, status: &NoteStatus) -> Result<()> {
+        self.pool_mut(pool).subtract(value, status)
+    }
+}
+
+/// Outcome of [`AirdropWallet::import_notes`]
+#[derive(Debug, Clone, Default)]
+pub struct ImportSummary {
+    pub imported: usize,
+    pub duplicates: usize,
+    /// Notes reported by the source wallet that were not found on chain
+    pub missing: Vec<ExpectedNote>,
+}
+
+/// Outcome of [`AirdropWallet::rescan`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RescanSummary {
+    /// Notes dropped to be found again
+    pub notes_removed: usize,
+    /// Kept notes whose spent flag was cleared
+    pub notes_reset: usize,
+    pub scan: ScanSummary,
+}
+
+/// How [`AirdropWallet::import_data`] treats the wallet's existing records
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Drop existing notes, claims and nullifiers first
+    Replace,
+    /// Keep existing records and add what the wallet lacks
+    Merge,
+}
+
+/// Outcome of importing a wallet export
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WalletImportSummary {
+    pub notes: usize,
+    /// Exported notes the wallet already held
+    pub duplicates: usize,
+    pub claims: usize,
+}
+
+/// Lifecycle of a claim submitted to Namada.
+///
+/// A claim is `Pending` once built, `Broadcast` once accepted into a node's
+/// mempool, and ends `Confirmed` when included in a block, `Failed` when
+/// rejected, or `Expired` when not included by its expiry height.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ClaimStatus {
+    Pending,
+    Broadcast,
+    Confirmed,
+    Failed,
+    Expired,
+}
+
+impl ClaimStatus {
+    pub const ALL: [ClaimStatus; 5] = [
+        ClaimStatus::Pending,
+        ClaimStatus::Broadcast,
+        ClaimStatus::Confirmed,
+        ClaimStatus::Failed,
+        ClaimStatus::Expired,
+    ];
+    
+    /// Whether a claim may move from this status to `next`
+    pub fn can_transition_to(self, next: ClaimStatus) -> bool {
+        use ClaimStatus::*;
+        matches!(
+            (self, next),
+            (Pending, Broadcast) | (Pending, Failed) | (Broadcast, Confirmed) | (Broadcast, Failed) | (Broadcast, Expired)
+        )
+    }
+    
+    /// Whether the claim did not and will never consume its note
+    pub fn is_abandoned(self) -> bool {
+        matches!(self, ClaimStatus::Failed | ClaimStatus::Expired)
+    }
+}
+
+impl std::fmt::Display for ClaimStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            ClaimStatus::Pending => "pending",
+            ClaimStatus::Broadcast => "broadcast",
+            ClaimStatus::Confirmed => "confirmed",
+            ClaimStatus::Failed => "failed",
+            ClaimStatus::Expired => "expired",
+        };
+        f.pad(name)
+    }
+}
+
+impl std::str::FromStr for ClaimStatus {
+    type Err = anyhow::Error;
+    
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|status| status.to_string() == s)
+            .ok_or_else(|| anyhow::anyhow!("Unknown claim status: {}", s))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransactionRecord {
     pub tx_hash: String,
     pub airdrop_nullifier: Vec<u8>,
+    /// The wallet note the claim spends, if it is one of ours
+    pub note: Option<NoteId>,
     pub amount: u64,
     pub recipient: String,
-    pub status: String, // "pending", "confirmed", "failed"
+    pub status: ClaimStatus,
     pub created_at: u64,
+    /// Namada height at which the claim was broadcast
+    pub broadcast_height: Option<u64>,
+    /// Last Namada height at which the claim may still be included
+    pub expiry_height: Option<u64>,
     pub confirmed_at: Option<u64>,
     pub block_height: Option<u64>,
+    /// Why the node rejected the claim
+    pub failure: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SaplingNoteRecord {
     pub note: SaplingNote,
     pub created_at: u64,
-    pub is_spent: bool,
+    pub status: NoteStatus,
     pub last_used: Option<u64>,
 }
 
@@ -54,22 +294,95 @@ pub struct SaplingNoteRecord {
 pub struct OrchardNoteRecord {
     pub note: OrchardNote,
     pub created_at: u64,
-    pub is_spent: bool,
+    pub status: NoteStatus,
     pub last_used: Option<u64>,
 }
 
+impl SaplingNoteRecord {
+    /// Summary of the note, attributed to whichever of `owners` holds it
+    pub fn metadata(&self, owners: &[ViewingAccount]) -> Result<NoteMetadata> {
+        Ok(NoteMetadata {
+            pool: Pool::Sapling,
+            account: note_account(owners, Pool::Sapling, &self.note.nullifier_key),
+            value: self.note.value,
+            position: self.note.position,
+            airdrop_nullifier: self.note.airdrop_nullifier()?,
+            status: self.status.clone(),
+            created_at: self.created_at,
+            last_used: self.last_used,
+        })
+    }
+}
+
+impl OrchardNoteRecord {
+    /// Summary of the note, attributed to whichever of `owners` holds it
+    pub fn metadata(&self, owners: &[ViewingAccount]) -> Result<NoteMetadata> {
+        Ok(NoteMetadata {
+            pool: Pool::Orchard,
+            account: note_account(owners, Pool::Orchard, &self.note.nullifier_key),
+            value: self.note.value,
+            position: self.note.position,
+            airdrop_nullifier: self.note.airdrop_nullifier()?,
+            status: self.status.clone(),
+            created_at: self.created_at,
+            last_used: self.last_used,
+        })
+    }
+}
+
+/// Layout version of the wallet database, stored under `schema_version`.
+///
+/// 0. Unversioned: note records carry a single `is_spent` flag set both by
+///    Zcash spends and by building a claim, and claim records a free-form
+///    status string.
+/// 1. Note records keep Zcash spends, airdrop claims and claim reservations
+///    apart; claim records carry a [`ClaimStatus`] and the note they spend.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Trees whose records are encrypted with the wallet's data key
+const ENCRYPTED_TABLES: [Table; 7] = [
+    Table::SaplingNotes,
+    Table::OrchardNotes,
+    Table::Transactions,
+    Table::Keys,
+    Table::NoteNullifiers,
+    Table::NoteSpends,
+    Table::ClaimSchedule,
+];
+
+/// Sled-backed wallet whose note and transaction records are encrypted at rest.
+///
+/// A wallet is either unlocked, holding the data key and the decrypted notes
+/// in its core wallet together with the account spending keys, or locked, in
+/// which case only the metadata and the airdrop nullifier registry are
+/// available.
+///
+/// A watch-only wallet is created from a unified full viewing key and never
+/// holds spending keys: it scans and prepares unsigned claims for a separate
+/// signer.
 pub struct AirdropWallet {
-    db: sled::Db,
+    store: Arc<dyn WalletStore>,
     core_wallet: CoreWallet,
     metadata: WalletMetadata,
+    data_key: Option<DataKey>,
+    accounts: BTreeMap<u32, AccountKeys>,
+    watch_accounts: BTreeMap<u32, ViewingAccount>,
+    /// Decrypted note summaries, kept in step with every committed write
+    notes: NoteIndex,
+    /// Abort the next commit before its write at this index, as a crash would
+    #[cfg(test)]
+    crash_after_writes: Option<usize>,
 }
 
 impl AirdropWallet {
-    pub fn new(path: &Path, name: &str, network: &str) -> Result<Self> {
-        let db = sled::open(path)
-            .with_context(|| format!("Failed to open wallet database: {:?}", path))?;
-        
-        let core_wallet = CoreWallet::new();
+    /// Create a wallet in a sled database at `path`
+    pub fn new(path: &Path, name: &str, network: &str, passphrase: &str) -> Result<Self> {
+        Self::create(Self::open_sled(path)?, name, network, passphrase)
+    }
+    
+    /// Create a wallet in `store`
+    pub fn create(store: Arc<dyn WalletStore>, name: &str, network: &str, passphrase: &str) -> Result<Self> {
+        let core_wallet = Self::open_core_wallet(&store);
         
         let metadata = WalletMetadata {
             name: name.to_string(),
@@ -83,99 +396,252 @@ impl AirdropWallet {
         };
         
         let mut wallet = Self {
-            db,
+            store,
             core_wallet,
             metadata,
+            data_key: None,
+            accounts: BTreeMap::new(),
+            watch_accounts: BTreeMap::new(),
+            notes: NoteIndex::default(),
+            #[cfg(test)]
+            crash_after_writes: None,
         };
         
         // Initialize database
         wallet.init_database()?;
         
+        let data_key = DataKey::generate();
+        wallet.store_wrapped_key(&WrappedKey::wrap(&data_key, passphrase)?)?;
+        wallet.data_key = Some(data_key);
+        
         Ok(wallet)
     }
     
-    pub fn load(path: &Path) -> Result<Self> {
-        let db = sled::open(path)
-            .with_context(|| format!("Failed to open wallet database: {:?}", path))?;
-        
-        let core_wallet = CoreWallet::new();
+    /// Create a watch-only wallet for the account viewed by `ufvk`
+    pub fn new_watch_only(path: &Path, name: &str, network: &str, passphrase: &str, ufvk: &str) -> Result<Self> {
+        let mut wallet = Self::new(path, name, network, passphrase)?;
+        wallet.import_viewing_key(ufvk)?;
+        Ok(wallet)
+    }
+    
+    /// Open and unlock an existing wallet
+    pub fn load(path: &Path, passphrase: &str) -> Result<Self> {
+        let mut wallet = Self::open_locked(path)?;
+        wallet.unlock(passphrase)?;
+        Ok(wallet)
+    }
+    
+    /// Open an existing sled wallet without decrypting any of its records
+    pub fn open_locked(path: &Path) -> Result<Self> {
+        Self::open_store(Self::open_sled(path)?)
+    }
+    
+    /// Open the wallet in `store` without decrypting any of its records
+    pub fn open_store(store: Arc<dyn WalletStore>) -> Result<Self> {
+        let core_wallet = Self::open_core_wallet(&store);
         
         // Load metadata
-        let metadata_bytes = db.get("metadata")?
+        let metadata_bytes = store.get(Table::Metadata, b"metadata")?
             .ok_or_else(|| anyhow::anyhow!("Wallet metadata not found"))?;
         let metadata: WalletMetadata = bincode::deserialize(&metadata_bytes)
             .with_context(|| "Failed to deserialize wallet metadata")?;
         
-        let mut wallet = Self {
-            db,
+        let schema_version = Self::read_schema_version(store.as_ref())?;
+        if schema_version > SCHEMA_VERSION {
+            anyhow::bail!(
+                "Wallet schema version {} is newer than the supported version {}; upgrade zec-nam to open it",
+                schema_version,
+                SCHEMA_VERSION
+            );
+        }
+        
+        Ok(Self {
+            store,
             core_wallet,
             metadata,
+            data_key: None,
+            accounts: BTreeMap::new(),
+            watch_accounts: BTreeMap::new(),
+            notes: NoteIndex::default(),
+            #[cfg(test)]
+            crash_after_writes: None,
+        })
+    }
+    
+    pub fn is_locked(&self) -> bool {
+        self.data_key.is_none()
+    }
+    
+    /// Derive the data key from `passphrase` and load the decrypted notes and keys
+    pub fn unlock(&mut self, passphrase: &str) -> Result<()> {
+        let data_key = match self.wrapped_key()? {
+            Some(wrapped) => wrapped.unwrap(passphrase)?,
+            None => self.encrypt_plaintext_records(passphrase)?,
         };
+        self.data_key = Some(data_key);
         
-        // Load notes
-        wallet.load_notes()?;
+        self.clear_notes();
+        // Accounts come first so that notes are indexed under their owners
+        if let Err(e) = self.load_accounts().and_then(|_| self.migrate()).and_then(|_| self.load_notes()) {
+            self.lock();
+            return Err(e);
+        }
         
-        Ok(wallet)
+        Ok(())
     }
     
-    fn init_database(&mut self) -> Result<()> {
-        // Store metadata
-        let metadata_bytes = bincode::serialize(&self.metadata)
-            .with_context(|| "Failed to serialize wallet metadata")?;
-        self.db.insert("metadata", metadata_bytes)?;
+    /// Forget the data key, the spending keys and every decrypted note
+    pub fn lock(&mut self) {
+        self.data_key = None;
+        self.accounts.clear();
+        self.watch_accounts.clear();
+        self.clear_notes();
+    }
+    
+    /// Rewrap the data key under a new passphrase
+    pub fn change_passphrase(&mut self, old_passphrase: &str, new_passphrase: &str) -> Result<()> {
+        let wrapped = self.wrapped_key()?
+            .ok_or_else(|| anyhow::anyhow!("Wallet has no encryption key; unlock it first"))?;
+        let data_key = wrapped.unwrap(old_passphrase)?;
         
-        // Initialize empty collections
-        self.db.insert("sapling_notes", b"")?;
-        self.db.insert("orchard_notes", b"")?;
-        self.db.insert("transactions", b"")?;
-        self.db.insert("nullifier_set", b"")?;
-        self.db.insert("airdrop_nullifier_set", b"")?;
+        self.store_wrapped_key(&WrappedKey::wrap(&data_key, new_passphrase)?)?;
+        info!("Changed wallet passphrase");
         
-        self.db.flush()?;
-        info!("Initialized wallet database");
-         
         Ok(())
     }
     
-    fn load_notes(&mut self) -> Result<()> {
-        // Load Sapling notes
-        let sapling_tree = self.db.open_tree("sapling_notes")?;
-        for result in sapling_tree.iter() {
-            let (key, value) = result?;
-            if let Ok(note_record) = bincode::deserialize::<SaplingNoteRecord>(&value) {
-                self.core_wallet.add_sapling_note(note_record.note);
-            }
+    fn clear_notes(&mut self) {
+        self.core_wallet.sapling_notes.clear();
+        self.core_wallet.orchard_notes.clear();
+        self.core_wallet.nullifier_set.nullifiers.clear();
+        self.notes.clear();
+    }
+    
+    fn wrapped_key(&self) -> Result<Option<WrappedKey>> {
+        match self.store.get(Table::Metadata, b"wrapped_key")? {
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes)
+                .with_context(|| "Failed to deserialize wrapped wallet key")?)),
+            None => Ok(None),
         }
+    }
+    
+    fn store_wrapped_key(&mut self, wrapped: &WrappedKey) -> Result<()> {
+        let bytes = bincode::serialize(wrapped)
+            .with_context(|| "Failed to serialize wrapped wallet key")?;
+        let mut batch = StoreBatch::default();
+        batch.insert(Table::Metadata, "wrapped_key", bytes);
+        self.commit(batch)
+    }
+    
+    /// Encrypt the records of a wallet created before encryption at rest
+    fn encrypt_plaintext_records(&mut self, passphrase: &str) -> Result<DataKey> {
+        let data_key = DataKey::generate();
         
-        // Load Orchard notes
-        let orchard_tree = self.db.open_tree("orchard_notes")?;
-        for result in orchard_tree.iter() {
-            let (key, value) = result?;
-            if let Ok(note_record) = bincode::deserialize::<OrchardNoteRecord>(&value) {
-                self.core_wallet.add_orchard_note(note_record.note);
+        // Records and the key that opens them are written together, so an
+        // interrupted upgrade leaves the wallet still in plaintext
+        let mut batch = StoreBatch::default();
+        for table in ENCRYPTED_TABLES {
+            for (key, value) in self.store.entries(table)? {
+                batch.insert(table, &key, data_key.encrypt(&value, &key));
             }
         }
+        let wrapped = bincode::serialize(&WrappedKey::wrap(&data_key, passphrase)?)
+            .with_context(|| "Failed to serialize wrapped wallet key")?;
+        batch.insert(Table::Metadata, "wrapped_key", wrapped);
+        self.commit(batch)?;
+        info!("Encrypted existing wallet records");
         
-        // Load nullifier sets
-        let nullifier_tree = self.db.open_tree("nullifier_set")?;
-        for result in nullifier_tree.iter() {
-            let (key, _) = result?;
-            let nullifier: Vec<u8> = key.to_vec();
-            if nullifier.len() == 32 {
-                let mut arr = [0u8; 32];
-                arr.copy_from_slice(&nullifier);
-                self.core_wallet.nullifier_set.insert(arr);
+        Ok(data_key)
+    }
+    
+    fn data_key(&self) -> Result<&DataKey> {
+        self.data_key.as_ref().ok_or_else(|| anyhow::anyhow!("Wallet is locked"))
+    }
+    
+    /// Serialize and encrypt a record stored under `key`
+    fn seal_record<T: Serialize>(&self, key: &[u8], record: &T) -> Result<Vec<u8>> {
+        let bytes = bincode::serialize(record)
+            .with_context(|| "Failed to serialize wallet record")?;
+        Ok(self.data_key()?.encrypt(&bytes, key))
+    }
+    
+    /// Decrypt and deserialize a record stored under `key`
+    fn open_record<T: DeserializeOwned>(&self, key: &[u8], sealed: &[u8]) -> Result<T> {
+        let bytes = self.data_key()?.decrypt(sealed, key)?;
+        bincode::deserialize(&bytes).with_context(|| "Failed to deserialize wallet record")
+    }
+    
+    /// Decrypt every record of `table`, failing with the keys of all the
+    /// records that cannot be read rather than skipping them
+    fn open_records<T: DeserializeOwned>(&self, table: Table) -> Result<Vec<(String, T)>> {
+        let mut records = Vec::new();
+        let mut corrupt = Vec::new();
+        for (key, value) in self.store.entries(table)? {
+            let key = String::from_utf8_lossy(&key).into_owned();
+            match self.open_record(key.as_bytes(), &value) {
+                Ok(record) => records.push((key, record)),
+                Err(e) => {
+                    error!("Corrupt record {} in {}: {:#}", key, table, e);
+                    corrupt.push(key);
+                }
             }
         }
         
-        let airdrop_tree = self.db.open_tree("airdrop_nullifier_set")?;
-        for result in airdrop_tree.iter() {
-            let (key, _) = result?;
-            let nullifier: Vec<u8> = key.to_vec();
+        if !corrupt.is_empty() {
+            anyhow::bail!("{} corrupt record(s) in {}: {}", corrupt.len(), table, corrupt.join(", "));
+        }
+        Ok(records)
+    }
+    
+    fn open_sled(path: &Path) -> Result<Arc<dyn WalletStore>> {
+        let store = SledStore::open(path)
+            .with_context(|| format!("Failed to open wallet database: {:?}", path))?;
+        Ok(Arc::new(store))
+    }
+    
+    fn open_core_wallet(store: &Arc<dyn WalletStore>) -> CoreWallet {
+        // Claims processed by this wallet are recorded in its own database
+        CoreWallet::with_registry(Box::new(StoreNullifierRegistry::new(store.clone())))
+    }
+    
+    fn init_database(&mut self) -> Result<()> {
+        // Store metadata
+        let metadata_bytes = bincode::serialize(&self.metadata)
+            .with_context(|| "Failed to serialize wallet metadata")?;
+        let mut batch = StoreBatch::default();
+        batch.insert(Table::Metadata, "metadata", metadata_bytes);
+        batch.insert(Table::Metadata, "schema_version", SCHEMA_VERSION.to_be_bytes());
+        self.commit(batch)?;
+        info!("Initialized wallet database");
+         
+        Ok(())
+    }
+    
+    /// Decrypt the notes again, e.g. once a new account may own some of them
+    fn reload_notes(&mut self) -> Result<()> {
+        self.clear_notes();
+        self.load_notes()
+    }
+    
+    fn load_notes(&mut self) -> Result<()> {
+        let owners = self.viewing_accounts();
+        for (_, note_record) in self.open_records::<SaplingNoteRecord>(Table::SaplingNotes)? {
+            self.notes.insert(note_record.metadata(&owners)?)?;
+            self.core_wallet.add_sapling_note(note_record.note);
+        }
+        for (_, note_record) in self.open_records::<OrchardNoteRecord>(Table::OrchardNotes)? {
+            self.notes.insert(note_record.metadata(&owners)?)?;
+            self.core_wallet.add_orchard_note(note_record.note);
+        }
+        
+        // Load the Zcash nullifier set; airdrop nullifiers are read from the
+        // registry on demand
+        for (key, _) in self.store.entries(Table::NullifierSet)? {
+            let nullifier: Vec<u8> = key;
             if nullifier.len() == 32 {
                 let mut arr = [0u8; 32];
                 arr.copy_from_slice(&nullifier);
-                self.core_wallet.airdrop_nullifier_set.insert(arr);
+                self.core_wallet.nullifier_set.insert(Nullifier(arr));
             }
         }
         
@@ -186,270 +652,861 @@ impl AirdropWallet {
         Ok(())
     }
     
-    pub fn add_sapling_note(&mut self, note: SaplingNote) -> Result<()> {
-        let note_id = format!("sapling_{}", note.position);
-        let created_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
-        
-        // Create note record with metadata
-        let note_record = SaplingNoteRecord {
-            note: note.clone(),
-            created_at,
-            is_spent: false,
-            last_used: None,
-        };
-        
-        // Store in database
-        let note_bytes = bincode::serialize(&note_record)
-            .with_context(|| "Failed to serialize Sapling note record")?;
+    fn load_accounts(&mut self) -> Result<()> {
+        let keys = self.store.entries(Table::Keys)?;
+        for (key, value) in keys.iter().filter(|(key, _)| key.starts_with(b"account_")) {
+            let record: AccountKeyRecord = self.open_record(key, value)
+                .with_context(|| "Failed to decrypt account keys")?;
+            self.accounts.insert(record.account, AccountKeys::from_record(&record)?);
+        }
+        for (key, value) in keys.iter().filter(|(key, _)| key.starts_with(b"viewing_")) {
+            let viewing: ViewingAccount = self.open_record(key, value)
+                .with_context(|| "Failed to decrypt viewing keys")?;
+            self.watch_accounts.insert(viewing.account, viewing);
+        }
+        Ok(())
+    }
+    
+    /// Whether a seed phrase has been stored in this wallet
+    pub fn has_seed(&self) -> Result<bool> {
+        Ok(self.store.contains_key(Table::Keys, b"seed")?)
+    }
+    
+    /// Store `seed` and derive its first account
+    pub fn import_seed(&mut self, seed: &WalletSeed) -> Result<()> {
+        if self.has_seed()? {
+            anyhow::bail!("Wallet already has a seed phrase");
+        }
+        if self.is_watch_only() {
+            anyhow::bail!("Watch-only wallets cannot hold spending keys");
+        }
         
-        let tree = self.db.open_tree("sapling_notes")?;
-        tree.insert(note_id.as_bytes(), note_bytes)?;
-        tree.flush()?;
+        let keys = seed.derive_account(self.coin_type(), 0)?;
+        let mut batch = StoreBatch::default();
+        batch.insert(Table::Keys, "seed", self.seal_record(b"seed", &*seed.phrase())?);
+        self.stage_account(&mut batch, &keys)?;
+        self.commit(batch)?;
+        self.accounts.insert(keys.account(), keys);
+        self.reload_notes()?;
         
-        // Add to core wallet
-        self.core_wallet.add_sapling_note(note);
+        info!("Imported wallet seed");
+        Ok(())
+    }
+    
+    /// Derive the next unused ZIP-32 account from the stored seed
+    pub fn add_account(&mut self) -> Result<u32> {
+        let sealed = self.store.get(Table::Keys, b"seed")?
+            .ok_or_else(|| anyhow::anyhow!("Wallet has no seed phrase"))?;
+        let phrase: Zeroizing<String> = Zeroizing::new(self.open_record(b"seed", &sealed)?);
+        let seed = WalletSeed::from_phrase(&phrase)?;
         
-        info!("Added Sapling note with value {} at position {}", note.value, note.position);
+        let account = self.accounts.keys().next_back().map_or(0, |a| a + 1);
+        let keys = seed.derive_account(self.coin_type(), account)?;
+        let mut batch = StoreBatch::default();
+        self.stage_account(&mut batch, &keys)?;
+        self.commit(batch)?;
+        self.accounts.insert(account, keys);
+        self.reload_notes()?;
         
+        info!("Added account {}", account);
+        Ok(account)
+    }
+    
+    fn stage_account(&self, batch: &mut StoreBatch, keys: &AccountKeys) -> Result<()> {
+        let key = format!("account_{}", keys.account());
+        batch.insert(Table::Keys, &key, self.seal_record(key.as_bytes(), &keys.to_record())?);
         Ok(())
     }
     
-    pub fn add_orchard_note(&mut self, note: OrchardNote) -> Result<()> {
-        let note_id = format!("orchard_{}", note.position);
-        let created_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    /// Add a watch-only account viewed by the unified full viewing key `ufvk`
+    pub fn import_viewing_key(&mut self, ufvk: &str) -> Result<u32> {
+        self.data_key()?;
+        if self.has_seed()? {
+            anyhow::bail!("Wallet has a seed phrase; its viewing keys are derived from it");
+        }
         
-        // Create note record with metadata
-        let note_record = OrchardNoteRecord {
-            note: note.clone(),
-            created_at,
-            is_spent: false,
-            last_used: None,
-        };
+        let account = self.watch_accounts.keys().next_back().map_or(0, |a| a + 1);
+        let viewing = ViewingAccount::from_ufvk(account, ufvk, self.network_type())?;
         
-        // Store in database
-        let note_bytes = bincode::serialize(&note_record)
-            .with_context(|| "Failed to serialize Orchard note record")?;
+        let key = format!("viewing_{}", account);
+        let mut batch = StoreBatch::default();
+        batch.insert(Table::Keys, &key, self.seal_record(key.as_bytes(), &viewing)?);
+        self.commit(batch)?;
+        
+        self.watch_accounts.insert(account, viewing);
+        self.reload_notes()?;
+        info!("Imported viewing key for watch-only account {}", account);
+        Ok(account)
+    }
+    
+    /// Whether this wallet only holds viewing keys
+    pub fn is_watch_only(&self) -> bool {
+        !self.watch_accounts.is_empty()
+    }
+    
+    /// Spending keys of `account`
+    pub fn account_keys(&self, account: u32) -> Result<&AccountKeys> {
+        self.data_key()?;
+        if self.is_watch_only() {
+            anyhow::bail!("Watch-only wallet has no spending keys");
+        }
+        self.accounts
+            .get(&account)
+            .ok_or_else(|| anyhow::anyhow!("Unknown account: {}", account))
+    }
+    
+    /// Viewing keys of `account`, whether derived from the seed or imported
+    pub fn viewing_account(&self, account: u32) -> Result<ViewingAccount> {
+        self.data_key()?;
+        self.viewing_accounts()
+            .into_iter()
+            .find(|viewing| viewing.account == account)
+            .ok_or_else(|| anyhow::anyhow!("Unknown account: {}", account))
+    }
+    
+    fn viewing_accounts(&self) -> Vec<ViewingAccount> {
+        self.accounts
+            .values()
+            .map(AccountKeys::viewing_account)
+            .chain(self.watch_accounts.values().cloned())
+            .collect()
+    }
+    
+    /// Indices of the accounts derived or imported so far
+    pub fn accounts(&self) -> Vec<u32> {
+        self.accounts.keys().chain(self.watch_accounts.keys()).copied().collect()
+    }
+    
+    fn coin_type(&self) -> u32 {
+        coin_type(&self.metadata.network)
+    }
+    
+    fn network_type(&self) -> NetworkType {
+        consensus_network(&self.metadata.network).network_type()
+    }
+    
+    /// Trial-decrypt `blocks` with every account's viewing keys, adding newly
+    /// found notes and marking spent the notes whose nullifiers appear.
+    ///
+    /// Blocks at or below the last scanned height are skipped and scanning
+    /// stops at `snapshot_height`. Progress is saved after each block.
+    pub fn scan_blocks(&mut self, blocks: &[CompactBlock], snapshot_height: Option<u64>) -> Result<ScanSummary> {
+        let keys = self.viewing_accounts()
+            .iter()
+            .map(ScanKeys::from_viewing_account)
+            .collect::<Result<Vec<_>, _>>()?;
+        if keys.is_empty() {
+            anyhow::bail!("Wallet has no keys to scan with");
+        }
         
-        let tree = self.db.open_tree("orchard_notes")?;
-        tree.insert(note_id.as_bytes(), note_bytes)?;
-        tree.flush()?;
+        let state = self.scan_state()?;
+        let mut scanner = Scanner::new(consensus_network(&self.metadata.network), keys, state, snapshot_height);
+        for (key, value) in self.store.entries(Table::NoteNullifiers)? {
+            let nullifier: Nullifier = self.open_record(&key, &value)
+                .with_context(|| "Failed to decrypt note nullifier")?;
+            scanner.track_nullifier(nullifier, NoteId::from_key(&key)?);
+        }
         
-        // Add to core wallet
-        self.core_wallet.add_orchard_note(note);
+        let mut summary = ScanSummary { last_height: state.last_height, ..Default::default() };
+        for block in blocks {
+            if state.last_height.is_some_and(|last| block.height <= last) {
+                continue;
+            }
+            if scanner.past_snapshot(block.height) {
+                break;
+            }
+            
+            // A block's notes, spends and scan progress are stored together
+            let scan = scanner.scan_block(block)?;
+            let mut batch = StoreBatch::default();
+            let mut sapling_notes = Vec::new();
+            let mut orchard_notes = Vec::new();
+            for found in scan.sapling_notes {
+                sapling_notes.extend(self.stage_scanned_sapling(&mut batch, found)?);
+            }
+            for found in scan.orchard_notes {
+                orchard_notes.extend(self.stage_scanned_orchard(&mut batch, found)?);
+            }
+            for note in &scan.spends {
+                self.stage_note_spent(&mut batch, *note, Some(block.height))?;
+            }
+            self.stage_scan_state(&mut batch, &scanner.state())?;
+            self.commit(batch)?;
+            
+            summary.sapling_notes += sapling_notes.len();
+            summary.orchard_notes += orchard_notes.len();
+            summary.spends += scan.spends.len();
+            sapling_notes.into_iter().for_each(|note| self.core_wallet.add_sapling_note(note));
+            orchard_notes.into_iter().for_each(|note| self.core_wallet.add_orchard_note(note));
+            summary.blocks_scanned += 1;
+            summary.last_height = Some(block.height);
+        }
         
-        info!("Added Orchard note with value {} at position {}", note.value, note.position);
+        self.update_last_sync()?;
+        info!("Scanned {} blocks, found {} Sapling and {} Orchard notes, {} spends",
+              summary.blocks_scanned, summary.sapling_notes, summary.orchard_notes, summary.spends);
         
-        Ok(())
+        Ok(summary)
     }
     
-    pub fn get_balance(&self) -> (u64, u64) {
-        let mut sapling_balance: u64 = 0;
-        let mut orchard_balance: u64 = 0;
-        
-        // Count unspent Sapling notes
-        if let Some(tree) = self.db.open_tree("sapling_notes").ok() {
-            for result in tree.iter() {
-                if let Ok((_, value)) = result {
-                    if let Ok(note_record) = bincode::deserialize::<SaplingNoteRecord>(&value) {
-                        if !note_record.is_spent {
-                            sapling_balance += note_record.note.value;
-                        }
-                    }
-                }
-            }
+    /// Rebuild what scanning derived from block `from_height` on, then scan
+    /// `blocks` again from there.
+    ///
+    /// Notes created at or after `from_height`, by their commitment tree
+    /// position, are dropped with their nullifiers and found again by the
+    /// rescan. Notes the wallet's keys cannot find again, those without an
+    /// account or held by a claim, are kept and only lose their spent flag,
+    /// which the rescan restores. Older notes keep their spend status unless
+    /// their spend was recorded at or after `from_height`, in which case it
+    /// is cleared and found again.
+    pub fn rescan(
+        &mut self,
+        blocks: &[CompactBlock],
+        from_height: u64,
+        snapshot_height: Option<u64>,
+    ) -> Result<RescanSummary> {
+        let scanned = self.scan_state()?.last_height
+            .ok_or_else(|| anyhow::anyhow!("Wallet has not been scanned yet; sync it instead"))?;
+        if from_height > scanned + 1 {
+            anyhow::bail!("Wallet has only been scanned to block {}", scanned);
         }
+        let first = blocks.iter()
+            .find(|block| block.height == from_height)
+            .ok_or_else(|| anyhow::anyhow!("Compact blocks do not include block {}", from_height))?;
+        let state = ScanState::before(first)?;
+        let spent_since = self.spent_since(from_height)?;
+        self.reload_notes()?;
         
-        // Count unspent Orchard notes
-        if let Some(tree) = self.db.open_tree("orchard_notes").ok() {
-            for result in tree.iter() {
-                if let Ok((_, value)) = result {
-                    if let Ok(note_record) = bincode::deserialize::<OrchardNoteRecord>(&value) {
-                        if !note_record.is_spent {
-                            orchard_balance += note_record.note.value;
-                        }
-                    }
-                }
+        // The rewind is committed at once, so an interrupted rescan resumes
+        // from wherever it got to. Notes received before the rescanned blocks
+        // stay, but their spends in those blocks are found again; a spend
+        // recorded without its height is kept.
+        let mut summary = RescanSummary::default();
+        let mut batch = StoreBatch::default();
+        for note in self.notes.select(&NoteQuery::default()) {
+            let id = note.id();
+            let received_since = id.position >= state.tree_size(id.pool);
+            if received_since && note.account.is_some() && note.status.claim_state() == ClaimState::Unclaimed {
+                batch.remove(notes_table(id.pool), id.key());
+                batch.remove(Table::NoteNullifiers, id.key());
+                batch.remove(Table::NoteSpends, id.key());
+                summary.notes_removed += 1;
+            } else if note.status.is_spent && (received_since || spent_since.contains(&id)) {
+                self.stage_note_update(&mut batch, id, |status| {
+                    status.is_spent = false;
+                    Ok(())
+                })?;
+                batch.remove(Table::NoteSpends, id.key());
+                summary.notes_reset += 1;
             }
         }
+        self.stage_scan_state(&mut batch, &state)?;
+        self.commit(batch)?;
+        self.reload_notes()?;
+        info!("Rewound scan to block {}: removed {} notes, reset {}", from_height, summary.notes_removed, summary.notes_reset);
         
-        (sapling_balance, orchard_balance)
+        summary.scan = self.scan_blocks(blocks, snapshot_height)?;
+        Ok(summary)
     }
     
-    pub fn list_notes(&self, min_value: Option<u64>, note_type: Option<&str>) -> Vec<NoteMetadata> {
-        let mut notes = Vec::new();
+    /// Locate notes of externally held `keys` in `blocks` and add them.
+    ///
+    /// When `expected` is given (zcashd's `z_listunspent`), only those notes
+    /// are imported and any that cannot be found are returned; otherwise every
+    /// note the keys can decrypt is imported. Notes already stored at the same
+    /// position are counted as duplicates and left untouched.
+    pub fn import_notes(
+        &mut self,
+        blocks: &[CompactBlock],
+        keys: Vec<ScanKeys>,
+        expected: Option<&[ExpectedNote]>,
+        snapshot_height: Option<u64>,
+    ) -> Result<ImportSummary> {
+        self.data_key()?;
+        let network = consensus_network(&self.metadata.network);
+        let mut scanner = Scanner::new(network, keys, ScanState::default(), snapshot_height);
+        let mut remaining = expected.map(<[ExpectedNote]>::to_vec);
+        let mut summary = ImportSummary::default();
         
-        if note_type.is_none() || note_type == Some("sapling") {
-            if let Some(tree) = self.db.open_tree("sapling_notes").ok() {
-                for result in tree.iter() {
-                    if let Ok((_, value)) = result {
-                        if let Ok(note_record) = bincode::deserialize::<SaplingNoteRecord>(&value) {
-                            if let Some(min_val) = min_value {
-                                if note_record.note.value < min_val {
-                                    continue;
-                                }
-                            }
-                            
-                            notes.push(NoteMetadata {
-                                note_type: "sapling".to_string(),
-                                value: note_record.note.value,
-                                position: note_record.note.position,
-                                is_spent: note_record.is_spent,
-                                created_at: note_record.created_at,
-                                last_used: note_record.last_used,
-                            });
-                        }
-                    }
-                }
+        let wanted = |remaining: &mut Option<Vec<ExpectedNote>>, pool, txid, index, value| {
+            let Some(remaining) = remaining else {
+                return Ok(true);
+            };
+            let Some(i) = remaining.iter().position(|e| e.pool == pool && e.txid == txid && e.index == index) else {
+                return Ok(false);
+            };
+            if remaining[i].value != value {
+                anyhow::bail!("{} note {}:{} has value {}, zcashd reported {}",
+                              pool, hex::encode(txid), index, value, remaining[i].value);
             }
-        }
+            remaining.swap_remove(i);
+            Ok(true)
+        };
         
-        if note_type.is_none() || note_type == Some("orchard") {
-            if let Some(tree) = self.db.open_tree("orchard_notes").ok() {
-                for result in tree.iter() {
-                    if let Ok((_, value)) = result {
-                        if let Ok(note_record) = bincode::deserialize::<OrchardNoteRecord>(&value) {
-                            if let Some(min_val) = min_value {
-                                if note_record.note.value < min_val {
-                                    continue;
-                                }
-                            }
-                            
-                            notes.push(NoteMetadata {
-                                note_type: "orchard".to_string(),
-                                value: note_record.note.value,
-                                position: note_record.note.position,
-                                is_spent: note_record.is_spent,
-                                created_at: note_record.created_at,
-                                last_used: note_record.last_used,
-                            });
-                        }
-                    }
+        for block in blocks {
+            if scanner.past_snapshot(block.height) {
+                break;
+            }
+            
+            let scan = scanner.scan_block(block)?;
+            let mut batch = StoreBatch::default();
+            let mut sapling_notes = Vec::new();
+            let mut orchard_notes = Vec::new();
+            let mut found_notes = 0;
+            for found in scan.sapling_notes {
+                if wanted(&mut remaining, Pool::Sapling, found.txid, found.output_index, found.note.value)? {
+                    found_notes += 1;
+                    sapling_notes.extend(self.stage_scanned_sapling(&mut batch, found)?);
+                }
+            }
+            for found in scan.orchard_notes {
+                if wanted(&mut remaining, Pool::Orchard, found.txid, found.output_index, found.note.value)? {
+                    found_notes += 1;
+                    orchard_notes.extend(self.stage_scanned_orchard(&mut batch, found)?);
+                }
+            }
+            for note in scan.spends {
+                if self.read(&batch, notes_table(note.pool), note.key().as_bytes())?.is_some() {
+                    self.stage_note_spent(&mut batch, note, Some(block.height))?;
                 }
             }
+            self.commit(batch)?;
+            
+            let imported = sapling_notes.len() + orchard_notes.len();
+            summary.imported += imported;
+            summary.duplicates += found_notes - imported;
+            sapling_notes.into_iter().for_each(|note| self.core_wallet.add_sapling_note(note));
+            orchard_notes.into_iter().for_each(|note| self.core_wallet.add_orchard_note(note));
         }
         
-        notes
+        summary.missing = remaining.unwrap_or_default();
+        info!("Imported {} notes ({} duplicates, {} not found)",
+              summary.imported, summary.duplicates, summary.missing.len());
+        
+        Ok(summary)
     }
     
-    pub fn create_sapling_airdrop_tx(
-        &self,
-        note_index: usize,
-        airdrop_amount: u64,
-        recipient: &str,
-    ) -> Result<ShieldedAirdropTransaction> {
-        let tx = self.core_wallet.create_sapling_airdrop_tx(
-            note_index,
-            airdrop_amount,
-            recipient.as_bytes(),
-        )
-        .map_err(|e| anyhow::anyhow!("Failed to create Sapling airdrop transaction: {}", e))?;
-        
-        // Mark the note as spent
-        if note_index < self.core_wallet.sapling_notes.len() {
-            let note = &self.core_wallet.sapling_notes[note_index];
-            // Note: We need to make this function mutable to mark as spent
-            // For now, we'll just return the transaction
+    /// Stage a scanned note unless its position is already known, returning
+    /// the note if it is new
+    fn stage_scanned_sapling(&self, batch: &mut StoreBatch, found: Discovered<SaplingNote>) -> Result<Option<SaplingNote>> {
+        let key = found.note.id().key();
+        let is_new = self.read(batch, Table::SaplingNotes, key.as_bytes())?.is_none();
+        if is_new {
+            self.stage_sapling_note(batch, &found.note)?;
         }
-        
-        Ok(tx)
+        self.stage_note_nullifier(batch, &key, &found.nullifier)?;
+        Ok(is_new.then_some(found.note))
     }
     
-    pub fn create_orchard_airdrop_tx(
-        &self,
-        note_index: usize,
-        airdrop_amount: u64,
-        recipient: &str,
-    ) -> Result<ShieldedAirdropTransaction> {
-        let tx = self.core_wallet.create_orchard_airdrop_tx(
-            note_index,
-            airdrop_amount,
-            recipient.as_bytes(),
-        )
-        .map_err(|e| anyhow::anyhow!("Failed to create Orchard airdrop transaction: {}", e))?;
-        
-        // Mark the note as spent
-        if note_index < self.core_wallet.orchard_notes.len() {
-            let note = &self.core_wallet.orchard_notes[note_index];
-            // Note: We need to make this function mutable to mark as spent
-            // For now, we'll just return the transaction
+    fn stage_scanned_orchard(&self, batch: &mut StoreBatch, found: Discovered<OrchardNote>) -> Result<Option<OrchardNote>> {
+        let key = found.note.id().key();
+        let is_new = self.read(batch, Table::OrchardNotes, key.as_bytes())?.is_none();
+        if is_new {
+            self.stage_orchard_note(batch, &found.note)?;
         }
-        
-        Ok(tx)
+        self.stage_note_nullifier(batch, &key, &found.nullifier)?;
+        Ok(is_new.then_some(found.note))
     }
     
-    pub fn create_sapling_airdrop_tx_mut(
-        &mut self,
-        note_index: usize,
-        airdrop_amount: u64,
-        recipient: &str,
-    ) -> Result<ShieldedAirdropTransaction> {
-        let tx = self.core_wallet.create_sapling_airdrop_tx(
-            note_index,
-            airdrop_amount,
-            recipient.as_bytes(),
-        )
-        .map_err(|e| anyhow::anyhow!("Failed to create Sapling airdrop transaction: {}", e))?;
-        
-        // Mark the note as spent
-        if note_index < self.core_wallet.sapling_notes.len() {
-            let note = &self.core_wallet.sapling_notes[note_index];
-            self.mark_note_as_spent("sapling", note.position)?;
+    /// Scanner progress, starting from an empty chain
+    pub fn scan_state(&self) -> Result<ScanState> {
+        match self.store.get(Table::Metadata, b"scan_state")? {
+            Some(bytes) => bincode::deserialize(&bytes).with_context(|| "Failed to deserialize scan state"),
+            None => Ok(ScanState::default()),
         }
-        
-        Ok(tx)
     }
     
-    pub fn create_orchard_airdrop_tx_mut(
+    fn stage_scan_state(&self, batch: &mut StoreBatch, state: &ScanState) -> Result<()> {
+        let bytes = bincode::serialize(state).with_context(|| "Failed to serialize scan state")?;
+        batch.insert(Table::Metadata, "scan_state", bytes);
+        Ok(())
+    }
+    
+    fn stage_note_nullifier(&self, batch: &mut StoreBatch, note_key: &str, nullifier: &Nullifier) -> Result<()> {
+        let sealed = self.seal_record(note_key.as_bytes(), nullifier)?;
+        batch.insert(Table::NoteNullifiers, note_key, sealed);
+        Ok(())
+    }
+    
+    pub fn add_sapling_note(&mut self, note: SaplingNote) -> Result<()> {
+        let mut batch = StoreBatch::default();
+        self.stage_sapling_note(&mut batch, &note)?;
+        self.commit(batch)?;
+        
+        info!("Added Sapling note with value {} at position {}", note.value, note.position);
+        
+        // Add to core wallet
+        self.core_wallet.add_sapling_note(note);
+        
+        Ok(())
+    }
+    
+    fn stage_sapling_note(&self, batch: &mut StoreBatch, note: &SaplingNote) -> Result<()> {
+        let note_id = note.id().key();
+        let note_record = SaplingNoteRecord {
+            note: note.clone(),
+            created_at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+            status: NoteStatus::default(),
+            last_used: None,
+        };
+        batch.insert(Table::SaplingNotes, &note_id, self.seal_record(note_id.as_bytes(), &note_record)?);
+        Ok(())
+    }
+    
+    pub fn add_orchard_note(&mut self, note: OrchardNote) -> Result<()> {
+        let mut batch = StoreBatch::default();
+        self.stage_orchard_note(&mut batch, &note)?;
+        self.commit(batch)?;
+        
+        info!("Added Orchard note with value {} at position {}", note.value, note.position);
+        
+        // Add to core wallet
+        self.core_wallet.add_orchard_note(note);
+        
+        Ok(())
+    }
+    
+    fn stage_orchard_note(&self, batch: &mut StoreBatch, note: &OrchardNote) -> Result<()> {
+        let note_id = note.id().key();
+        let note_record = OrchardNoteRecord {
+            note: note.clone(),
+            created_at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+            status: NoteStatus::default(),
+            last_used: None,
+        };
+        batch.insert(Table::OrchardNotes, &note_id, self.seal_record(note_id.as_bytes(), &note_record)?);
+        Ok(())
+    }
+    
+    /// Value of the wallet's notes by pool, Zcash spend status and claim status
+    pub fn get_balance(&self) -> Result<Balance> {
+        self.data_key()?;
+        Ok(self.notes.balance())
+    }
+    
+    /// Value of the notes held by `account`
+    pub fn account_balance(&self, account: u32) -> Result<Balance> {
+        self.ensure_account(account)?;
+        Ok(self.notes.account_balance(account))
+    }
+    
+    /// The account holding the note `id`, if any
+    pub fn note_account(&self, id: NoteId) -> Result<Option<u32>> {
+        self.data_key()?;
+        let note = self.notes.get(id).ok_or_else(|| anyhow::anyhow!("Unknown note: {}", id))?;
+        Ok(note.account)
+    }
+    
+    fn ensure_account(&self, account: u32) -> Result<()> {
+        if !self.accounts().contains(&account) {
+            anyhow::bail!("Unknown account: {}", account);
+        }
+        Ok(())
+    }
+    
+    /// Notes worth at least `min_value` that can still claim the airdrop,
+    /// whether or not they were spent on Zcash since the snapshot
+    pub fn find_eligible_notes(&self, min_value: u64) -> Result<Vec<NoteMetadata>> {
+        let query = NoteQuery { min_value: Some(min_value), eligible: true, ..NoteQuery::default() };
+        Ok(self.query_notes(&query)?.notes)
+    }
+    
+    pub fn list_notes(&self, min_value: Option<u64>, pool: Option<Pool>) -> Result<Vec<NoteMetadata>> {
+        Ok(self.query_notes(&NoteQuery { min_value, pool, ..NoteQuery::default() })?.notes)
+    }
+    
+    /// The page of notes matching `query`, answered from the note index
+    pub fn query_notes(&self, query: &NoteQuery) -> Result<NotePage> {
+        self.data_key()?;
+        let mut notes = Vec::new();
+        for note in self.notes.select(query) {
+            // Notes claimed through another wallet are only known to the registry
+            if query.eligible && self.core_wallet.airdrop_nullifiers.contains(&note.airdrop_nullifier)? {
+                continue;
+            }
+            notes.push(note.clone());
+        }
+        Ok(query.page(notes))
+    }
+    
+    /// Check every note of `account`, or of all accounts, against the airdrop
+    /// `rules` and the wallet's policy
+    pub fn eligibility_report(&self, rules: &EligibilityRules, account: Option<u32>) -> Result<EligibilityReport> {
+        let notes = self.query_notes(&NoteQuery { account, ..NoteQuery::default() })?.notes;
+        let spend_heights = self.spend_heights()?;
+        let mut assessed = Vec::with_capacity(notes.len());
+        for note in notes {
+            let in_registry = self.core_wallet.airdrop_nullifiers.contains(&note.airdrop_nullifier)?;
+            let materials = match (note.account, self.is_watch_only()) {
+                (Some(account), false) => ProofMaterials::SpendingKey(account),
+                (Some(account), true) => ProofMaterials::ViewingKey(account),
+                (None, _) => ProofMaterials::Missing,
+            };
+            let spent_at = spend_heights.get(&note.id()).copied();
+            assessed.push((note, in_registry, materials, spent_at));
+        }
+        assess_notes(assessed, rules, &self.core_wallet.policy, &self.scan_state()?)
+    }
+    
+    /// Choose which eligible notes to claim under `options` and the airdrop policy
+    pub fn plan_claims(&self, options: &PlanOptions) -> Result<ClaimPlan> {
+        let query = NoteQuery {
+            account: options.account,
+            min_value: Some(options.min_value),
+            eligible: true,
+            ..NoteQuery::default()
+        };
+        let notes = self.query_notes(&query)?.notes;
+        plan_claims(notes, &self.core_wallet.policy, options, &mut rand::thread_rng())
+    }
+    
+    /// Build the claims of `batch` of `plan`, or all of them, each minting
+    /// its planned amount to `recipient`.
+    ///
+    /// Claims are built one at a time; if one fails, those already built stay
+    /// recorded as pending and their notes reserved.
+    pub fn execute_plan(
+        &mut self,
+        plan: &ClaimPlan,
+        batch: Option<usize>,
+        recipient: &PublicKey,
+    ) -> Result<Vec<ShieldedAirdropTransaction>> {
+        let mut txs = Vec::new();
+        for claim in plan.claims_in(batch) {
+            let tx = self.create_airdrop_tx(claim.id(), claim.amount, recipient)
+                .with_context(|| format!("Failed to claim note {} after {} claims", claim.id(), txs.len()))?;
+            txs.push(tx);
+        }
+        info!("Built {} claims of a {} plan", txs.len(), plan.strategy);
+        Ok(txs)
+    }
+    
+    /// Create an airdrop transaction claiming the note `id`, recording the
+    /// claim as pending and reserving the note for it.
+    ///
+    /// The claim is authorized with the spending key of the account holding
+    /// the note. Notes held by no account, such as notes imported with
+    /// another wallet's viewing key, must be claimed by the wallet holding
+    /// their spending key.
+    pub fn create_airdrop_tx(
         &mut self,
-        note_index: usize,
+        id: NoteId,
         airdrop_amount: u64,
-        recipient: &str,
+        recipient: &PublicKey,
     ) -> Result<ShieldedAirdropTransaction> {
-        let tx = self.core_wallet.create_orchard_airdrop_tx(
-            note_index,
-            airdrop_amount,
-            recipient.as_bytes(),
-        )
-        .map_err(|e| anyhow::anyhow!("Failed to create Orchard airdrop transaction: {}", e))?;
+        self.ensure_can_sign()?;
+        self.ensure_claimable(id)?;
         
-        // Mark the note as spent
-        if note_index < self.core_wallet.orchard_notes.len() {
-            let note = &self.core_wallet.orchard_notes[note_index];
-            self.mark_note_as_spent("orchard", note.position)?;
-        }
+        let Some(owner) = self.note_owner(id)? else {
+            anyhow::bail!("Wallet holds no spending key for note {}; claim it from the wallet holding the note's keys", id);
+        };
+        let mut partial = self.prepare_airdrop_tx(id, airdrop_amount, recipient)?;
+        partial.sign(self.account_keys(owner.account)?)?;
+        let tx = partial.finalize()
+            .map_err(|e| anyhow::anyhow!("Failed to create {} airdrop transaction: {}", id.pool, e))?;
+        
+        self.track_claim(id, &tx)?;
+        
+        Ok(tx)
+    }
+    
+    /// Prepare an unsigned claim of the note `id` for the holder of its spending key.
+    ///
+    /// The claim's spend-auth key is the owning account's `ak` rerandomized by
+    /// a fresh `alpha`, so only viewing keys are needed. The note is left
+    /// untouched until the signed claim is finalized.
+    pub fn prepare_airdrop_tx(
+        &self,
+        id: NoteId,
+        airdrop_amount: u64,
+        recipient: &PublicKey,
+    ) -> Result<PartialAirdropTransaction> {
+        let owner = self.note_owner(id)?
+            .ok_or_else(|| anyhow::anyhow!("Note {} is not held by any account of this wallet", id))?;
+        self.ensure_claimable(id)?;
+        
+        let alpha = Scalar(rand::random());
+        let randomized_key = owner.pool(id.pool)?.randomized_key(&alpha)?;
+        
+        self.core_wallet
+            .prepare_airdrop_tx(id, airdrop_amount, &recipient.0, &alpha, &randomized_key)
+            .map_err(|e| anyhow::anyhow!("Failed to prepare {} airdrop transaction: {}", id.pool, e))
+    }
+    
+    /// Add the spend-auth signature of whichever account the claim's
+    /// randomized key belongs to, returning that account
+    pub fn sign_airdrop_tx(&self, partial: &mut PartialAirdropTransaction) -> Result<u32> {
+        self.ensure_can_sign()?;
+        let keys = self.accounts
+            .values()
+            .find(|keys| keys.randomized_key(partial.pool(), &partial.alpha) == *partial.randomized_key())
+            .ok_or_else(|| anyhow::anyhow!("Claim is not authorized by any account of this wallet"))?;
+        
+        partial.sign(keys)?;
+        info!("Signed {} claim with account {}", partial.pool(), keys.account());
+        Ok(keys.account())
+    }
+    
+    /// Add the binding signature to a claim signed elsewhere, recording it as
+    /// pending and reserving its note
+    pub fn finalize_airdrop_tx(&mut self, partial: PartialAirdropTransaction) -> Result<ShieldedAirdropTransaction> {
+        self.data_key()?;
+        let pool = partial.pool();
+        let id = self.note_by_airdrop_nullifier(pool, &partial.get_airdrop_nullifier())
+            .ok_or_else(|| anyhow::anyhow!("Claim does not spend a note of this wallet"))?;
+        
+        let tx = partial.finalize()
+            .map_err(|e| anyhow::anyhow!("Failed to finalize {} airdrop transaction: {}", pool, e))?;
+        self.track_claim(id, &tx)?;
         
         Ok(tx)
     }
     
+    /// The wallet note of `pool` whose airdrop nullifier is `airdrop_nullifier`
+    fn note_by_airdrop_nullifier(&self, pool: Pool, airdrop_nullifier: &Nullifier) -> Option<NoteId> {
+        match pool {
+            Pool::Sapling => self.core_wallet.sapling_notes.iter()
+                .find(|note| note.airdrop_nullifier().ok().as_ref() == Some(airdrop_nullifier))
+                .map(SaplingNote::id),
+            Pool::Orchard => self.core_wallet.orchard_notes.iter()
+                .find(|note| note.airdrop_nullifier().ok().as_ref() == Some(airdrop_nullifier))
+                .map(OrchardNote::id),
+        }
+    }
+    
+    /// The account holding the note `id`, if any
+    fn note_owner(&self, id: NoteId) -> Result<Option<ViewingAccount>> {
+        self.data_key()?;
+        let nk = match id.pool {
+            Pool::Sapling => self.core_wallet.sapling_note(id).map(|note| note.nullifier_key),
+            Pool::Orchard => self.core_wallet.orchard_note(id).map(|note| note.nullifier_key),
+        }
+        .ok_or_else(|| anyhow::anyhow!("Unknown note: {}", id))?;
+        
+        Ok(self.viewing_accounts()
+            .into_iter()
+            .find(|viewing| viewing.owns(id.pool, &nk)))
+    }
+    
+    fn ensure_can_sign(&self) -> Result<()> {
+        if self.is_watch_only() {
+            anyhow::bail!("Watch-only wallet cannot authorize claims; prepare an unsigned claim instead");
+        }
+        Ok(())
+    }
+    
+    /// Validate a claim and record its airdrop nullifier in this wallet's registry
+    pub fn process_airdrop_transaction(
+        &mut self,
+        tx: &ShieldedAirdropTransaction,
+        block_height: u64,
+    ) -> Result<()> {
+        self.core_wallet
+            .process_airdrop_transaction(tx, block_height)
+            .with_context(|| "Airdrop transaction rejected")
+    }
+    
+    /// Reserve the note `id` for the claim `tx` and record the claim as
+    /// pending, atomically
+    fn track_claim(&mut self, id: NoteId, tx: &ShieldedAirdropTransaction) -> Result<()> {
+        let tx_hash = hex::encode(tx.txid());
+        let mut batch = StoreBatch::default();
+        self.stage_reservation(&mut batch, id, &tx_hash)?;
+        self.stage_claim(&mut batch, &self.pending_claim(tx, &tx_hash)?)?;
+        self.commit(batch)?;
+        info!("Reserved {} note at position {} for claim {}", id.pool, id.position, tx_hash);
+        Ok(())
+    }
+    
+    /// Record a claim as pending
     pub fn record_transaction(&mut self, tx: &ShieldedAirdropTransaction, tx_hash: &str) -> Result<()> {
+        let mut batch = StoreBatch::default();
+        self.stage_claim(&mut batch, &self.pending_claim(tx, tx_hash)?)?;
+        self.commit(batch)
+    }
+    
+    fn pending_claim(&self, tx: &ShieldedAirdropTransaction, tx_hash: &str) -> Result<TransactionRecord> {
         let airdrop_nullifier = tx.get_airdrop_nullifier();
-        let amount = match &tx.claim_description {
-            ClaimDescription::Sapling(claim) => claim.value_commitment[0] as u64,
-            ClaimDescription::Orchard(claim) => claim.value_commitment[0] as u64,
-        };
+        let amount = tx.masp_mint_description.value_commitment.mock_value()
+            .ok_or_else(|| anyhow::anyhow!("Mint value commitment is out of range"))?;
         
         let record = TransactionRecord {
             tx_hash: tx_hash.to_string(),
-            airdrop_nullifier: airdrop_nullifier.to_vec(),
+            airdrop_nullifier: airdrop_nullifier.0.to_vec(),
+            note: self.note_by_airdrop_nullifier(tx.claim_description.pool(), &airdrop_nullifier),
             amount,
-            recipient: "masp_recipient".to_string(), // Would be extracted from MASP description
-            status: "pending".to_string(),
+            recipient: hex::encode(tx.masp_mint_description.recipient.0),
+            status: ClaimStatus::Pending,
             created_at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+            broadcast_height: None,
+            expiry_height: None,
             confirmed_at: None,
             block_height: None,
+            failure: None,
         };
+        Ok(record)
+    }
+    
+    fn stage_claim(&self, batch: &mut StoreBatch, record: &TransactionRecord) -> Result<()> {
+        batch.insert(Table::Transactions, &record.tx_hash, self.seal_record(record.tx_hash.as_bytes(), record)?);
+        Ok(())
+    }
+    
+    /// The recorded claim with hash `tx_hash`
+    pub fn claim(&self, tx_hash: &str) -> Result<Option<TransactionRecord>> {
+        self.store.get(Table::Transactions, tx_hash.as_bytes())?
+            .map(|value| self.open_record(tx_hash.as_bytes(), &value))
+            .transpose()
+            .with_context(|| format!("Failed to decrypt claim record {}", tx_hash))
+    }
+    
+    /// Recorded claims, oldest first, optionally only those with `status`
+    pub fn claims(&self, status: Option<ClaimStatus>) -> Result<Vec<TransactionRecord>> {
+        let mut claims: Vec<TransactionRecord> = self.open_records(Table::Transactions)?
+            .into_iter()
+            .map(|(_, record)| record)
+            .filter(|record: &TransactionRecord| status.is_none_or(|status| record.status == status))
+            .collect();
+        claims.sort_by_key(|record| record.created_at);
+        Ok(claims)
+    }
+    
+    /// Recorded claims of notes held by `account`, oldest first
+    pub fn account_claims(&self, account: u32, status: Option<ClaimStatus>) -> Result<Vec<TransactionRecord>> {
+        self.ensure_account(account)?;
+        let mut claims = self.claims(status)?;
+        claims.retain(|record| {
+            record.note.and_then(|id| self.notes.get(id)).is_some_and(|note| note.account == Some(account))
+        });
+        Ok(claims)
+    }
+    
+    /// Move a claim to `next`, atomically updating its note: a confirmed
+    /// claim marks the note as claimed and registers its airdrop nullifier; a
+    /// failed or expired one releases the note's reservation.
+    fn transition_claim(&mut self, record: &mut TransactionRecord, next: ClaimStatus) -> Result<()> {
+        if !record.status.can_transition_to(next) {
+            anyhow::bail!("Claim {} cannot move from {} to {}", record.tx_hash, record.status, next);
+        }
+        
+        record.status = next;
+        let mut batch = StoreBatch::default();
+        if let Some(id) = record.note.filter(|_| next == ClaimStatus::Confirmed || next.is_abandoned()) {
+            let tx_hash = record.tx_hash.clone();
+            self.stage_note_update(&mut batch, id, |status| {
+                // The chain consumed the note's airdrop nullifier whichever
+                // claim reserves it now; only that claim's reservation ends
+                status.is_claimed |= next == ClaimStatus::Confirmed;
+                if status.reserved_by.as_deref() == Some(tx_hash.as_str()) {
+                    status.reserved_by = None;
+                }
+                Ok(())
+            })?;
+        }
+        if next == ClaimStatus::Confirmed {
+            self.stage_confirmed_claim(&mut batch, record)?;
+        }
+        self.stage_claim(&mut batch, record)?;
+        self.commit(batch)?;
+        info!("Claim {} is now {}", record.tx_hash, next);
+        Ok(())
+    }
+    
+    /// Mirror a confirmed claim in the wallet's airdrop nullifier registry
+    fn stage_confirmed_claim(&self, batch: &mut StoreBatch, record: &TransactionRecord) -> Result<()> {
+        let nullifier = Nullifier(record.airdrop_nullifier.as_slice().try_into()
+            .map_err(|_| anyhow::anyhow!("Malformed airdrop nullifier in claim {}", record.tx_hash))?);
+        let txid = hex::decode(&record.tx_hash).ok()
+            .and_then(|txid| txid.try_into().ok())
+            .ok_or_else(|| anyhow::anyhow!("Malformed claim hash: {}", record.tx_hash))?;
         
-        let record_bytes = bincode::serialize(&record)
-            .with_context(|| "Failed to serialize transaction record")?;
-        let tree = self.db.open_tree("transactions")?;
-        tree.insert(tx_hash.as_bytes(), record_bytes)?;
+        if !self.core_wallet.airdrop_nullifiers.contains(&nullifier)? {
+            let consumed = ConsumedNullifier { block_height: record.block_height.unwrap_or(0), txid };
+            StoreNullifierRegistry::stage(batch, &nullifier, &consumed)?;
+        }
         Ok(())
     }
     
+    /// Broadcast a pending claim, which may be included up to `expiry_blocks`
+    /// blocks after the current Namada height
+    pub fn broadcast_claim(
+        &mut self,
+        client: &mut dyn NamadaClient,
+        tx: &ShieldedAirdropTransaction,
+        expiry_blocks: u64,
+    ) -> Result<ClaimStatus> {
+        let tx_hash = hex::encode(tx.txid());
+        let mut record = self.claim(&tx_hash)?
+            .ok_or_else(|| anyhow::anyhow!("Unknown claim: {}", tx_hash))?;
+        if record.status != ClaimStatus::Pending {
+            anyhow::bail!("Claim {} is already {}", tx_hash, record.status);
+        }
+        
+        let owner = self.relay_tag(self.claim_owner(record.note)?)?;
+        let height = client.latest_height()?;
+        let expiry_height = height.checked_add(expiry_blocks)
+            .ok_or_else(|| anyhow::anyhow!("Expiry of {} blocks after block {} is out of range", expiry_blocks, height))?;
+        match client.broadcast_owned(tx, owner) {
+            Ok(()) => {
+                record.broadcast_height = Some(height);
+                record.expiry_height = Some(expiry_height);
+                self.transition_claim(&mut record, ClaimStatus::Broadcast)?;
+            }
+            Err(NamadaError::Rejected(reason)) => {
+                record.failure = Some(reason);
+                self.transition_claim(&mut record, ClaimStatus::Failed)?;
+            }
+            Err(e) => return Err(e.into()),
+        }
+        Ok(record.status)
+    }
+    
+    /// Update every broadcast claim from the chain, returning the claims
+    /// whose status changed
+    pub fn sync_claims(&mut self, client: &dyn NamadaClient) -> Result<Vec<TransactionRecord>> {
+        let height = client.latest_height()?;
+        let mut updated = Vec::new();
+        
+        for mut record in self.claims(Some(ClaimStatus::Broadcast))? {
+            let txid: [u8; 32] = hex::decode(&record.tx_hash).ok()
+                .and_then(|txid| txid.try_into().ok())
+                .ok_or_else(|| anyhow::anyhow!("Malformed claim hash: {}", record.tx_hash))?;
+            
+            let next = match client.claim_status(&txid)? {
+                ChainStatus::Included { block_height } => {
+                    record.block_height = Some(block_height);
+                    record.confirmed_at = Some(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs());
+                    ClaimStatus::Confirmed
+                }
+                ChainStatus::Rejected { reason } => {
+                    record.failure = Some(reason);
+                    ClaimStatus::Failed
+                }
+                ChainStatus::Unknown if record.expiry_height.is_some_and(|expiry| height > expiry) => {
+                    ClaimStatus::Expired
+                }
+                ChainStatus::Unknown => continue,
+            };
+            self.transition_claim(&mut record, next)?;
+            updated.push(record);
+        }
+        
+        Ok(updated)
+    }
+    
     pub fn get_metadata(&self) -> &WalletMetadata {
         &self.metadata
     }
     
+    /// The in-memory protocol wallet backing this database
+    pub fn core(&self) -> &CoreWallet {
+        &self.core_wallet
+    }
+    
+    pub fn set_policy(&mut self, policy: AirdropPolicy) {
+        self.core_wallet.policy = policy;
+    }
+    
     pub fn update_last_sync(&mut self) -> Result<()> {
         self.metadata.last_sync = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
@@ -458,164 +1515,1825 @@ impl AirdropWallet {
         
         let metadata_bytes = bincode::serialize(&self.metadata)
             .with_context(|| "Failed to serialize wallet metadata")?;
-        self.db.insert("metadata", metadata_bytes)?;
+        let mut batch = StoreBatch::default();
+        batch.insert(Table::Metadata, "metadata", metadata_bytes);
+        self.commit(batch)
+    }
+    
+    /// The wallet's notes, claims and nullifiers, for [`WalletExport::encode`]
+    pub fn export_data(&self) -> Result<WalletExport> {
+        self.data_key()?;
+        let nullifiers: HashMap<String, Nullifier> = self.open_records(Table::NoteNullifiers)?.into_iter().collect();
+        let spends: HashMap<String, u64> = self.open_records(Table::NoteSpends)?.into_iter().collect();
+        let chain_state = |key: &str| (nullifiers.get(key).copied(), spends.get(key).copied());
+        Ok(WalletExport {
+            version: EXPORT_VERSION,
+            metadata: self.metadata.clone(),
+            sapling_notes: self.open_records::<SaplingNoteRecord>(Table::SaplingNotes)?
+                .into_iter()
+                .map(|(key, record)| {
+                    let (nullifier, spent_at) = chain_state(&key);
+                    ExportedNote { note: record.note, status: record.status, nullifier, spent_at }
+                })
+                .collect(),
+            orchard_notes: self.open_records::<OrchardNoteRecord>(Table::OrchardNotes)?
+                .into_iter()
+                .map(|(key, record)| {
+                    let (nullifier, spent_at) = chain_state(&key);
+                    ExportedNote { note: record.note, status: record.status, nullifier, spent_at }
+                })
+                .collect(),
+            claims: self.claims(None)?,
+            nullifier_set: self.core_wallet.nullifier_set.nullifiers.iter().copied().collect(),
+            airdrop_nullifiers: self.core_wallet.airdrop_nullifiers.entries()?,
+        })
+    }
+    
+    /// Validate `export` and write it to the wallet in one commit.
+    ///
+    /// [`ImportMode::Replace`] first drops the wallet's notes, claims and
+    /// Zcash nullifiers; imported notes bring their own nullifiers and spend
+    /// heights. [`ImportMode::Merge`] keeps them, skipping exported
+    /// notes and claims the wallet already has and refusing an export whose
+    /// note at some position differs from the wallet's.
+    pub fn import_data(&mut self, export: WalletExport, mode: ImportMode) -> Result<WalletImportSummary> {
+        self.data_key()?;
+        export.validate(&self.metadata.network)?;
+        
+        let mut batch = StoreBatch::default();
+        if mode == ImportMode::Replace {
+            for table in [
+                Table::SaplingNotes,
+                Table::OrchardNotes,
+                Table::NoteNullifiers,
+                Table::NoteSpends,
+                Table::Transactions,
+                Table::NullifierSet,
+                Table::ClaimSchedule,
+            ] {
+                for (key, _) in self.store.entries(table)? {
+                    batch.remove(table, key);
+                }
+            }
+        }
         
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let mut summary = WalletImportSummary::default();
+        for ExportedNote { note, status, nullifier, spent_at } in export.sapling_notes {
+            let key = note.id().key();
+            if mode == ImportMode::Merge && self.holds_note(note.id(), &note.note_commitment)? {
+                summary.duplicates += 1;
+                continue;
+            }
+            let record = SaplingNoteRecord { note, created_at: now, status, last_used: None };
+            batch.insert(Table::SaplingNotes, &key, self.seal_record(key.as_bytes(), &record)?);
+            self.stage_exported_spend(&mut batch, &key, nullifier, spent_at)?;
+            summary.notes += 1;
+        }
+        for ExportedNote { note, status, nullifier, spent_at } in export.orchard_notes {
+            let key = note.id().key();
+            if mode == ImportMode::Merge && self.holds_note(note.id(), &note.note_commitment)? {
+                summary.duplicates += 1;
+                continue;
+            }
+            let record = OrchardNoteRecord { note, created_at: now, status, last_used: None };
+            batch.insert(Table::OrchardNotes, &key, self.seal_record(key.as_bytes(), &record)?);
+            self.stage_exported_spend(&mut batch, &key, nullifier, spent_at)?;
+            summary.notes += 1;
+        }
+        for claim in export.claims {
+            if mode == ImportMode::Merge && self.store.contains_key(Table::Transactions, claim.tx_hash.as_bytes())? {
+                continue;
+            }
+            self.stage_claim(&mut batch, &claim)?;
+            summary.claims += 1;
+        }
+        
+        for nullifier in export.nullifier_set {
+            batch.insert(Table::NullifierSet, nullifier.0, []);
+        }
+        for (nullifier, consumed) in export.airdrop_nullifiers {
+            if !self.core_wallet.airdrop_nullifiers.contains(&nullifier)? {
+                StoreNullifierRegistry::stage(&mut batch, &nullifier, &consumed)?;
+            }
+        }
+        self.commit(batch)?;
+        
+        self.reload_notes()?;
+        info!("Imported {} notes and {} claims", summary.notes, summary.claims);
+        Ok(summary)
+    }
+    
+    /// Stage an imported note's Zcash nullifier, so later scans detect its
+    /// spend, and the block it was already seen spent in
+    fn stage_exported_spend(
+        &self,
+        batch: &mut StoreBatch,
+        note_key: &str,
+        nullifier: Option<Nullifier>,
+        spent_at: Option<u64>,
+    ) -> Result<()> {
+        if let Some(nullifier) = nullifier {
+            self.stage_note_nullifier(batch, note_key, &nullifier)?;
+        }
+        if let Some(height) = spent_at {
+            batch.insert(Table::NoteSpends, note_key, self.seal_record(note_key.as_bytes(), &height)?);
+        }
         Ok(())
     }
     
-    pub fn export_data(&self) -> Result<Vec<u8>> {
-        let export_data = ExportData {
-            metadata: self.metadata.clone(),
-            sapling_notes: self.core_wallet.sapling_notes.clone(),
-            orchard_notes: self.core_wallet.orchard_notes.clone(),
-            nullifier_set: self.core_wallet.nullifier_set.nullifiers.iter().cloned().collect(),
-            airdrop_nullifier_set: self.core_wallet.airdrop_nullifier_set.nullifiers.iter().cloned().collect(),
+    /// Whether the wallet already holds the note `id`, failing if the note it
+    /// holds at that position has a different commitment
+    fn holds_note(&self, id: NoteId, commitment: &NoteCommitment) -> Result<bool> {
+        let key = id.key();
+        let Some(value) = self.store.get(notes_table(id.pool), key.as_bytes())? else {
+            return Ok(false);
+        };
+        let existing = match id.pool {
+            Pool::Sapling => self.open_record::<SaplingNoteRecord>(key.as_bytes(), &value)?.note.note_commitment,
+            Pool::Orchard => self.open_record::<OrchardNoteRecord>(key.as_bytes(), &value)?.note.note_commitment,
+        };
+        if existing != *commitment {
+            anyhow::bail!("Note {} in the export differs from the wallet's note at that position", id);
+        }
+        Ok(true)
+    }
+    
+    pub fn mark_note_as_spent(&mut self, id: NoteId) -> Result<()> {
+        let mut batch = StoreBatch::default();
+        self.stage_note_spent(&mut batch, id, None)?;
+        self.commit(batch)?;
+        info!("Marked {} note at position {} as spent on Zcash", id.pool, id.position);
+        Ok(())
+    }
+    
+    /// Mark the note `id` spent on Zcash, remembering the block it was spent
+    /// in when known so a rescan from before that block can undo it
+    fn stage_note_spent(&self, batch: &mut StoreBatch, id: NoteId, height: Option<u64>) -> Result<()> {
+        if let Some(height) = height {
+            let key = id.key();
+            batch.insert(Table::NoteSpends, &key, self.seal_record(key.as_bytes(), &height)?);
+        }
+        self.stage_note_update(batch, id, |status| {
+            status.is_spent = true;
+            Ok(())
+        })
+    }
+    
+    /// Notes whose Zcash spend was seen in block `height` or later
+    fn spent_since(&self, height: u64) -> Result<HashSet<NoteId>> {
+        Ok(self.spend_heights()?
+            .into_iter()
+            .filter(|(_, spent_at)| *spent_at >= height)
+            .map(|(id, _)| id)
+            .collect())
+    }
+    
+    /// Zcash block each note was seen spent in, for the spends recorded with one
+    fn spend_heights(&self) -> Result<HashMap<NoteId, u64>> {
+        let mut heights = HashMap::new();
+        for (key, value) in self.store.entries(Table::NoteSpends)? {
+            let spent_at: u64 = self.open_record(&key, &value)
+                .with_context(|| "Failed to decrypt note spend height")?;
+            heights.insert(NoteId::from_key(&key)?, spent_at);
+        }
+        Ok(heights)
+    }
+
+    /// Lock the note `id` for the claim `tx_hash` until the claim is
+    /// confirmed, fails or expires
+    fn stage_reservation(&self, batch: &mut StoreBatch, id: NoteId, tx_hash: &str) -> Result<()> {
+        self.stage_note_update(batch, id, |status| {
+            if status.is_claimed {
+                anyhow::bail!("Note {} has already been claimed", id);
+            }
+            if let Some(holder) = &status.reserved_by {
+                anyhow::bail!("Note {} is reserved by claim {}", id, holder);
+            }
+            status.reserved_by = Some(tx_hash.to_string());
+            Ok(())
+        })
+    }
+    
+    fn ensure_claimable(&self, id: NoteId) -> Result<()> {
+        let status = self.note_status(id)?;
+        if status.is_claimed {
+            anyhow::bail!("Note {} has already been claimed", id);
+        }
+        if let Some(holder) = status.reserved_by {
+            anyhow::bail!("Note {} is reserved by claim {}; use --force-release to abandon that claim", id, holder);
+        }
+        Ok(())
+    }
+    
+    /// Release the note `id` from the claim reserving it, marking that claim
+    /// as failed. Returns the abandoned claim's hash, if any.
+    ///
+    /// If the abandoned claim was in fact broadcast and is later included,
+    /// the note can be claimed twice locally; Namada still accepts only one.
+    pub fn force_release(&mut self, id: NoteId) -> Result<Option<String>> {
+        let Some(tx_hash) = self.note_status(id)?.reserved_by else {
+            return Ok(None);
         };
         
-        bincode::serialize(&export_data)
-            .with_context(|| "Failed to serialize export data")
+        // Failing the claim releases the note in the same write
+        match self.claim(&tx_hash)? {
+            Some(mut record) if record.status.can_transition_to(ClaimStatus::Failed) => {
+                record.failure = Some("released manually".to_string());
+                self.transition_claim(&mut record, ClaimStatus::Failed)?;
+            }
+            _ => {
+                let mut batch = StoreBatch::default();
+                self.stage_note_update(&mut batch, id, |status| {
+                    status.reserved_by = None;
+                    Ok(())
+                })?;
+                self.commit(batch)?;
+            }
+        }
+        info!("Released {} note at position {} from claim {}", id.pool, id.position, tx_hash);
+        Ok(Some(tx_hash))
+    }
+    
+    fn note_status(&self, id: NoteId) -> Result<NoteStatus> {
+        let note_id = id.key();
+        let value = self.store.get(notes_table(id.pool), note_id.as_bytes())?
+            .ok_or_else(|| anyhow::anyhow!("Note not found: {}", id))?;
+        let status = match id.pool {
+            Pool::Sapling => self.open_record::<SaplingNoteRecord>(note_id.as_bytes(), &value)
+                .with_context(|| "Failed to decrypt Sapling note record")?.status,
+            Pool::Orchard => self.open_record::<OrchardNoteRecord>(note_id.as_bytes(), &value)
+                .with_context(|| "Failed to decrypt Orchard note record")?.status,
+        };
+        Ok(status)
     }
     
-    pub fn import_data(&mut self, data: &[u8]) -> Result<()> {
-        let export_data: ExportData = bincode::deserialize(data)
-            .with_context(|| "Failed to deserialize import data")?;
+    /// Stage `update` to the status of the note `id`, on top of any write to
+    /// that note already in `batch`
+    fn stage_note_update(
+        &self,
+        batch: &mut StoreBatch,
+        id: NoteId,
+        update: impl FnOnce(&mut NoteStatus) -> Result<()>,
+    ) -> Result<()> {
+        let note_id = id.key();
+        let table = notes_table(id.pool);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
         
-        // Clear existing data
-        self.core_wallet.sapling_notes.clear();
-        self.core_wallet.orchard_notes.clear();
-        self.core_wallet.nullifier_set.nullifiers.clear();
-        self.core_wallet.airdrop_nullifier_set.nullifiers.clear();
+        let value = self.read(batch, table, note_id.as_bytes())?
+            .ok_or_else(|| anyhow::anyhow!("Note not found: {}", id))?;
+        let updated_bytes = match id.pool {
+            Pool::Sapling => {
+                let mut note_record: SaplingNoteRecord = self.open_record(note_id.as_bytes(), &value)
+                    .with_context(|| "Failed to decrypt Sapling note record")?;
+                update(&mut note_record.status)?;
+                note_record.last_used = Some(now);
+                self.seal_record(note_id.as_bytes(), &note_record)?
+            }
+            Pool::Orchard => {
+                let mut note_record: OrchardNoteRecord = self.open_record(note_id.as_bytes(), &value)
+                    .with_context(|| "Failed to decrypt Orchard note record")?;
+                update(&mut note_record.status)?;
+                note_record.last_used = Some(now);
+                self.seal_record(note_id.as_bytes(), &note_record)?
+            }
+        };
+        batch.insert(table, &note_id, updated_bytes);
+        
+        Ok(())
+    }
+    
+    // ==================== CLAIM SCHEDULING ====================
+    
+    /// Schedule the pending claims `txs` for submission under `policy`.
+    ///
+    /// The claims are shuffled and each is due a random delay after the
+    /// previous one, starting from `now` or from the last slot already
+    /// scheduled; the policy's decoy slots fall at random times in between.
+    pub fn schedule_claims(
+        &mut self,
+        txs: &[ShieldedAirdropTransaction],
+        policy: &SchedulePolicy,
+        now: u64,
+        rng: &mut impl Rng,
+    ) -> Result<Vec<ScheduledSlot>> {
+        let scheduled = self.schedule()?;
+        let start = scheduled.iter().map(|(_, slot)| slot.not_before).max().unwrap_or(now).max(now);
+        let mut txs = txs.to_vec();
+        txs.shuffle(rng);
         
-        // Import new data
-        for note in export_data.sapling_notes {
-            self.add_sapling_note(note)?;
+        let mut cursor = start;
+        let mut slots = Vec::new();
+        for tx in txs {
+            let tx_hash = hex::encode(tx.txid());
+            let record = self.claim(&tx_hash)?
+                .ok_or_else(|| anyhow::anyhow!("Unknown claim: {}", tx_hash))?;
+            if record.status != ClaimStatus::Pending {
+                anyhow::bail!("Claim {} is already {}", tx_hash, record.status);
+            }
+            if scheduled.iter().any(|(key, _)| *key == tx_hash) {
+                anyhow::bail!("Claim {} is already scheduled", tx_hash);
+            }
+            
+            cursor += policy.delay(rng)?;
+            let account = record.note.map(|id| self.note_account(id)).transpose()?.flatten();
+            let claim = ScheduledClaim { tx_hash: tx_hash.clone(), account, tx };
+            slots.push((tx_hash, ScheduledSlot { not_before: cursor, claim: Some(claim) }));
+        }
+        if !slots.is_empty() {
+            for _ in 0..policy.decoys {
+                let key = format!("decoy_{}", hex::encode(rng.gen::<[u8; 8]>()));
+                slots.push((key, ScheduledSlot { not_before: rng.gen_range(start..=cursor), claim: None }));
+            }
         }
         
-        for note in export_data.orchard_notes {
-            self.add_orchard_note(note)?;
+        let mut batch = StoreBatch::default();
+        for (key, slot) in &slots {
+            batch.insert(Table::ClaimSchedule, key, self.seal_record(key.as_bytes(), slot)?);
         }
+        self.commit(batch)?;
+        info!("Scheduled {} claims until {}", slots.iter().filter(|(_, slot)| !slot.is_decoy()).count(), cursor);
         
-        for nullifier in export_data.nullifier_set {
-            self.core_wallet.nullifier_set.insert(nullifier.try_into().unwrap());
+        let mut slots: Vec<ScheduledSlot> = slots.into_iter().map(|(_, slot)| slot).collect();
+        slots.sort_by_key(|slot| slot.not_before);
+        Ok(slots)
+    }
+    
+    /// Scheduled slots by key, earliest first
+    pub fn schedule(&self) -> Result<Vec<(String, ScheduledSlot)>> {
+        let mut slots: Vec<(String, ScheduledSlot)> = self.open_records(Table::ClaimSchedule)?;
+        slots.sort_by_key(|(_, slot)| slot.not_before);
+        Ok(slots)
+    }
+    
+    /// Act on every slot due at `now`: query the node for a decoy and
+    /// broadcast a claim through `client`.
+    ///
+    /// Broadcast claims are synced from the node first. A claim whose
+    /// account still has one broadcast but not yet included, rejected or
+    /// expired stays scheduled for a later run, as the two could otherwise
+    /// land in the same block. Slots whose claim is no longer pending are
+    /// dropped.
+    pub fn run_schedule(
+        &mut self,
+        client: &mut dyn NamadaClient,
+        policy: &SchedulePolicy,
+        now: u64,
+    ) -> Result<ScheduleRun> {
+        self.sync_claims(client)?;
+        let mut busy = HashSet::new();
+        for record in self.claims(Some(ClaimStatus::Broadcast))? {
+            busy.extend(self.claim_owner(record.note)?);
         }
         
-        for nullifier in export_data.airdrop_nullifier_set {
-            self.core_wallet.airdrop_nullifier_set.insert(nullifier.try_into().unwrap());
+        let mut run = ScheduleRun::default();
+        for (key, slot) in self.schedule()? {
+            if slot.not_before > now {
+                break;
+            }
+            let mut batch = StoreBatch::default();
+            batch.remove(Table::ClaimSchedule, &key);
+            
+            let Some(claim) = slot.claim else {
+                client.latest_height()?;
+                self.commit(batch)?;
+                run.decoys += 1;
+                continue;
+            };
+            let Some(record) = self.claim(&claim.tx_hash)?.filter(|record| record.status == ClaimStatus::Pending) else {
+                self.commit(batch)?;
+                run.dropped += 1;
+                continue;
+            };
+            if self.claim_owner(record.note)?.is_some_and(|owner| !busy.insert(owner)) {
+                run.postponed += 1;
+                continue;
+            }
+            
+            let status = self.broadcast_claim(client, &claim.tx, policy.expiry_blocks)?;
+            self.commit(batch)?;
+            run.submitted.push((claim.tx_hash, status));
         }
+        Ok(run)
+    }
+    
+    /// Whose claims the claims of the note `id` must not share a height with;
+    /// none when the note is not one of ours
+    fn claim_owner(&self, id: Option<NoteId>) -> Result<Option<ClaimOwner>> {
+        let Some(id) = id else {
+            return Ok(None);
+        };
+        Ok(Some(match self.note_account(id)? {
+            Some(account) => ClaimOwner::Account(account),
+            None => ClaimOwner::Note(id),
+        }))
+    }
+    
+    /// Tag by which a relay queue keeps the claims of `owner` apart. An
+    /// account's is a hash of its viewing keys, the same in every wallet
+    /// holding the account; a note held by no account needs none, as the
+    /// relay goes by the airdrop nullifier its claims carry.
+    fn relay_tag(&self, owner: Option<ClaimOwner>) -> Result<Option<[u8; 32]>> {
+        let Some(ClaimOwner::Account(account)) = owner else {
+            return Ok(None);
+        };
+        let viewing = self.viewing_account(account)?;
+        let mut state = blake2s_simd::Params::new().hash_length(32).personal(b"ZNRelayO").to_state();
+        for keys in [&viewing.sapling, &viewing.orchard].into_iter().flatten() {
+            state.update(&keys.fvk);
+        }
+        let mut tag = [0u8; 32];
+        tag.copy_from_slice(state.finalize().as_bytes());
+        Ok(Some(tag))
+    }
+    
+    // ==================== CONSISTENCY ====================
+    
+    /// Compare the in-memory notes and the note index with the stored note
+    /// records, and the records with the nullifiers and claims they refer to
+    pub fn check_consistency(&self) -> Result<Vec<Discrepancy>> {
+        self.data_key()?;
+        let owners = self.viewing_accounts();
+        let mut found = Vec::new();
         
-        info!("Imported wallet data successfully");
-        Ok(())
+        let mut memory: BTreeMap<NoteId, Vec<(u64, NoteCommitment)>> = BTreeMap::new();
+        for note in &self.core_wallet.sapling_notes {
+            memory.entry(note.id()).or_default().push((note.value, note.note_commitment));
+        }
+        for note in &self.core_wallet.orchard_notes {
+            memory.entry(note.id()).or_default().push((note.value, note.note_commitment));
+        }
+        
+        let mut stored = BTreeMap::new();
+        for (_, record) in self.open_records::<SaplingNoteRecord>(Table::SaplingNotes)? {
+            let held = (record.note.value, record.note.note_commitment);
+            stored.insert(record.note.id(), (held, record.metadata(&owners)?));
+        }
+        for (_, record) in self.open_records::<OrchardNoteRecord>(Table::OrchardNotes)? {
+            let held = (record.note.value, record.note.note_commitment);
+            stored.insert(record.note.id(), (held, record.metadata(&owners)?));
+        }
+        
+        for (id, (held, metadata)) in &stored {
+            if memory.remove(id) != Some(vec![*held]) {
+                found.push(Discrepancy::MemoryNote(*id));
+            }
+            if self.notes.get(*id) != Some(metadata) {
+                found.push(Discrepancy::IndexedNote(*id));
+            }
+        }
+        found.extend(memory.into_keys().map(Discrepancy::MemoryNote));
+        for note in self.notes.select(&NoteQuery::default()) {
+            if !stored.contains_key(&note.id()) {
+                found.push(Discrepancy::IndexedNote(note.id()));
+            }
+        }
+        
+        for (key, _) in self.store.entries(Table::NoteNullifiers)? {
+            let id = NoteId::from_key(&key)?;
+            if !stored.contains_key(&id) {
+                found.push(Discrepancy::OrphanNullifier(id));
+            }
+        }
+        for (key, _) in self.store.entries(Table::NoteSpends)? {
+            let id = NoteId::from_key(&key)?;
+            if !stored.get(&id).is_some_and(|(_, note)| note.status.is_spent) {
+                found.push(Discrepancy::StaleSpendHeight(id));
+            }
+        }
+        
+        let claims: HashMap<String, TransactionRecord> = self.claims(None)?
+            .into_iter()
+            .map(|record| (record.tx_hash.clone(), record))
+            .collect();
+        for (id, (_, note)) in &stored {
+            let Some(claim) = &note.status.reserved_by else {
+                continue;
+            };
+            let under_way = claims.get(claim)
+                .is_some_and(|record| matches!(record.status, ClaimStatus::Pending | ClaimStatus::Broadcast));
+            if !under_way {
+                found.push(Discrepancy::StaleReservation { note: *id, claim: claim.clone() });
+            }
+        }
+        for record in claims.values().filter(|record| record.status == ClaimStatus::Confirmed) {
+            let Some((id, (_, note))) = record.note.and_then(|id| stored.get_key_value(&id)) else {
+                continue;
+            };
+            if !note.status.is_claimed {
+                found.push(Discrepancy::UnmarkedClaim { note: *id, claim: record.tx_hash.clone() });
+            }
+        }
+        
+        Ok(found)
     }
     
-    pub fn mark_note_as_spent(&mut self, note_type: &str, position: u64) -> Result<()> {
-        let note_id = format!("{}_{}", note_type, position);
-        let tree_name = format!("{}_notes", note_type);
-        
-        let tree = self.db.open_tree(&tree_name)?;
-        if let Some(value) = tree.get(&note_id.as_bytes())? {
-            match note_type {
-                "sapling" => {
-                    let mut note_record: SaplingNoteRecord = bincode::deserialize(&value)
-                        .with_context(|| "Failed to deserialize Sapling note record")?;
-                    note_record.is_spent = true;
-                    note_record.last_used = Some(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs());
-                    
-                    let updated_bytes = bincode::serialize(&note_record)
-                        .with_context(|| "Failed to serialize updated Sapling note record")?;
-                    tree.insert(note_id.as_bytes(), updated_bytes)?;
-                    
-                    info!("Marked Sapling note at position {} as spent", position);
-                }
-                "orchard" => {
-                    let mut note_record: OrchardNoteRecord = bincode::deserialize(&value)
-                        .with_context(|| "Failed to deserialize Orchard note record")?;
-                    note_record.is_spent = true;
-                    note_record.last_used = Some(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs());
-                    
-                    let updated_bytes = bincode::serialize(&note_record)
-                        .with_context(|| "Failed to serialize updated Orchard note record")?;
-                    tree.insert(note_id.as_bytes(), updated_bytes)?;
-                    
-                    info!("Marked Orchard note at position {} as spent", position);
+    /// Fix every discrepancy [`Self::check_consistency`] finds, returning them.
+    ///
+    /// Orphan nullifiers and stale spend heights are removed, stale
+    /// reservations released, and notes
+    /// of confirmed claims marked as claimed, in one atomic write; the
+    /// in-memory notes and the note index are then rebuilt from the store.
+    pub fn repair(&mut self) -> Result<Vec<Discrepancy>> {
+        let found = self.check_consistency()?;
+        let mut batch = StoreBatch::default();
+        for discrepancy in &found {
+            match discrepancy {
+                Discrepancy::OrphanNullifier(id) => batch.remove(Table::NoteNullifiers, id.key()),
+                Discrepancy::StaleSpendHeight(id) => batch.remove(Table::NoteSpends, id.key()),
+                Discrepancy::StaleReservation { note, claim } => {
+                    self.stage_note_update(&mut batch, *note, |status| {
+                        if status.reserved_by.as_ref() == Some(claim) {
+                            status.reserved_by = None;
+                        }
+                        Ok(())
+                    })?;
                 }
-                _ => {
-                    return Err(anyhow::anyhow!("Invalid note type: {}", note_type));
+                Discrepancy::UnmarkedClaim { note, claim } => {
+                    self.stage_note_update(&mut batch, *note, |status| {
+                        status.is_claimed = true;
+                        if status.reserved_by.as_ref() == Some(claim) {
+                            status.reserved_by = None;
+                        }
+                        Ok(())
+                    })?;
                 }
+                Discrepancy::MemoryNote(_) | Discrepancy::IndexedNote(_) => {}
+            }
+        }
+        self.commit(batch)?;
+        self.reload_notes()?;
+        if !found.is_empty() {
+            info!("Repaired {} wallet discrepancies", found.len());
+        }
+        Ok(found)
+    }
+    
+    // ==================== ATOMIC WRITES ====================
+    
+    /// Read `key` from `table`, as it will be once `batch` is committed
+    fn read(&self, batch: &StoreBatch, table: Table, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        if let Some(staged) = batch.staged(table, key) {
+            return Ok(staged.map(<[u8]>::to_vec));
+        }
+        Ok(self.store.get(table, key)?)
+    }
+    
+    /// Apply every write in `batch` atomically, so a crash leaves either all
+    /// of them or none in the store, then bring the note index up to date
+    fn commit(&mut self, batch: StoreBatch) -> Result<()> {
+        #[cfg(test)]
+        let batch = batch.aborting_at(self.crash_after_writes);
+        self.store.commit(&batch).with_context(|| "Failed to write wallet")?;
+        self.index_notes(&batch)
+    }
+    
+    /// Apply the note writes of a committed `batch` to the note index
+    fn index_notes(&mut self, batch: &StoreBatch) -> Result<()> {
+        // Records of a locked wallet cannot be read; unlocking indexes them
+        if self.is_locked() {
+            return Ok(());
+        }
+        let owners = self.viewing_accounts();
+        for pool in [Pool::Sapling, Pool::Orchard] {
+            for (key, value) in batch.writes_to(notes_table(pool)) {
+                let Some(value) = value else {
+                    self.notes.remove(NoteId::from_key(key)?)?;
+                    continue;
+                };
+                let note = match pool {
+                    Pool::Sapling => self.open_record::<SaplingNoteRecord>(key, value)?.metadata(&owners)?,
+                    Pool::Orchard => self.open_record::<OrchardNoteRecord>(key, value)?.metadata(&owners)?,
+                };
+                self.notes.insert(note)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The account among `owners` holding a note of `pool` with nullifier deriving key `nk`
+fn note_account(owners: &[ViewingAccount], pool: Pool, nk: &Scalar) -> Option<u32> {
+    owners.iter().find(|viewing| viewing.owns(pool, nk)).map(|viewing| viewing.account)
+}
+
+fn notes_table(pool: Pool) -> Table {
+    match pool {
+        Pool::Sapling => Table::SaplingNotes,
+        Pool::Orchard => Table::OrchardNotes,
+    }
+}
+
+// ==================== SCHEMA MIGRATIONS ====================
+
+/// Note record of an unversioned wallet
+#[derive(Deserialize)]
+struct LegacyNoteRecord<N> {
+    note: N,
+    created_at: u64,
+    is_spent: bool,
+    last_used: Option<u64>,
+}
+
+/// Claim record of an unversioned wallet
+#[derive(Deserialize)]
+struct LegacyTransactionRecord {
+    tx_hash: String,
+    airdrop_nullifier: Vec<u8>,
+    amount: u64,
+    recipient: String,
+    status: String,
+    created_at: u64,
+    confirmed_at: Option<u64>,
+    block_height: Option<u64>,
+}
+
+impl AirdropWallet {
+    fn read_schema_version(store: &dyn WalletStore) -> Result<u32> {
+        match store.get(Table::Metadata, b"schema_version")? {
+            Some(bytes) => Ok(u32::from_be_bytes(bytes.as_slice().try_into()
+                .map_err(|_| anyhow::anyhow!("Malformed wallet schema version"))?)),
+            None => Ok(0),
+        }
+    }
+    
+    /// The layout version of this wallet's database
+    pub fn schema_version(&self) -> Result<u32> {
+        Self::read_schema_version(self.store.as_ref())
+    }
+    
+    /// Bring the database up to [`SCHEMA_VERSION`], one version at a time
+    fn migrate(&mut self) -> Result<()> {
+        let mut version = self.schema_version()?;
+        while version < SCHEMA_VERSION {
+            // Each step lands together with its version number
+            let mut batch = StoreBatch::default();
+            match version {
+                0 => self.migrate_unversioned(&mut batch)?,
+                _ => unreachable!("no migration from schema version {}", version),
             }
-        } else {
-            return Err(anyhow::anyhow!("Note not found: {}", note_id));
+            version += 1;
+            batch.insert(Table::Metadata, "schema_version", version.to_be_bytes());
+            self.commit(batch)?;
+            info!("Migrated wallet database to schema version {}", version);
+        }
+        Ok(())
+    }
+    
+    /// Version 0 to 1: split each note's `is_spent` flag using the claims
+    /// recorded for it, and type the claim statuses.
+    ///
+    /// A flagged note with a pending claim is reserved by that claim, one
+    /// with a confirmed claim is claimed, and one without a claim was spent
+    /// on Zcash. A failed claim never consumed its note.
+    fn migrate_unversioned(&self, batch: &mut StoreBatch) -> Result<()> {
+        let mut claims = HashMap::new();
+        for (key, legacy) in self.open_records::<LegacyTransactionRecord>(Table::Transactions)? {
+            let status = match legacy.status.as_str() {
+                "pending" => ClaimStatus::Pending,
+                "confirmed" => ClaimStatus::Confirmed,
+                "failed" => ClaimStatus::Failed,
+                other => anyhow::bail!("Claim {} has unknown status {:?}", key, other),
+            };
+            claims.insert(legacy.airdrop_nullifier.clone(), (legacy, status));
+        }
+        
+        let mut notes = HashMap::new();
+        for (key, legacy) in self.open_records::<LegacyNoteRecord<SaplingNote>>(Table::SaplingNotes)? {
+            let airdrop_nullifier = legacy.note.airdrop_nullifier()?;
+            let record = SaplingNoteRecord {
+                status: Self::migrated_status(legacy.is_spent, claims.get(airdrop_nullifier.0.as_slice())),
+                note: legacy.note,
+                created_at: legacy.created_at,
+                last_used: legacy.last_used,
+            };
+            notes.insert(airdrop_nullifier.0.to_vec(), record.note.id());
+            batch.insert(Table::SaplingNotes, &key, self.seal_record(key.as_bytes(), &record)?);
+        }
+        for (key, legacy) in self.open_records::<LegacyNoteRecord<OrchardNote>>(Table::OrchardNotes)? {
+            let airdrop_nullifier = legacy.note.airdrop_nullifier()?;
+            let record = OrchardNoteRecord {
+                status: Self::migrated_status(legacy.is_spent, claims.get(airdrop_nullifier.0.as_slice())),
+                note: legacy.note,
+                created_at: legacy.created_at,
+                last_used: legacy.last_used,
+            };
+            notes.insert(airdrop_nullifier.0.to_vec(), record.note.id());
+            batch.insert(Table::OrchardNotes, &key, self.seal_record(key.as_bytes(), &record)?);
+        }
+        
+        for (legacy, status) in claims.into_values() {
+            self.stage_claim(batch, &TransactionRecord {
+                note: notes.get(&legacy.airdrop_nullifier).copied(),
+                tx_hash: legacy.tx_hash,
+                airdrop_nullifier: legacy.airdrop_nullifier,
+                amount: legacy.amount,
+                recipient: legacy.recipient,
+                status,
+                created_at: legacy.created_at,
+                broadcast_height: None,
+                expiry_height: None,
+                confirmed_at: legacy.confirmed_at,
+                block_height: legacy.block_height,
+                failure: None,
+            })?;
         }
         
+        // Unversioned wallets wrote empty placeholders for their trees into
+        // the default tree
+        for placeholder in ["sapling_notes", "orchard_notes", "transactions", "nullifier_set", "airdrop_nullifier_set"] {
+            batch.remove(Table::Metadata, placeholder);
+        }
         Ok(())
     }
     
-    pub fn mark_note_as_spent_by_index(&mut self, note_type: &str, note_index: usize) -> Result<()> {
-        match note_type {
-            "sapling" => {
-                if note_index < self.core_wallet.sapling_notes.len() {
-                    let note = &self.core_wallet.sapling_notes[note_index];
-                    self.mark_note_as_spent("sapling", note.position)?;
-                }
+    fn migrated_status(is_spent: bool, claim: Option<&(LegacyTransactionRecord, ClaimStatus)>) -> NoteStatus {
+        match claim {
+            Some((legacy, ClaimStatus::Pending)) if is_spent => NoteStatus {
+                reserved_by: Some(legacy.tx_hash.clone()),
+                ..NoteStatus::default()
+            },
+            Some((_, ClaimStatus::Confirmed)) => NoteStatus { is_claimed: true, ..NoteStatus::default() },
+            Some(_) => NoteStatus::default(),
+            None => NoteStatus { is_spent, ..NoteStatus::default() },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{NoteCommitment, Scalar};
+
+    fn test_note(position: u64) -> SaplingNote {
+        SaplingNote {
+            diversifier: [0u8; 11],
+            value: 1000000,
+            note_commitment: NoteCommitment([position as u8; 32]),
+            nullifier_key: Scalar([2u8; 32]),
+            randomness: Scalar([position as u8; 32]),
+            position,
+        }
+    }
+
+    fn test_seed() -> WalletSeed {
+        WalletSeed::from_phrase(&crate::scanner::tests::test_seed_phrase()).unwrap()
+    }
+
+    /// A note of account 0 of [`test_seed`], which a wallet holding the seed can claim
+    fn owned_note(position: u64) -> SaplingNote {
+        static NK: std::sync::OnceLock<Scalar> = std::sync::OnceLock::new();
+        let nk = NK.get_or_init(|| {
+            test_seed().derive_account(1, 0).unwrap().viewing_keys(Pool::Sapling).nullifier_keys()[0]
+        });
+        SaplingNote { nullifier_key: *nk, ..test_note(position) }
+    }
+
+    /// Values of the notes that can still claim, per pool
+    fn claimable(wallet: &AirdropWallet) -> (u64, u64) {
+        let balance = wallet.get_balance().unwrap();
+        (balance.sapling.claimable, balance.orchard.claimable)
+    }
+    
+    /// Values of the notes unspent on Zcash, per pool
+    fn unspent(wallet: &AirdropWallet) -> (u64, u64) {
+        let balance = wallet.get_balance().unwrap();
+        (balance.sapling.unspent, balance.orchard.unspent)
+    }
+    
+    #[test]
+    fn test_wallet_persists_notes_and_claims() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wallet.db");
+        let recipient = PublicKey([4u8; 32]);
+
+        let tx = {
+            let mut wallet = AirdropWallet::new(&path, "test", "testnet", "passphrase").unwrap();
+            wallet.import_seed(&test_seed()).unwrap();
+            wallet.add_sapling_note(owned_note(0)).unwrap();
+            wallet.add_sapling_note(owned_note(1)).unwrap();
+
+            let tx = wallet.create_airdrop_tx(NoteId::new(Pool::Sapling, 0), 1000000, &recipient).unwrap();
+            wallet.process_airdrop_transaction(&tx, 5).unwrap();
+            tx
+        };
+
+        let mut wallet = AirdropWallet::load(&path, "passphrase").unwrap();
+        assert_eq!(wallet.get_metadata().name, "test");
+        assert_eq!(wallet.core().sapling_notes.len(), 2);
+        assert_eq!(claimable(&wallet), (1000000, 0));
+
+        // The claim recorded before the restart is still rejected
+        assert!(wallet.process_airdrop_transaction(&tx, 6).is_err());
+    }
+    
+    #[test]
+    fn test_claim_lifecycle_follows_namada() {
+        use crate::namada::LocalNamadaChain;
+        
+        let dir = tempfile::tempdir().unwrap();
+        let mut wallet = AirdropWallet::new(&dir.path().join("wallet.db"), "test", "testnet", "pw").unwrap();
+        wallet.import_seed(&test_seed()).unwrap();
+        let mut claims = Vec::new();
+        for position in 0..3 {
+            wallet.add_sapling_note(owned_note(position)).unwrap();
+            let tx = wallet.create_airdrop_tx(NoteId::new(Pool::Sapling, position), 500000, &PublicKey([4u8; 32])).unwrap();
+            claims.push(tx);
+        }
+        assert_eq!(wallet.claims(Some(ClaimStatus::Pending)).unwrap().len(), 3);
+        assert_eq!(claimable(&wallet), (0, 0));
+        
+        // Included claims are confirmed with their block height
+        let mut chain = LocalNamadaChain::new(AirdropPolicy::default());
+        assert_eq!(wallet.broadcast_claim(&mut chain, &claims[0], 2).unwrap(), ClaimStatus::Broadcast);
+        assert_eq!(wallet.broadcast_claim(&mut chain, &claims[1], 2).unwrap(), ClaimStatus::Broadcast);
+        chain.evict(&claims[1].txid());
+        chain.produce_block();
+        let updated = wallet.sync_claims(&chain).unwrap();
+        assert_eq!(updated.len(), 1);
+        assert_eq!((updated[0].status, updated[0].block_height), (ClaimStatus::Confirmed, Some(1)));
+        
+        // A claim never included expires once its expiry height has passed
+        chain.produce_block();
+        assert!(wallet.sync_claims(&chain).unwrap().is_empty());
+        chain.produce_block();
+        assert_eq!(wallet.sync_claims(&chain).unwrap()[0].status, ClaimStatus::Expired);
+        
+        // An expiry past the last representable height is refused before
+        // anything is sent
+        assert!(wallet.broadcast_claim(&mut chain, &claims[2], u64::MAX).is_err());
+        assert_eq!(wallet.claim(&hex::encode(claims[2].txid())).unwrap().unwrap().status, ClaimStatus::Pending);
+        
+        // A claim the node refuses fails immediately
+        let mut strict = LocalNamadaChain::new(AirdropPolicy { rate_numerator: 1, rate_denominator: 4 });
+        assert_eq!(wallet.broadcast_claim(&mut strict, &claims[2], 2).unwrap(), ClaimStatus::Failed);
+        assert!(wallet.claim(&hex::encode(claims[2].txid())).unwrap().unwrap().failure.is_some());
+        
+        // Failed and expired claims return their notes; finished claims stay put
+        assert_eq!(claimable(&wallet), (2000000, 0));
+        assert!(wallet.broadcast_claim(&mut chain, &claims[0], 2).is_err());
+        for status in [ClaimStatus::Confirmed, ClaimStatus::Expired, ClaimStatus::Failed] {
+            assert_eq!(wallet.claims(Some(status)).unwrap().len(), 1);
+        }
+        assert!(!ClaimStatus::Confirmed.can_transition_to(ClaimStatus::Failed));
+    }
+    
+    #[test]
+    fn test_interrupted_writes_leave_no_partial_state() {
+        use crate::namada::LocalNamadaChain;
+        use crate::NullifierRegistry;
+        
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wallet.db");
+        let id = NoteId::new(Pool::Sapling, 0);
+        let recipient = PublicKey([4u8; 32]);
+        let mut chain = LocalNamadaChain::new(AirdropPolicy::default());
+        
+        {
+            let mut wallet = AirdropWallet::new(&path, "test", "testnet", "pw").unwrap();
+            wallet.import_seed(&test_seed()).unwrap();
+            wallet.add_sapling_note(owned_note(0)).unwrap();
+            
+            // A crash between reserving the note and recording the claim
+            // loses both
+            wallet.crash_after_writes = Some(1);
+            assert!(wallet.create_airdrop_tx(id, 1000, &recipient).is_err());
+            wallet.crash_after_writes = None;
+            assert!(wallet.claims(None).unwrap().is_empty());
+            assert_eq!(wallet.list_notes(None, None).unwrap()[0].status, NoteStatus::default());
+            
+            let tx = wallet.create_airdrop_tx(id, 1000, &recipient).unwrap();
+            wallet.broadcast_claim(&mut chain, &tx, 10).unwrap();
+            chain.produce_block();
+            
+            // A crash while confirming keeps the claim broadcast, the note
+            // reserved and the nullifier out of the registry
+            wallet.crash_after_writes = Some(2);
+            assert!(wallet.sync_claims(&chain).is_err());
+        }
+        
+        let mut wallet = AirdropWallet::load(&path, "pw").unwrap();
+        let record = wallet.claims(None).unwrap().remove(0);
+        let nullifier = Nullifier(record.airdrop_nullifier.clone().try_into().unwrap());
+        let registry = StoreNullifierRegistry::new(wallet.store.clone());
+        assert_eq!(record.status, ClaimStatus::Broadcast);
+        assert_eq!(wallet.list_notes(None, None).unwrap()[0].status.reserved_by, Some(record.tx_hash));
+        assert!(!registry.contains(&nullifier).unwrap());
+        
+        // Syncing again completes the confirmation
+        assert_eq!(wallet.sync_claims(&chain).unwrap()[0].status, ClaimStatus::Confirmed);
+        assert!(wallet.list_notes(None, None).unwrap()[0].status.is_claimed);
+        assert!(registry.contains(&nullifier).unwrap());
+    }
+    
+    #[test]
+    fn test_wallet_runs_on_every_backend() {
+        use crate::store::{open_store, StorageBackend};
+        
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wallet.sqlite");
+        for backend in [StorageBackend::Sqlite, StorageBackend::Memory] {
+            let store = open_store(backend, &path).unwrap();
+            {
+                let mut wallet = AirdropWallet::create(store.clone(), "test", "testnet", "pw").unwrap();
+                wallet.import_seed(&test_seed()).unwrap();
+                wallet.add_sapling_note(owned_note(0)).unwrap();
+                wallet.create_airdrop_tx(NoteId::new(Pool::Sapling, 0), 1000, &PublicKey([4u8; 32])).unwrap();
             }
-            "orchard" => {
-                if note_index < self.core_wallet.orchard_notes.len() {
-                    let note = &self.core_wallet.orchard_notes[note_index];
-                    self.mark_note_as_spent("orchard", note.position)?;
-                }
+            
+            // A SQLite wallet survives reopening its file
+            let store = match backend {
+                StorageBackend::Memory => store,
+                _ => open_store(backend, &path).unwrap(),
+            };
+            let mut wallet = AirdropWallet::open_store(store).unwrap();
+            wallet.unlock("pw").unwrap();
+            assert_eq!(wallet.claims(Some(ClaimStatus::Pending)).unwrap().len(), 1);
+            assert_eq!(claimable(&wallet), (0, 0));
+        }
+    }
+    
+    #[test]
+    fn test_import_merges_or_replaces_notes() {
+        use crate::export::{ExportFormat, WalletExport};
+        
+        let dir = tempfile::tempdir().unwrap();
+        let mut source = AirdropWallet::new(&dir.path().join("source.db"), "source", "testnet", "pw").unwrap();
+        source.import_seed(&test_seed()).unwrap();
+        source.add_sapling_note(owned_note(0)).unwrap();
+        source.add_sapling_note(owned_note(1)).unwrap();
+        source.create_airdrop_tx(NoteId::new(Pool::Sapling, 1), 1000, &PublicKey([4u8; 32])).unwrap();
+        let bytes = source.export_data().unwrap().encode(ExportFormat::Json, None).unwrap();
+        let export = || WalletExport::decode(&bytes, None).unwrap();
+        
+        // Merging keeps the wallet's own notes and skips the ones it shares
+        let mut wallet = AirdropWallet::new(&dir.path().join("merge.db"), "merge", "testnet", "pw").unwrap();
+        wallet.add_sapling_note(test_note(0)).unwrap();
+        wallet.add_sapling_note(test_note(5)).unwrap();
+        let summary = wallet.import_data(export(), ImportMode::Merge).unwrap();
+        assert_eq!(summary, WalletImportSummary { notes: 1, duplicates: 1, claims: 1 });
+        assert_eq!(wallet.core().sapling_notes.len(), 3);
+        assert_eq!(claimable(&wallet), (2000000, 0));
+        
+        // Replacing drops them
+        wallet.import_data(export(), ImportMode::Replace).unwrap();
+        let positions: Vec<u64> = wallet.list_notes(None, None).unwrap().iter().map(|n| n.position).collect();
+        assert_eq!(positions, vec![0, 1]);
+        
+        // A different note at a shared position aborts the whole merge
+        let mut conflicting = AirdropWallet::new(&dir.path().join("conflict.db"), "conflict", "testnet", "pw").unwrap();
+        conflicting.add_sapling_note(SaplingNote { note_commitment: NoteCommitment([9u8; 32]), ..test_note(0) }).unwrap();
+        let err = conflicting.import_data(export(), ImportMode::Merge).unwrap_err();
+        assert!(err.to_string().contains("sapling:0"), "{}", err);
+        assert_eq!(conflicting.list_notes(None, None).unwrap().len(), 1);
+        assert!(conflicting.claims(None).unwrap().is_empty());
+    }
+    
+    #[test]
+    fn test_replaced_notes_are_still_watched_for_spends() {
+        use crate::export::{ExportFormat, WalletExport};
+        use crate::scanner::proto::{CompactSaplingSpend, CompactTx};
+        use crate::scanner::tests::*;
+        use crate::store::MemoryStore;
+        
+        let ours = test_seed().derive_account(1, 0).unwrap();
+        let received = block(HEIGHT, vec![CompactTx {
+            outputs: vec![sapling_output_to(&ours, 1000)],
+            ..Default::default()
+        }]);
+        let scanned_wallet = || {
+            let mut wallet = AirdropWallet::create(Arc::new(MemoryStore::new()), "test", "testnet", "pw").unwrap();
+            wallet.import_seed(&test_seed()).unwrap();
+            wallet.scan_blocks(std::slice::from_ref(&received), None).unwrap();
+            wallet
+        };
+        let bytes = scanned_wallet().export_data().unwrap().encode(ExportFormat::Bincode, None).unwrap();
+        let export = WalletExport::decode(&bytes, None).unwrap();
+        let nullifier = export.sapling_notes[0].nullifier.unwrap();
+        
+        // A wallet that already scanned past the note replaces its records
+        // and still sees the note spent in a later block
+        let mut wallet = scanned_wallet();
+        wallet.import_data(export, ImportMode::Replace).unwrap();
+        let spend = block(HEIGHT + 1, vec![CompactTx {
+            spends: vec![CompactSaplingSpend { nf: nullifier.0.to_vec() }],
+            ..Default::default()
+        }]);
+        assert_eq!(wallet.scan_blocks(&[spend], None).unwrap().spends, 1);
+        assert_eq!(unspent(&wallet), (0, 0));
+        
+        // The spend height travels with the next export
+        let export = wallet.export_data().unwrap();
+        assert_eq!(export.sapling_notes[0].spent_at, Some(HEIGHT + 1));
+        let mut copy = scanned_wallet();
+        copy.import_data(export, ImportMode::Replace).unwrap();
+        assert!(copy.spent_since(HEIGHT + 1).unwrap().contains(&NoteId::new(Pool::Sapling, 0)));
+        assert!(copy.check_consistency().unwrap().is_empty());
+    }
+    
+    #[test]
+    fn test_claims_reserve_notes_until_resolved() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut wallet = AirdropWallet::new(&dir.path().join("wallet.db"), "test", "testnet", "pw").unwrap();
+        wallet.import_seed(&test_seed()).unwrap();
+        wallet.add_sapling_note(owned_note(0)).unwrap();
+        let id = NoteId::new(Pool::Sapling, 0);
+        let recipient = PublicKey([4u8; 32]);
+        
+        // A failed build leaves the note untouched
+        assert!(wallet.create_airdrop_tx(id, 2000000, &recipient).is_err());
+        assert_eq!(claimable(&wallet), (1000000, 0));
+        
+        // A built claim reserves the note against a second claim
+        let tx = wallet.create_airdrop_tx(id, 1000, &recipient).unwrap();
+        let tx_hash = hex::encode(tx.txid());
+        let status = wallet.list_notes(None, None).unwrap()[0].status.clone();
+        assert_eq!(status.reserved_by.as_deref(), Some(tx_hash.as_str()));
+        assert!(!status.is_claimed);
+        assert!(wallet.create_airdrop_tx(id, 1000, &recipient).is_err());
+        assert_eq!(claimable(&wallet), (0, 0));
+        
+        // Forcing a release abandons the claim and frees the note
+        assert_eq!(wallet.force_release(id).unwrap(), Some(tx_hash.clone()));
+        assert_eq!(wallet.claim(&tx_hash).unwrap().unwrap().status, ClaimStatus::Failed);
+        assert_eq!(claimable(&wallet), (1000000, 0));
+        
+        // The note is claimed once a new claim is confirmed
+        let tx = wallet.create_airdrop_tx(id, 1000, &recipient).unwrap();
+        let mut chain = crate::namada::LocalNamadaChain::new(AirdropPolicy::default());
+        wallet.broadcast_claim(&mut chain, &tx, 10).unwrap();
+        chain.produce_block();
+        wallet.sync_claims(&chain).unwrap();
+        let status = &wallet.list_notes(None, None).unwrap()[0].status;
+        assert!(status.is_claimed && status.reserved_by.is_none() && !status.is_spent);
+        assert_eq!(wallet.get_balance().unwrap().sapling.claimed, 1000000);
+        assert!(wallet.find_eligible_notes(0).unwrap().is_empty());
+        assert_eq!(wallet.force_release(id).unwrap(), None);
+        
+        // A confirmed claim marks its note claimed even when another claim
+        // holds the reservation, and leaves that reservation alone
+        wallet.add_sapling_note(owned_note(1)).unwrap();
+        let id = NoteId::new(Pool::Sapling, 1);
+        let tx = wallet.create_airdrop_tx(id, 1000, &recipient).unwrap();
+        wallet.broadcast_claim(&mut chain, &tx, 10).unwrap();
+        let mut batch = StoreBatch::default();
+        wallet.stage_note_update(&mut batch, id, |status| {
+            status.reserved_by = Some("other".to_string());
+            Ok(())
+        }).unwrap();
+        wallet.commit(batch).unwrap();
+        chain.produce_block();
+        wallet.sync_claims(&chain).unwrap();
+        let status = wallet.note_status(id).unwrap();
+        assert!(status.is_claimed);
+        assert_eq!(status.reserved_by.as_deref(), Some("other"));
+    }
+
+    #[test]
+    fn test_note_queries_follow_committed_writes() {
+        use crate::index::{ClaimState, NoteQuery, NoteSort};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wallet.db");
+        let mut wallet = AirdropWallet::new(&path, "test", "testnet", "pw").unwrap();
+        wallet.import_seed(&test_seed()).unwrap();
+        for position in 0..12 {
+            wallet.add_sapling_note(SaplingNote { value: 1000 * (12 - position), ..owned_note(position) }).unwrap();
+        }
+        wallet.create_airdrop_tx(NoteId::new(Pool::Sapling, 3), 1000, &PublicKey([4u8; 32])).unwrap();
+        wallet.mark_note_as_spent(NoteId::new(Pool::Sapling, 4)).unwrap();
+
+        // Pages of the largest eligible notes, with the total across pages
+        let query = NoteQuery {
+            eligible: true,
+            sort: NoteSort::Value,
+            descending: true,
+            offset: 2,
+            limit: Some(3),
+            ..NoteQuery::default()
+        };
+        let page = wallet.query_notes(&query).unwrap();
+        assert_eq!(page.total, 11);
+        let positions: Vec<u64> = page.notes.iter().map(|note| note.position).collect();
+        assert_eq!(positions, [2, 4, 5]);
+
+        let reserved = NoteQuery { claim_state: Some(ClaimState::Reserved), ..NoteQuery::default() };
+        assert_eq!(wallet.query_notes(&reserved).unwrap().notes[0].position, 3);
+        let unspent = NoteQuery { unspent: true, min_value: Some(8000), ..NoteQuery::default() };
+        assert_eq!(wallet.query_notes(&unspent).unwrap().total, 4);
+
+        // Running totals match the records, and an interrupted write changes neither
+        let balance = wallet.get_balance().unwrap();
+        assert_eq!((balance.sapling.claimable, balance.sapling.reserved, balance.sapling.unspent), (69000, 9000, 70000));
+        wallet.crash_after_writes = Some(0);
+        assert!(wallet.mark_note_as_spent(NoteId::new(Pool::Sapling, 0)).is_err());
+        wallet.crash_after_writes = None;
+        assert_eq!(wallet.get_balance().unwrap(), balance);
+
+        drop(wallet);
+        let wallet = AirdropWallet::load(&path, "pw").unwrap();
+        assert_eq!(wallet.get_balance().unwrap(), balance);
+        assert_eq!(wallet.query_notes(&query).unwrap().notes.len(), 3);
+    }
+
+    #[test]
+    fn test_executed_plan_reserves_its_notes() {
+        use crate::planner::{PlanOptions, SelectionStrategy};
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut wallet = AirdropWallet::new(&dir.path().join("wallet.db"), "test", "testnet", "pw").unwrap();
+        wallet.import_seed(&test_seed()).unwrap();
+        for position in 0..4 {
+            wallet.add_sapling_note(SaplingNote { value: 1000 * (position + 1), ..owned_note(position) }).unwrap();
+        }
+
+        let options = PlanOptions { strategy: SelectionStrategy::Spread, min_value: 2000, ..PlanOptions::default() };
+        let plan = wallet.plan_claims(&options).unwrap();
+        assert_eq!((plan.claims.len(), plan.total_value), (3, 9000));
+
+        // Building one batch leaves the rest of the notes to a later plan
+        let txs = wallet.execute_plan(&plan, Some(0), &PublicKey([4u8; 32])).unwrap();
+        assert_eq!(txs.len(), 1);
+        assert_eq!(wallet.plan_claims(&options).unwrap().claims.len(), 2);
+        wallet.execute_plan(&wallet.plan_claims(&options).unwrap(), None, &PublicKey([4u8; 32])).unwrap();
+        assert!(wallet.plan_claims(&options).unwrap().claims.is_empty());
+        assert_eq!(wallet.claims(Some(ClaimStatus::Pending)).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_scheduled_claims_are_spread_over_blocks() {
+        use crate::namada::LocalNamadaChain;
+        use crate::scanner::proto::CompactTx;
+        use crate::scanner::tests::*;
+        use crate::scheduler::{RelayClient, RelayQueue, SchedulePolicy};
+        use crate::store::MemoryStore;
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let dir = tempfile::tempdir().unwrap();
+        let seed = WalletSeed::from_phrase(&test_seed_phrase()).unwrap();
+        let ours = seed.derive_account(1, 0).unwrap();
+        let mut wallet = AirdropWallet::create(Arc::new(MemoryStore::new()), "test", "testnet", "pw").unwrap();
+        wallet.import_seed(&seed).unwrap();
+        wallet.scan_blocks(&[block(HEIGHT, vec![CompactTx {
+            outputs: vec![sapling_output_to(&ours, 1000)],
+            actions: vec![orchard_action_to(&ours, 2000), orchard_action_to(&ours, 3000)],
+            ..Default::default()
+        }])], None).unwrap();
+        
+        // Three claims of account 0
+        let mut txs = Vec::new();
+        for note in wallet.list_notes(None, None).unwrap() {
+            txs.push(wallet.create_airdrop_tx(note.id(), 1000, &PublicKey([4u8; 32])).unwrap());
+        }
+        assert_eq!(txs.len(), 3);
+
+        let policy = SchedulePolicy { min_delay_secs: 10, max_delay_secs: 20, decoys: 1, ..SchedulePolicy::default() };
+        let mut rng = StdRng::seed_from_u64(3);
+        let slots = wallet.schedule_claims(&txs, &policy, 1000, &mut rng).unwrap();
+        assert_eq!(slots.iter().filter(|slot| slot.is_decoy()).count(), 1);
+        assert!(slots.iter().all(|slot| (1000..=1080).contains(&slot.not_before)));
+        assert!(wallet.schedule_claims(&txs[..1], &policy, 1000, &mut rng).is_err());
+
+        // Nothing is due before the first delay has passed
+        let mut chain = LocalNamadaChain::new(AirdropPolicy::default());
+        assert_eq!(wallet.run_schedule(&mut chain, &policy, 1009).unwrap(), Default::default());
+
+        // Claims of one account go out one block at a time
+        let run = wallet.run_schedule(&mut chain, &policy, 2000).unwrap();
+        assert_eq!((run.submitted.len(), run.postponed, run.decoys), (1, 2, 1));
+        assert!(wallet.run_schedule(&mut chain, &policy, 2000).unwrap().submitted.is_empty());
+        chain.produce_block();
+        assert_eq!(wallet.run_schedule(&mut chain, &policy, 2000).unwrap().submitted.len(), 1);
+
+        // Handed to a relay queue, the last claim is submitted with the relay's batch
+        let queue = RelayQueue::open(&dir.path().join("relay")).unwrap();
+        chain.produce_block();
+        let run = wallet.run_schedule(&mut RelayClient::new(&chain, &queue), &policy, 2000).unwrap();
+        assert_eq!(run.submitted[0].1, ClaimStatus::Broadcast);
+        assert!(wallet.schedule().unwrap().is_empty());
+        assert_eq!(queue.flush(&mut chain, 2, policy.expiry_blocks, &mut rng).unwrap().submitted, 0);
+        assert_eq!(queue.flush(&mut chain, 1, policy.expiry_blocks, &mut rng).unwrap().submitted, 1);
+        assert!(queue.is_empty().unwrap());
+        chain.produce_block();
+        wallet.sync_claims(&chain).unwrap();
+        let confirmed = wallet.claims(Some(ClaimStatus::Confirmed)).unwrap();
+        assert_eq!(confirmed.len(), 3);
+        
+        // No two claims of the account were included in the same block
+        let mut heights: Vec<_> = confirmed.iter()
+            .filter(|record| record.note.is_some_and(|id| wallet.note_account(id).unwrap() == Some(0)))
+            .map(|record| record.block_height.unwrap())
+            .collect();
+        heights.sort_unstable();
+        heights.dedup();
+        assert_eq!(heights.len(), 3);
+    }
+
+    #[test]
+    fn test_unresolved_claims_hold_back_their_account() {
+        use crate::namada::LocalNamadaChain;
+        use crate::scheduler::SchedulePolicy;
+        use crate::store::MemoryStore;
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+        
+        let mut wallet = AirdropWallet::create(Arc::new(MemoryStore::new()), "test", "testnet", "pw").unwrap();
+        wallet.import_seed(&test_seed()).unwrap();
+        let mut txs = Vec::new();
+        for position in 0..2 {
+            wallet.add_sapling_note(owned_note(position)).unwrap();
+            txs.push(wallet.create_airdrop_tx(NoteId::new(Pool::Sapling, position), 1000, &PublicKey([4u8; 32])).unwrap());
+        }
+        let policy = SchedulePolicy { min_delay_secs: 10, max_delay_secs: 20, decoys: 0, expiry_blocks: 2, ..SchedulePolicy::default() };
+        wallet.schedule_claims(&txs, &policy, 1000, &mut StdRng::seed_from_u64(3)).unwrap();
+        
+        let mut chain = LocalNamadaChain::new(AirdropPolicy::default());
+        let run = wallet.run_schedule(&mut chain, &policy, 2000).unwrap();
+        assert_eq!((run.submitted.len(), run.postponed), (1, 1));
+        
+        // The node produces no block, then one without the claim it dropped
+        assert_eq!(wallet.run_schedule(&mut chain, &policy, 2000).unwrap().postponed, 1);
+        txs.iter().for_each(|tx| chain.evict(&tx.txid()));
+        chain.produce_block();
+        assert_eq!(wallet.run_schedule(&mut chain, &policy, 2000).unwrap().postponed, 1);
+        
+        // Once the dropped claim has expired the account's next claim goes out
+        chain.produce_block();
+        chain.produce_block();
+        let run = wallet.run_schedule(&mut chain, &policy, 2000).unwrap();
+        assert_eq!(run.submitted[0].1, ClaimStatus::Broadcast);
+        assert_eq!(wallet.claims(Some(ClaimStatus::Expired)).unwrap().len(), 1);
+    }
+    
+    #[test]
+    fn test_rescan_rebuilds_notes_from_a_height() {
+        use crate::scanner::proto::{ChainMetadata, CompactSaplingSpend, CompactTx};
+        use crate::scanner::tests::*;
+        use crate::store::MemoryStore;
+        
+        let seed = WalletSeed::from_phrase(&test_seed_phrase()).unwrap();
+        let ours = seed.derive_account(1, 0).unwrap();
+        let mut wallet = AirdropWallet::create(Arc::new(MemoryStore::new()), "test", "testnet", "pw").unwrap();
+        wallet.import_seed(&seed).unwrap();
+        
+        let with_sizes = |height, vtx, sapling_commitment_tree_size| CompactBlock {
+            chain_metadata: Some(ChainMetadata { sapling_commitment_tree_size, orchard_commitment_tree_size: 0 }),
+            ..block(height, vtx)
+        };
+        let output = |value| vec![CompactTx { outputs: vec![sapling_output_to(&ours, value)], ..Default::default() }];
+        let mut blocks = vec![with_sizes(HEIGHT, output(1000), 1), with_sizes(HEIGHT + 1, output(2000), 2)];
+        wallet.scan_blocks(&blocks, None).unwrap();
+        let mut scanner = Scanner::new(
+            consensus_network("testnet"), vec![scan_keys(&ours)], ScanState::default(), None,
+        );
+        let spent_nf = scanner.scan_block(&blocks[1]).unwrap().sapling_notes[0].nullifier;
+        blocks.push(with_sizes(HEIGHT + 2, vec![CompactTx {
+            spends: vec![CompactSaplingSpend { nf: spent_nf.0.to_vec() }],
+            ..Default::default()
+        }], 2));
+        wallet.scan_blocks(&blocks, None).unwrap();
+        
+        // A note record lost behind the wallet's back
+        let mut batch = StoreBatch::default();
+        batch.remove(Table::SaplingNotes, "sapling_1");
+        wallet.store.commit(&batch).unwrap();
+        let id = NoteId::new(Pool::Sapling, 1);
+        assert_eq!(wallet.check_consistency().unwrap(), [
+            Discrepancy::MemoryNote(id),
+            Discrepancy::IndexedNote(id),
+            Discrepancy::OrphanNullifier(id),
+            Discrepancy::StaleSpendHeight(id),
+        ]);
+        
+        // Rescanning from its block finds it again, spend included, and
+        // leaves the older note alone
+        assert!(wallet.rescan(&blocks, HEIGHT + 3, None).is_err());
+        assert!(wallet.rescan(&blocks[2..], HEIGHT + 1, None).is_err());
+        let summary = wallet.rescan(&blocks, HEIGHT + 1, None).unwrap();
+        assert_eq!((summary.notes_removed, summary.scan.sapling_notes, summary.scan.spends), (0, 1, 1));
+        assert_eq!(wallet.scan_state().unwrap().last_height, Some(HEIGHT + 2));
+        assert_eq!(unspent(&wallet), (1000, 0));
+        assert!(wallet.check_consistency().unwrap().is_empty());
+        
+        let summary = wallet.rescan(&blocks, HEIGHT + 1, None).unwrap();
+        assert_eq!((summary.notes_removed, summary.scan.sapling_notes), (1, 1));
+        assert_eq!(wallet.list_notes(None, None).unwrap().len(), 2);
+
+        // The older note is spent in a block that is then reorged away
+        let mut scanner = Scanner::new(
+            consensus_network("testnet"), vec![scan_keys(&ours)], ScanState::default(), None,
+        );
+        let older_nf = scanner.scan_block(&blocks[0]).unwrap().sapling_notes[0].nullifier;
+        blocks.push(with_sizes(HEIGHT + 3, vec![CompactTx {
+            spends: vec![CompactSaplingSpend { nf: older_nf.0.to_vec() }],
+            ..Default::default()
+        }], 2));
+        wallet.scan_blocks(&blocks, None).unwrap();
+        assert_eq!(unspent(&wallet), (0, 0));
+
+        // Rescanning the new block clears that spend but keeps the one seen
+        // in an earlier block
+        blocks[3] = with_sizes(HEIGHT + 3, Vec::new(), 2);
+        let summary = wallet.rescan(&blocks, HEIGHT + 3, None).unwrap();
+        assert_eq!((summary.notes_removed, summary.notes_reset, summary.scan.spends), (0, 1, 0));
+        assert_eq!(unspent(&wallet), (1000, 0));
+        assert!(wallet.check_consistency().unwrap().is_empty());
+    }
+    
+    #[test]
+    fn test_repair_fixes_records_out_of_step() {
+        use crate::namada::LocalNamadaChain;
+        use crate::scanner::tests::HEIGHT;
+        use crate::store::MemoryStore;
+        
+        let mut wallet = AirdropWallet::create(Arc::new(MemoryStore::new()), "test", "testnet", "pw").unwrap();
+        wallet.import_seed(&test_seed()).unwrap();
+        wallet.add_sapling_note(owned_note(0)).unwrap();
+        wallet.add_sapling_note(owned_note(1)).unwrap();
+        let (reserved, confirmed) = (NoteId::new(Pool::Sapling, 0), NoteId::new(Pool::Sapling, 1));
+        let recipient = PublicKey([4u8; 32]);
+        let stale = wallet.create_airdrop_tx(reserved, 1000, &recipient).unwrap();
+        let tx = wallet.create_airdrop_tx(confirmed, 1000, &recipient).unwrap();
+        let mut chain = LocalNamadaChain::new(AirdropPolicy::default());
+        wallet.broadcast_claim(&mut chain, &tx, 10).unwrap();
+        chain.produce_block();
+        wallet.sync_claims(&chain).unwrap();
+        assert!(wallet.check_consistency().unwrap().is_empty());
+        
+        // A lost claim record, a lost claimed flag, a stray nullifier and
+        // spend heights of an unknown and an unspent note
+        let stale = hex::encode(stale.txid());
+        let mut batch = StoreBatch::default();
+        batch.remove(Table::Transactions, &stale);
+        wallet.stage_note_update(&mut batch, confirmed, |status| {
+            status.is_claimed = false;
+            Ok(())
+        }).unwrap();
+        wallet.stage_note_nullifier(&mut batch, "sapling_9", &Nullifier([9u8; 32])).unwrap();
+        for key in ["sapling_0", "sapling_9"] {
+            batch.insert(Table::NoteSpends, key, wallet.seal_record(key.as_bytes(), &HEIGHT).unwrap());
+        }
+        wallet.store.commit(&batch).unwrap();
+        
+        let expected = [
+            Discrepancy::IndexedNote(confirmed),
+            Discrepancy::OrphanNullifier(NoteId::new(Pool::Sapling, 9)),
+            Discrepancy::StaleSpendHeight(reserved),
+            Discrepancy::StaleSpendHeight(NoteId::new(Pool::Sapling, 9)),
+            Discrepancy::StaleReservation { note: reserved, claim: stale },
+            Discrepancy::UnmarkedClaim { note: confirmed, claim: hex::encode(tx.txid()) },
+        ];
+        assert_eq!(wallet.check_consistency().unwrap(), expected);
+        assert_eq!(wallet.repair().unwrap(), expected);
+        assert!(wallet.check_consistency().unwrap().is_empty());
+        assert!(wallet.note_status(reserved).unwrap().is_claimable());
+        assert!(wallet.note_status(confirmed).unwrap().is_claimed);
+    }
+    
+    #[test]
+    fn test_unversioned_wallet_is_migrated() {
+        #[derive(Serialize)]
+        struct V0Note {
+            note: SaplingNote,
+            created_at: u64,
+            is_spent: bool,
+            last_used: Option<u64>,
+        }
+        #[derive(Serialize)]
+        struct V0Claim {
+            tx_hash: String,
+            airdrop_nullifier: Vec<u8>,
+            amount: u64,
+            recipient: String,
+            status: String,
+            created_at: u64,
+            confirmed_at: Option<u64>,
+            block_height: Option<u64>,
+        }
+        
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wallet.db");
+        {
+            // Note 0 was claimed, note 1 spent on Zcash, note 2 untouched
+            let wallet = AirdropWallet::new(&path, "test", "testnet", "pw").unwrap();
+            let mut batch = StoreBatch::default();
+            for position in 0..3 {
+                let key = NoteId::new(Pool::Sapling, position).key();
+                let record = V0Note { note: test_note(position), created_at: 0, is_spent: position < 2, last_used: None };
+                batch.insert(Table::SaplingNotes, &key, wallet.seal_record(key.as_bytes(), &record).unwrap());
             }
-            _ => return Err(anyhow::anyhow!("Invalid note type: {}", note_type)),
+            let claim = V0Claim {
+                tx_hash: hex::encode([7u8; 32]),
+                airdrop_nullifier: test_note(0).airdrop_nullifier().unwrap().0.to_vec(),
+                amount: 1000,
+                recipient: hex::encode([4u8; 32]),
+                status: "pending".to_string(),
+                created_at: 0,
+                confirmed_at: None,
+                block_height: None,
+            };
+            let sealed = wallet.seal_record(claim.tx_hash.as_bytes(), &claim).unwrap();
+            batch.insert(Table::Transactions, &claim.tx_hash, sealed);
+            batch.remove(Table::Metadata, "schema_version");
+            batch.insert(Table::Metadata, "sapling_notes", []);
+            wallet.store.commit(&batch).unwrap();
         }
         
-        Ok(())
+        let wallet = AirdropWallet::load(&path, "pw").unwrap();
+        assert_eq!(wallet.schema_version().unwrap(), SCHEMA_VERSION);
+        assert!(!wallet.store.contains_key(Table::Metadata, b"sapling_notes").unwrap());
+        
+        let statuses: Vec<_> = wallet.list_notes(None, None).unwrap().into_iter().map(|note| note.status).collect();
+        assert_eq!(statuses[0].reserved_by, Some(hex::encode([7u8; 32])));
+        assert!(statuses[1].is_spent && statuses[1].is_claimable());
+        assert_eq!(statuses[2], NoteStatus::default());
+        
+        let claims = wallet.claims(None).unwrap();
+        assert_eq!((claims[0].status, claims[0].note), (ClaimStatus::Pending, Some(NoteId::new(Pool::Sapling, 0))));
+    }
+    
+    #[test]
+    fn test_newer_or_corrupt_wallets_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wallet.db");
+        {
+            let mut wallet = AirdropWallet::new(&path, "test", "testnet", "pw").unwrap();
+            wallet.add_sapling_note(test_note(0)).unwrap();
+            let mut batch = StoreBatch::default();
+            batch.insert(Table::SaplingNotes, "sapling_1", b"garbage".to_vec());
+            wallet.store.commit(&batch).unwrap();
+        }
+        
+        // Corrupt records are named instead of silently dropped
+        let err = AirdropWallet::load(&path, "pw").err().unwrap();
+        assert!(err.to_string().contains("sapling_1"), "{}", err);
+        
+        {
+            let db = crate::store::open_sled(&path).unwrap();
+            db.open_tree("sapling_notes").unwrap().remove("sapling_1").unwrap();
+            db.insert("schema_version", &(SCHEMA_VERSION + 1).to_be_bytes()).unwrap();
+            db.flush().unwrap();
+        }
+        let err = AirdropWallet::open_locked(&path).err().unwrap();
+        assert!(err.to_string().contains("newer"), "{}", err);
+    }
+    
+    #[test]
+    fn test_encrypted_wallet_lifecycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wallet.db");
+        
+        {
+            let mut wallet = AirdropWallet::new(&path, "test", "testnet", "old").unwrap();
+            wallet.add_sapling_note(test_note(0)).unwrap();
+            
+            wallet.lock();
+            assert!(wallet.is_locked());
+            assert!(wallet.core().sapling_notes.is_empty());
+            assert!(wallet.get_balance().is_err());
+            assert!(wallet.add_sapling_note(test_note(1)).is_err());
+            
+            assert!(wallet.unlock("wrong").is_err());
+            wallet.unlock("old").unwrap();
+            assert_eq!(wallet.core().sapling_notes.len(), 1);
+            
+            wallet.change_passphrase("old", "new").unwrap();
+        }
+        
+        // Secrets never hit the disk in plaintext
+        let db = crate::store::open_sled(&path).unwrap();
+        let sealed = db.open_tree("sapling_notes").unwrap().get("sapling_0").unwrap().unwrap();
+        assert!(bincode::deserialize::<SaplingNoteRecord>(&sealed).is_err());
+        assert!(!sealed.windows(32).any(|w| w == [2u8; 32]));
+        drop(db);
+        
+        assert!(AirdropWallet::load(&path, "old").is_err());
+        let wallet = AirdropWallet::load(&path, "new").unwrap();
+        assert_eq!(claimable(&wallet), (1000000, 0));
+    }
+    
+    #[test]
+    fn test_seed_accounts_are_encrypted_and_restored() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wallet.db");
+        let seed = WalletSeed::generate();
+        
+        let viewing_keys = {
+            let mut wallet = AirdropWallet::new(&path, "test", "testnet", "passphrase").unwrap();
+            wallet.import_seed(&seed).unwrap();
+            assert!(wallet.import_seed(&seed).is_err());
+            assert_eq!(wallet.add_account().unwrap(), 1);
+            
+            wallet.lock();
+            assert!(wallet.account_keys(0).is_err());
+            wallet.unlock("passphrase").unwrap();
+            wallet.account_keys(1).unwrap().viewing_keys(Pool::Orchard)
+        };
+        
+        let wallet = AirdropWallet::load(&path, "passphrase").unwrap();
+        assert_eq!(wallet.accounts(), vec![0, 1]);
+        assert_eq!(wallet.account_keys(1).unwrap().viewing_keys(Pool::Orchard), viewing_keys);
+        
+        // Matches a fresh derivation from the phrase on the same network
+        let rederived = seed.derive_account(coin_type("testnet"), 1).unwrap();
+        assert_eq!(rederived.viewing_keys(Pool::Orchard), viewing_keys);
+    }
+    
+    #[test]
+    fn test_scan_discovers_notes_until_snapshot() {
+        use crate::scanner::proto::{CompactSaplingSpend, CompactTx};
+        use crate::scanner::{read_compact_blocks, tests::*, write_compact_blocks};
+        
+        let dir = tempfile::tempdir().unwrap();
+        let seed = WalletSeed::from_phrase(&test_seed_phrase()).unwrap();
+        let ours = seed.derive_account(1, 0).unwrap();
+        
+        let mut wallet = AirdropWallet::new(&dir.path().join("wallet.db"), "test", "testnet", "pw").unwrap();
+        wallet.import_seed(&seed).unwrap();
+        
+        let first = block(HEIGHT, vec![CompactTx {
+            outputs: vec![sapling_output_to(&ours, 1000)],
+            actions: vec![orchard_action_to(&ours, 2000)],
+            ..Default::default()
+        }]);
+        let summary = wallet.scan_blocks(std::slice::from_ref(&first), None).unwrap();
+        assert_eq!((summary.sapling_notes, summary.orchard_notes), (1, 1));
+        
+        // The nullifier tracked in the first run marks the spend in the
+        // second; blocks past the snapshot are ignored
+        let spent_nf = {
+            let mut scanner = Scanner::new(
+                consensus_network("testnet"), vec![scan_keys(&ours)], ScanState::default(), None,
+            );
+            scanner.scan_block(&first).unwrap().sapling_notes[0].nullifier
+        };
+        let blocks = [
+            first,
+            block(HEIGHT + 1, vec![CompactTx {
+                spends: vec![CompactSaplingSpend { nf: spent_nf.0.to_vec() }],
+                ..Default::default()
+            }]),
+            block(HEIGHT + 2, vec![CompactTx {
+                outputs: vec![sapling_output_to(&ours, 3000)],
+                ..Default::default()
+            }]),
+        ];
+        let path = dir.path().join("blocks.bin");
+        write_compact_blocks(&path, &blocks).unwrap();
+        
+        let summary = wallet.scan_blocks(&read_compact_blocks(&path).unwrap(), Some(HEIGHT + 1)).unwrap();
+        assert_eq!(summary.blocks_scanned, 1);
+        assert_eq!(summary.spends, 1);
+        assert_eq!(wallet.scan_state().unwrap().last_height, Some(HEIGHT + 1));
+        assert_eq!(unspent(&wallet), (0, 2000));
+        
+        // Spending on Zcash after the snapshot does not forfeit the claim
+        assert_eq!(claimable(&wallet), (1000, 2000));
+        assert_eq!(wallet.find_eligible_notes(0).unwrap().len(), 2);
     }
 
-    /// Create a Sapling->MASP airdrop transaction
-    pub fn create_sapling_to_masp_airdrop_tx(
-        &self,
-        note_index: usize,
-        airdrop_amount: u64,
-        masp_recipient: &PublicKey,
-    ) -> Result<ShieldedAirdropTransaction, ProtocolError> {
-        if note_index >= self.core_wallet.sapling_notes.len() {
-            return Err(ProtocolError("Invalid note index".to_string()));
-        }
-        let note = &self.core_wallet.sapling_notes[note_index];
-        let merkle_path = vec![[0u8; 32]; 32];
-        ShieldedAirdropTransaction::create_sapling_to_masp_airdrop(
-            note,
-            &merkle_path,
-            &self.core_wallet.nullifier_set,
-            airdrop_amount,
-            masp_recipient,
-        )
+    #[test]
+    fn test_spent_and_claimed_value_are_kept_apart() {
+        use crate::scanner::proto::{CompactSaplingSpend, CompactTx};
+        use crate::scanner::tests::*;
+        
+        let dir = tempfile::tempdir().unwrap();
+        let seed = WalletSeed::from_phrase(&test_seed_phrase()).unwrap();
+        let ours = seed.derive_account(1, 0).unwrap();
+        let mut wallet = AirdropWallet::new(&dir.path().join("wallet.db"), "test", "testnet", "pw").unwrap();
+        wallet.import_seed(&seed).unwrap();
+        
+        // Both notes exist at the snapshot
+        let first = block(HEIGHT, vec![CompactTx {
+            outputs: vec![sapling_output_to(&ours, 1000)],
+            actions: vec![orchard_action_to(&ours, 2000)],
+            ..Default::default()
+        }]);
+        wallet.scan_blocks(std::slice::from_ref(&first), Some(HEIGHT)).unwrap();
+        let sapling = NoteId::new(Pool::Sapling, 0);
+        let orchard = NoteId::new(Pool::Orchard, 0);
+        
+        // The Sapling note is spent on Zcash after the snapshot
+        let spent_nf = {
+            let mut scanner = Scanner::new(
+                consensus_network("testnet"), vec![scan_keys(&ours)], ScanState::default(), None,
+            );
+            scanner.scan_block(&first).unwrap().sapling_notes[0].nullifier
+        };
+        let spend = block(HEIGHT + 1, vec![CompactTx {
+            spends: vec![CompactSaplingSpend { nf: spent_nf.0.to_vec() }],
+            ..Default::default()
+        }]);
+        assert_eq!(wallet.scan_blocks(&[spend], None).unwrap().spends, 1);
+        
+        // The Orchard note claims the airdrop
+        let tx = wallet.create_airdrop_tx(orchard, 1000, &PublicKey([4u8; 32])).unwrap();
+        let mut chain = crate::namada::LocalNamadaChain::new(AirdropPolicy::default());
+        wallet.broadcast_claim(&mut chain, &tx, 10).unwrap();
+        chain.produce_block();
+        wallet.sync_claims(&chain).unwrap();
+        
+        // Only the spent note can still claim
+        let eligible = wallet.find_eligible_notes(0).unwrap();
+        assert_eq!(eligible.iter().map(|note| note.id()).collect::<Vec<_>>(), vec![sapling]);
+        let rules = EligibilityRules { snapshot_height: Some(HEIGHT), ..EligibilityRules::default() };
+        let report = wallet.eligibility_report(&rules, None).unwrap();
+        assert_eq!(report.eligible().map(|note| note.note).collect::<Vec<_>>(), vec![sapling]);
+        
+        // Spending on Zcash and claiming on Namada show up separately
+        let balance = wallet.get_balance().unwrap();
+        assert_eq!(balance.sapling, PoolBalance { unspent: 0, claimable: 1000, reserved: 0, claimed: 0 });
+        assert_eq!(balance.orchard, PoolBalance { unspent: 2000, claimable: 0, reserved: 0, claimed: 2000 });
     }
-    /// Create an Orchard->MASP airdrop transaction
-    pub fn create_orchard_to_masp_airdrop_tx(
-        &self,
-        note_index: usize,
-        airdrop_amount: u64,
-        masp_recipient: &PublicKey,
-    ) -> Result<ShieldedAirdropTransaction, ProtocolError> {
-        if note_index >= self.core_wallet.orchard_notes.len() {
-            return Err(ProtocolError("Invalid note index".to_string()));
-        }
-        let note = &self.core_wallet.orchard_notes[note_index];
-        let merkle_path = vec![[0u8; 32]; 32];
-        ShieldedAirdropTransaction::create_orchard_to_masp_airdrop(
-            note,
-            &merkle_path,
-            &self.core_wallet.nullifier_set,
-            airdrop_amount,
-            masp_recipient,
-        )
+
+    #[test]
+    fn test_accounts_keep_separate_notes_and_claims() {
+        use crate::eligibility::Check;
+        use crate::index::NoteQuery;
+        use crate::scanner::proto::CompactTx;
+        use crate::scanner::tests::*;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wallet.db");
+        let seed = WalletSeed::from_phrase(&test_seed_phrase()).unwrap();
+        let second = seed.derive_account(1, 1).unwrap();
+
+        {
+            let mut wallet = AirdropWallet::new(&path, "test", "testnet", "pw").unwrap();
+            wallet.import_seed(&seed).unwrap();
+            wallet.add_account().unwrap();
+            wallet.add_sapling_note(test_note(7)).unwrap();
+            let blocks = [block(HEIGHT, vec![CompactTx {
+                outputs: vec![sapling_output_to(&seed.derive_account(1, 0).unwrap(), 1000)],
+                actions: vec![orchard_action_to(&second, 2000), orchard_action_to(&second, 3000)],
+                ..Default::default()
+            }])];
+            wallet.scan_blocks(&blocks, None).unwrap();
+        }
+
+        let mut wallet = AirdropWallet::load(&path, "pw").unwrap();
+        assert_eq!(wallet.account_balance(0).unwrap().sapling.claimable, 1000);
+        assert_eq!(wallet.account_balance(1).unwrap().orchard.claimable, 5000);
+        // Notes added without keys count only towards the wallet's balance
+        assert_eq!(wallet.get_balance().unwrap().sapling.claimable, 1001000);
+        assert!(wallet.account_balance(2).is_err());
+
+        // They cannot be claimed, as the wallet holds no spending key for them
+        let keyless = NoteId::new(Pool::Sapling, 7);
+        assert!(wallet.create_airdrop_tx(keyless, 1000, &PublicKey([4u8; 32])).is_err());
+        let report = wallet.eligibility_report(&EligibilityRules::default(), None).unwrap();
+        let keyless = report.notes.iter().find(|note| note.note == keyless).unwrap();
+        let failures: Vec<Check> = keyless.failures().map(|result| result.check).collect();
+        assert_eq!(failures, [Check::ProofMaterials]);
+
+        let notes = wallet.query_notes(&NoteQuery { account: Some(1), ..NoteQuery::default() }).unwrap().notes;
+        assert_eq!(notes.len(), 2);
+        assert!(notes.iter().all(|note| note.pool == Pool::Orchard && note.account == Some(1)));
+
+        wallet.create_airdrop_tx(notes[0].id(), 1000, &PublicKey([4u8; 32])).unwrap();
+        assert_eq!(wallet.account_claims(1, None).unwrap().len(), 1);
+        assert!(wallet.account_claims(0, None).unwrap().is_empty());
+        assert_eq!(wallet.account_balance(1).unwrap().orchard.reserved, notes[0].value);
     }
-}
 
-#[derive(Debug, Serialize, Deserialize)]
-struct ExportData {
-    metadata: WalletMetadata,
-    sapling_notes: Vec<SaplingNote>,
-    orchard_notes: Vec<OrchardNote>,
-    nullifier_set: Vec<Vec<u8>>,
-    airdrop_nullifier_set: Vec<Vec<u8>>,
-} 
\ No newline at end of file
+    #[test]
+    fn test_watch_only_wallet_prepares_unsigned_claims() {
+        use crate::scanner::proto::CompactTx;
+        use crate::scanner::tests::*;
+        
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wallet.db");
+        let ours = test_account(0);
+        let ufvk = ours.viewing_account().to_ufvk(NetworkType::Test).unwrap();
+        
+        {
+            let mut wallet = AirdropWallet::new_watch_only(&path, "watch", "testnet", "pw", &ufvk).unwrap();
+            let blocks = [block(HEIGHT, vec![CompactTx {
+                outputs: vec![sapling_output_to(&ours, 1000)],
+                actions: vec![orchard_action_to(&ours, 2000)],
+                ..Default::default()
+            }])];
+            let summary = wallet.scan_blocks(&blocks, None).unwrap();
+            assert_eq!((summary.sapling_notes, summary.orchard_notes), (1, 1));
+        }
+        
+        let mut wallet = AirdropWallet::load(&path, "pw").unwrap();
+        assert!(wallet.is_watch_only());
+        assert_eq!(wallet.viewing_account(0).unwrap(), ours.viewing_account());
+        assert!(wallet.account_keys(0).is_err());
+        assert!(wallet.import_seed(&WalletSeed::generate()).is_err());
+        
+        // Airdrop nullifiers are computed from the viewing key alone
+        let notes = wallet.list_notes(None, Some(Pool::Orchard)).unwrap();
+        let recipient = PublicKey([4u8; 32]);
+        for pool in [Pool::Sapling, Pool::Orchard] {
+            let id = wallet.list_notes(None, Some(pool)).unwrap()[0].id();
+            let unsigned = wallet.prepare_airdrop_tx(id, 500, &recipient).unwrap();
+            assert_eq!(*unsigned.randomized_key(), ours.randomized_key(pool, &unsigned.alpha));
+            assert_eq!(
+                PartialAirdropTransaction::from_bytes(&unsigned.to_bytes().unwrap()).unwrap().sighash(),
+                unsigned.sighash()
+            );
+            if pool == Pool::Orchard {
+                assert_eq!(unsigned.get_airdrop_nullifier(), notes[0].airdrop_nullifier);
+            }
+        }
+        
+        // Authorizing a claim needs the spending key
+        let id = wallet.list_notes(None, Some(Pool::Sapling)).unwrap()[0].id();
+        assert!(wallet.create_airdrop_tx(id, 500, &recipient).is_err());
+        assert_eq!(claimable(&wallet), (1000, 2000));
+    }
+    
+    #[test]
+    fn test_offline_signing_round_trip() {
+        use crate::scanner::proto::CompactTx;
+        use crate::scanner::tests::*;
+        use crate::ProtocolError;
+        
+        let dir = tempfile::tempdir().unwrap();
+        let seed = WalletSeed::from_phrase(&test_seed_phrase()).unwrap();
+        let mut signer = AirdropWallet::new(&dir.path().join("cold.db"), "cold", "testnet", "pw").unwrap();
+        signer.import_seed(&seed).unwrap();
+        
+        let ufvk = signer.viewing_account(0).unwrap().to_ufvk(NetworkType::Test).unwrap();
+        let mut online = AirdropWallet::new_watch_only(&dir.path().join("watch.db"), "watch", "testnet", "pw", &ufvk).unwrap();
+        let ours = seed.derive_account(1, 0).unwrap();
+        let blocks = [block(HEIGHT, vec![CompactTx {
+            actions: vec![orchard_action_to(&ours, 2000)],
+            ..Default::default()
+        }])];
+        online.scan_blocks(&blocks, None).unwrap();
+        
+        // Export, sign offline, and finalize online
+        let id = online.list_notes(None, None).unwrap()[0].id();
+        let partial = online.prepare_airdrop_tx(id, 2000, &PublicKey([4u8; 32])).unwrap();
+        assert_eq!(partial.clone().finalize().unwrap_err(), ProtocolError::MissingSpendAuthSig);
+        assert!(online.sign_airdrop_tx(&mut partial.clone()).is_err());
+        
+        let mut exported = PartialAirdropTransaction::from_bytes(&partial.to_bytes().unwrap()).unwrap();
+        assert_eq!(signer.sign_airdrop_tx(&mut exported).unwrap(), 0);
+        let signed = PartialAirdropTransaction::from_bytes(&exported.to_bytes().unwrap()).unwrap();
+        assert!(signed.is_signed());
+        
+        // A signature by another key is refused when combined
+        let mut forged = partial.clone();
+        let other = seed.derive_account(1, 1).unwrap();
+        let signature = other.sign_spend_auth(Pool::Orchard, &forged.alpha, &forged.sighash());
+        assert_eq!(forged.add_spend_auth_signature(signature), Err(ProtocolError::BadSpendAuthSig));
+        
+        let tx = online.finalize_airdrop_tx(signed).unwrap();
+        assert!(tx.verify_spend_auth_signature());
+        online.process_airdrop_transaction(&tx, 7).unwrap();
+        assert_eq!(claimable(&online), (0, 0));
+        
+        // A seed wallet holding the note signs with the account key directly
+        signer.scan_blocks(&blocks, None).unwrap();
+        let tx = signer.create_airdrop_tx(id, 2000, &PublicKey([4u8; 32])).unwrap();
+        assert!(tx.validate(&crate::MemoryNullifierRegistry::new(), &AirdropPolicy::default()).is_ok());
+        assert_ne!(*tx.claim_description.randomized_key(), ours.viewing_keys(Pool::Orchard).ak);
+    }
+    
+    #[test]
+    fn test_import_zcashd_notes_deduplicates() {
+        use crate::scanner::proto::CompactTx;
+        use crate::scanner::tests::*;
+        
+        let dir = tempfile::tempdir().unwrap();
+        let mut wallet = AirdropWallet::new(&dir.path().join("wallet.db"), "test", "testnet", "pw").unwrap();
+        
+        // Notes held by a zcashd key unrelated to this wallet's seed
+        let zcashd = test_account(7);
+        let mut tx = CompactTx {
+            hash: vec![9u8; 32],
+            outputs: vec![sapling_output_to(&zcashd, 1000), sapling_output_to(&zcashd, 2000)],
+            ..Default::default()
+        };
+        tx.hash[0] = 1;
+        let blocks = [block(HEIGHT, vec![tx])];
+        
+        let mut txid = [9u8; 32];
+        txid[0] = 1;
+        let expected = [
+            ExpectedNote { txid, pool: Pool::Sapling, index: 1, value: 2000 },
+            ExpectedNote { txid: [0u8; 32], pool: Pool::Orchard, index: 0, value: 5 },
+        ];
+        
+        let summary = wallet
+            .import_notes(&blocks, vec![scan_keys(&zcashd)], Some(&expected), None)
+            .unwrap();
+        assert_eq!((summary.imported, summary.duplicates), (1, 0));
+        assert_eq!(summary.missing, vec![expected[1].clone()]);
+        assert_eq!(wallet.list_notes(None, None).unwrap()[0].position, 1);
+        
+        // Importing everything the key can see skips the note already stored
+        let summary = wallet.import_notes(&blocks, vec![scan_keys(&zcashd)], None, None).unwrap();
+        assert_eq!((summary.imported, summary.duplicates), (1, 1));
+        assert_eq!(claimable(&wallet), (3000, 0));
+        
+        // A reported value that does not match the chain is rejected
+        let wrong = [ExpectedNote { value: 1, ..expected[0].clone() }];
+        assert!(wallet.import_notes(&blocks, vec![scan_keys(&zcashd)], Some(&wrong), None).is_err());
+    }
+}